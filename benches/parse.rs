@@ -0,0 +1,89 @@
+//! # The rushttp Rust HTTP Library - Parser Benchmarks
+//!
+//! Measures the request parser on a few typical (and not so typical)
+//! requests, with and without `Parser::fast_path`. Run with `cargo bench`.
+
+// ****************************************************************************
+//
+// Imports
+//
+// ****************************************************************************
+
+#[macro_use]
+extern crate criterion;
+extern crate rushttp;
+
+use criterion::{black_box, Criterion};
+
+use rushttp::request::*;
+
+// ****************************************************************************
+//
+// Private Data
+//
+// ****************************************************************************
+
+const MINIMAL: &[u8] = b"GET / HTTP/1.1\r\n\r\n";
+
+const BROWSER: &[u8] = b"GET /index.html?lang=en HTTP/1.1\r\n\
+Host: www.example.com\r\n\
+User-Agent: Mozilla/5.0 (X11; Linux x86_64; rv:78.0) Gecko/20100101 Firefox/78.0\r\n\
+Accept: text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,*/*;q=0.8\r\n\
+Accept-Language: en-GB,en;q=0.5\r\n\
+Accept-Encoding: gzip, deflate, br\r\n\
+Referer: https://www.example.com/\r\n\
+Connection: keep-alive\r\n\
+Cookie: session=0123456789abcdef0123456789abcdef; theme=dark; lang=en\r\n\
+Upgrade-Insecure-Requests: 1\r\n\
+Cache-Control: max-age=0\r\n\
+If-None-Match: \"5d8c72a5edda8d6a\"\r\n\
+If-Modified-Since: Tue, 15 Sep 2020 12:00:00 GMT\r\n\
+\r\n";
+
+// ****************************************************************************
+//
+// Private Functions
+//
+// ****************************************************************************
+
+/// A request with lots of long headers.
+fn many_headers() -> Vec<u8> {
+    let mut request = b"GET / HTTP/1.1\r\n".to_vec();
+    for i in 0..100 {
+        request.extend(format!("X-Header-Number-{}: {}\r\n", i, "v".repeat(200)).bytes());
+    }
+    request.extend(b"\r\n");
+    request
+}
+
+fn parse(request: &[u8], fast: bool) {
+    let mut parser = Parser::new();
+    parser.fast_path(fast);
+    match parser.parse(request) {
+        ParseResult::Complete(r, _) => {
+            black_box(r);
+        }
+        _ => panic!("benchmark request didn't parse"),
+    }
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let many = many_headers();
+    let requests: [(&str, &[u8]); 3] = [("minimal", MINIMAL), ("browser", BROWSER), ("many", &many)];
+    for &(name, request) in &requests {
+        c.bench_function(&format!("parse {}", name), |b| b.iter(|| parse(request, false)));
+        c.bench_function(&format!("parse {} fast", name), |b| b.iter(|| parse(request, true)));
+        c.bench_function(&format!("parse_once {}", name), |b| {
+            b.iter(|| black_box(parse_once(request)))
+        });
+    }
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
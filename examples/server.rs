@@ -9,8 +9,6 @@
 // Use our own library
 extern crate rushttp;
 
-extern crate http;
-
 use rushttp::request::*;
 use rushttp::response::*;
 
@@ -83,7 +81,7 @@ fn main() {
 /// This function is started in a new thread for every incoming connection.
 fn handle_client(mut stream: TcpStream) {
     println!("+conn on {:?}!", stream);
-    if let Ok(_) = stream.set_read_timeout(Some(Duration::from_secs(TCP_READ_TIMEOUT_SECONDS))) {
+    if stream.set_read_timeout(Some(Duration::from_secs(TCP_READ_TIMEOUT_SECONDS))).is_ok() {
         match read_request(&mut stream) {
             Ok(r) => generate_response(&mut stream, r),
             Err(e) => render_parse_error(&mut stream, e),
@@ -94,16 +92,21 @@ fn handle_client(mut stream: TcpStream) {
 }
 
 /// Process the incoming HTTP request
+#[allow(clippy::result_large_err)]
 fn read_request(stream: &mut TcpStream) -> Result<Request, ParseResult> {
     let mut ctx: Parser = Parser::new();
     loop {
         let mut buffer = vec![0; 1024];
         match stream.read(&mut buffer) {
-            Ok(_) => {
-                let r = ctx.parse(&buffer);
+            Ok(0) => {
+                println!("eof {:?}", stream);
+                return Err(ParseResult::Error);
+            }
+            Ok(n) => {
+                let r = ctx.parse(&buffer[..n]);
                 match r {
                     ParseResult::Complete(req, _) => {
-                        println!("<request {:?}: {:?}", stream, req);
+                        println!("<request {:?}: {}", stream, summarize(&req));
                         return Ok(req);
                     }
                     ParseResult::InProgress => {}
@@ -120,7 +123,7 @@ fn read_request(stream: &mut TcpStream) -> Result<Request, ParseResult> {
 
 /// Send back a noddy response based on the request
 fn generate_response(stream: &mut TcpStream, request: Request) {
-    if *request.method() == http::Method::GET {
+    if is_get(&request) {
         let mut body: String = String::new();
         body.push_str("This is a test.\r\n");
         body.push_str(&format!("You asked for URL {}\r\n", request.uri()));
@@ -129,30 +132,25 @@ fn generate_response(stream: &mut TcpStream, request: Request) {
             body.push_str(&format!("Key {:?} = {:?}\r\n", k, v));
         }
 
-        let mut response = HttpResponse::new_with_body(HttpResponseStatus::OK, "HTTP/1.1", body);
-        response.add_header("Content-Type", "text/plain; charset=utf-8");
+        let mut response = HttpResponse::ok_text(body);
         response.add_header("Connection", "close");
         response.write(stream).unwrap();
     } else {
-        render_error(stream,
-                     HttpResponseStatus::MethodNotAllowed,
-                     &format!("Method {:?} not allowed.", request.method()));
+        let mut response = HttpResponse::method_not_allowed(&["GET"]);
+        response.body = format!("Error {}: Method {} not allowed.\r\n",
+                                response.status,
+                                method_str(&request))
+                            .into();
+        response.add_header("Content-Type", "text/plain; charset=utf-8");
+        response.add_header("Connection", "close");
+        response.write(stream).unwrap();
     }
 }
 
 /// Handle a parsing error
 fn render_parse_error(stream: &mut TcpStream, error: ParseResult) {
-    let (status, msg) = match error {
-        ParseResult::ErrorBadHeader => (HttpResponseStatus::BadRequest, "Bad Header"),
-        ParseResult::ErrorBadHeaderValue => (HttpResponseStatus::BadRequest, "Bad Header Value"),
-        ParseResult::ErrorBadMethod => (HttpResponseStatus::MethodNotAllowed, "Bad Method"),
-        ParseResult::ErrorBadProtocol => {
-            (HttpResponseStatus::HTTPVersionNotSupported, "Bad Protocol")
-        }
-        ParseResult::ErrorBadURL => (HttpResponseStatus::BadRequest, "Bad URL"),
-        _ => (HttpResponseStatus::BadRequest, "Unknown Error"),
-    };
-    render_error(stream, status, msg);
+    let error = error.into_result().err().unwrap_or(ParseError::Malformed);
+    render_error(stream, error.as_status(), &error.to_string());
 }
 
 /// Send an error page
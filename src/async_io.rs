@@ -0,0 +1,123 @@
+//! # Async Request Reading
+//!
+//! The `Parser` is a plain state machine which doesn't care where its
+//! octets come from, so it works just as well with async I/O. This module
+//! (enabled with the `async` feature) drives it from one of tokio's
+//! `AsyncRead` streams, so you can use rushttp in a tokio server without
+//! tying up a thread per connection.
+
+// ****************************************************************************
+//
+// Imports
+//
+// ****************************************************************************
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, ReadBuf};
+
+use request::{is_timeout, ParseError, Parser, Request};
+
+// ****************************************************************************
+//
+// Public Types
+//
+// ****************************************************************************
+
+/// The future returned by `read_request_async`.
+#[derive(Debug)]
+pub struct ReadRequest<'r, R: 'r> {
+    /// Where the request comes from
+    reader: &'r mut R,
+    /// Does the actual work
+    parser: Parser,
+}
+
+// ****************************************************************************
+//
+// Private Types
+//
+// ****************************************************************************
+
+// None
+
+// ****************************************************************************
+//
+// Private Data
+//
+// ****************************************************************************
+
+// None
+
+// ****************************************************************************
+//
+// Public Functions
+//
+// ****************************************************************************
+
+/// The async version of `request::read_request_from`: read a request from
+/// `reader`. Nothing after the end of the header block is read, so the body
+/// (or the next request) is still there in `reader` afterwards. To manage
+/// that, `reader` is read one octet at a time, so wrap it in a
+/// `tokio::io::BufReader`.
+///
+/// If reading fails, a time out (`TimedOut` or `WouldBlock`) gives
+/// `ParseError::Timeout` and anything else means we've no more input, so
+/// gives `ParseError::UnexpectedEof`. If you want a time limit, wrap this
+/// in `tokio::time::timeout`.
+pub fn read_request_async<R>(reader: &mut R) -> ReadRequest<'_, R>
+    where R: AsyncRead + Unpin
+{
+    ReadRequest {
+        reader,
+        parser: Parser::new(),
+    }
+}
+
+impl<'r, R> Future for ReadRequest<'r, R>
+    where R: AsyncRead + Unpin
+{
+    type Output = Result<Request, ParseError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            let mut octet = [0u8; 1];
+            let mut buf = ReadBuf::new(&mut octet);
+            match Pin::new(&mut *this.reader).poll_read(cx, &mut buf) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(ref e)) if is_timeout(e) => {
+                    return Poll::Ready(Err(ParseError::Timeout))
+                }
+                Poll::Ready(Err(_)) => return Poll::Ready(Err(ParseError::UnexpectedEof)),
+                Poll::Pending => return Poll::Pending,
+            }
+            let result = if buf.filled().is_empty() {
+                this.parser.finish()
+            } else {
+                this.parser.parse(&octet)
+            };
+            match result.into_result() {
+                Ok((r, _)) => return Poll::Ready(Ok(r)),
+                Err(ParseError::Incomplete) => {}
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+}
+
+// ****************************************************************************
+//
+// Private Functions
+//
+// ****************************************************************************
+
+// None
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
@@ -0,0 +1,115 @@
+//! # Authentication
+//!
+//! Helpers for pulling credentials out of the `Authorization` header of a
+//! request. `basic_auth` handles the `Basic` scheme (RFC 7617) and
+//! `bearer_token` the `Bearer` scheme (RFC 6750). Checking the credentials
+//! is up to you.
+
+// ****************************************************************************
+//
+// Imports
+//
+// ****************************************************************************
+
+use request::Request;
+
+// ****************************************************************************
+//
+// Public Types
+//
+// ****************************************************************************
+
+// None
+
+// ****************************************************************************
+//
+// Private Types
+//
+// ****************************************************************************
+
+// None
+
+// ****************************************************************************
+//
+// Public Functions
+//
+// ****************************************************************************
+
+/// Get the username and password from an `Authorization: Basic ...` header.
+/// The password is everything after the first colon, so it may itself
+/// contain colons. Returns `None` if there's no such header, the base64 is
+/// malformed, the decoded credentials aren't UTF-8 or there's no colon.
+pub fn basic_auth(r: &Request) -> Option<(String, String)> {
+    let encoded = credentials(r, "Basic")?;
+    let decoded = String::from_utf8(base64_decode(encoded)?).ok()?;
+    let mut parts = decoded.splitn(2, ':');
+    let user = parts.next()?.to_string();
+    let password = parts.next()?.to_string();
+    Some((user, password))
+}
+
+/// Get the token from an `Authorization: Bearer <token>` header. Returns
+/// `None` if there's no such header or the token is empty.
+pub fn bearer_token(r: &Request) -> Option<String> {
+    credentials(r, "Bearer").map(|t| t.to_string())
+}
+
+// ****************************************************************************
+//
+// Private Functions
+//
+// ****************************************************************************
+
+/// Get whatever follows `scheme` (matched case-insensitively) in the
+/// `Authorization` header, if it's there and isn't empty.
+fn credentials<'a>(r: &'a Request, scheme: &str) -> Option<&'a str> {
+    let value = r.headers().get("Authorization")?.to_str().ok()?.trim();
+    let mut parts = value.splitn(2, ' ');
+    if !parts.next()?.eq_ignore_ascii_case(scheme) {
+        return None;
+    }
+    let rest = parts.next()?.trim();
+    if rest.is_empty() {
+        None
+    } else {
+        Some(rest)
+    }
+}
+
+/// Decode standard (RFC 4648 section 4) base64. Padding is optional, but if
+/// present it must be correct.
+fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    let data = input.trim_end_matches('=').as_bytes();
+    let padding = input.len() - data.len();
+    // Padding, if any, must make the length up to a multiple of four
+    if padding > 2 || data.len() % 4 == 1 || (padding > 0 && data.len() % 4 + padding != 4) {
+        return None;
+    }
+    let mut result = Vec::with_capacity(data.len() * 3 / 4);
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    for &c in data {
+        let sextet = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        acc = (acc << 6) | u32::from(sextet);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Some(result)
+}
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
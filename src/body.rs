@@ -0,0 +1,391 @@
+//! # HTTP Request Bodies
+//!
+//! The `Parser` stops at the end of the header block. These functions pick
+//! up from there and collect the body, using whatever octets the parser
+//! didn't consume plus anything more that has to be read from the stream.
+
+// ****************************************************************************
+//
+// Imports
+//
+// ****************************************************************************
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::str;
+
+#[cfg(feature = "gzip")]
+use flate2::read::{GzDecoder, ZlibDecoder};
+
+use request::{expects_continue, get_content_length, split_unquoted, unquote, ParseError, Parser,
+              Request, TrailersResult};
+use response::{HttpResponse, HttpResponseStatus};
+
+// ****************************************************************************
+//
+// Public Types
+//
+// ****************************************************************************
+
+/// The ways reading a body can fail.
+#[derive(Debug)]
+pub enum BodyError {
+    /// The body is (or claims to be) longer than the caller will accept
+    TooLarge,
+    /// The stream ended before the whole body arrived
+    Truncated,
+    /// The `Content-Length` header couldn't be understood
+    BadContentLength,
+    /// The chunked transfer coding was broken
+    BadChunk,
+    /// One of the trailers after a chunked body was malformed
+    BadTrailer,
+    /// The body uses a `Content-Encoding` we don't know how to decode
+    UnsupportedEncoding,
+    /// The body couldn't be decoded with its `Content-Encoding`
+    BadEncoding,
+    /// Reading from the stream failed
+    Io(io::Error),
+}
+
+// ****************************************************************************
+//
+// Private Types
+//
+// ****************************************************************************
+
+// None
+
+// ****************************************************************************
+//
+// Private Data
+//
+// ****************************************************************************
+
+/// The longest chunk-size line we'll accept, extensions and all.
+const MAX_CHUNK_LINE: usize = 1024;
+
+/// The most `decode_body` will decompress a body to.
+#[cfg(feature = "gzip")]
+const DEFAULT_MAX_DECODED_BODY: usize = 16 * 1024 * 1024;
+
+// ****************************************************************************
+//
+// Public Functions
+//
+// ****************************************************************************
+
+/// Read the body of `r`, which is `Content-Length` octets long. The body
+/// starts with `leftover` (the octets after the header block which the
+/// parser didn't consume), and the rest is read from `reader`. Anything in
+/// `leftover` beyond the end of the body is ignored.
+///
+/// Fails with `BodyError::TooLarge` if the body is longer than `max`, which
+/// we check before reading anything, and with `BodyError::Truncated` if the
+/// stream ends early. A request with no `Content-Length`, or with a
+/// `Content-Length` of zero, has no body - we return straight away without
+/// touching `reader`, and anything in `leftover` belongs to the next request.
+pub fn read_body<R: io::Read>(r: &Request,
+                              leftover: &[u8],
+                              reader: &mut R,
+                              max: usize)
+                              -> Result<Vec<u8>, BodyError> {
+    let length = checked_length(r, max)?;
+    read_length(length, leftover, reader)
+}
+
+/// Like `read_body`, but if the client sent `Expect: 100-continue` we first
+/// send it a `100 Continue` interim response, so it knows to go ahead with
+/// the body. If the body is too large we fail without sending anything, and
+/// the caller should send a final error response (e.g. 413) instead. There's
+/// also no point sending it when there's no body to wait for. Either way the
+/// caller still has to send the final response afterwards.
+pub fn read_body_with_continue<S>(r: &Request,
+                                  leftover: &[u8],
+                                  stream: &mut S,
+                                  max: usize)
+                                  -> Result<Vec<u8>, BodyError>
+    where S: io::Read + io::Write
+{
+    let length = checked_length(r, max)?;
+    if length > 0 && expects_continue(r) {
+        HttpResponse::new(HttpResponseStatus::Continue, "HTTP/1.1").write_interim(stream)?;
+        stream.flush()?;
+    }
+    read_length(length, leftover, stream)
+}
+
+/// Read and decode a body sent with `Transfer-Encoding: chunked`. As with
+/// `read_body`, it starts with the `leftover` octets and carries on with
+/// `reader`, which is read one octet at a time so should be buffered. The
+/// trailer section after the last chunk is handed to `parser`, which must
+/// be the one that parsed the request - it knows which trailers the client
+/// announced. Get them afterwards with `Parser::trailers`.
+///
+/// Fails with `BodyError::TooLarge` as soon as the decoded body would be
+/// longer than `max`, or would take the request over the parser's
+/// `max_total_bytes`.
+///
+/// Returns the body along with whatever of `leftover` came after the
+/// trailer section. That's the start of the next request on a keep-alive
+/// connection, so hand it to the next parse. Nothing is read from `reader`
+/// past the end of the trailers.
+///
+/// Any chunk extensions (`1a;name=value`) are checked and then discarded.
+/// Use `read_chunked_body_with_extensions` if you want to see them.
+pub fn read_chunked_body<R: io::Read>(parser: &mut Parser,
+                                      leftover: &[u8],
+                                      reader: &mut R,
+                                      max: usize)
+                                      -> Result<(Vec<u8>, Vec<u8>), BodyError> {
+    read_chunked_body_with_extensions(parser, leftover, reader, max, |_, _| {})
+}
+
+/// Like `read_chunked_body`, but calls `on_extension` with the name and (if
+/// there is one) value of each chunk extension, in the order they arrive.
+/// A quoted value has its quotes and escapes removed. The last (zero
+/// length) chunk can carry extensions too.
+pub fn read_chunked_body_with_extensions<R, F>(parser: &mut Parser,
+                                               leftover: &[u8],
+                                               reader: &mut R,
+                                               max: usize,
+                                               mut on_extension: F)
+                                               -> Result<(Vec<u8>, Vec<u8>), BodyError>
+    where R: io::Read,
+          F: FnMut(&str, Option<&str>)
+{
+    let mut unread = leftover;
+    let mut source = (&mut unread).chain(reader);
+    let mut body = Vec::new();
+    let max = parser.bytes_remaining().map_or(max, |budget| budget.min(max));
+    loop {
+        let line = read_line(&mut source)?;
+        let size = chunk_size(&line, &mut on_extension)?;
+        if size == 0 {
+            break;
+        }
+        if size > max - body.len() {
+            return Err(BodyError::TooLarge);
+        }
+        append_exact(&mut source, size, &mut body)?;
+        if !read_line(&mut source)?.is_empty() {
+            return Err(BodyError::BadChunk);
+        }
+        parser.body_received(size);
+    }
+    let mut octet = [0u8; 1];
+    loop {
+        source.read_exact(&mut octet)?;
+        match parser.parse_trailers(&octet) {
+            TrailersResult::InProgress => {}
+            TrailersResult::Complete(_) => break,
+            TrailersResult::Error(ParseError::RequestTooLarge) => return Err(BodyError::TooLarge),
+            TrailersResult::Error(_) => return Err(BodyError::BadTrailer),
+        }
+    }
+    let (unread, _) = source.into_inner();
+    Ok((body, unread.to_vec()))
+}
+
+/// Undo the `Content-Encoding` of a request body, if it has one. `gzip`
+/// (or `x-gzip`), `deflate` and `identity` are supported, and if several
+/// encodings were applied they are undone in reverse order. Fails with
+/// `BodyError::UnsupportedEncoding` for any other encoding, and with
+/// `BodyError::BadEncoding` if the body is corrupt.
+///
+/// A small compressed body can expand enormously, so we stop with
+/// `BodyError::TooLarge` if the result gets bigger than 16 MiB. Use
+/// `decode_body_limited` to pick your own limit.
+#[cfg(feature = "gzip")]
+pub fn decode_body(r: &Request, body: Vec<u8>) -> Result<Vec<u8>, BodyError> {
+    decode_body_limited(r, body, DEFAULT_MAX_DECODED_BODY)
+}
+
+/// Like `decode_body`, but fails with `BodyError::TooLarge` if decoding
+/// any of the encodings produces more than `max` octets.
+#[cfg(feature = "gzip")]
+pub fn decode_body_limited(r: &Request, body: Vec<u8>, max: usize) -> Result<Vec<u8>, BodyError> {
+    let codings: Vec<String> = r.headers()
+        .get_all("Content-Encoding")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|c| c.trim().to_ascii_lowercase())
+        .filter(|c| !c.is_empty())
+        .collect();
+    let mut body = body;
+    for coding in codings.iter().rev() {
+        body = match coding.as_str() {
+            "identity" => body,
+            "gzip" | "x-gzip" => inflate(GzDecoder::new(&body[..]), max)?,
+            "deflate" => inflate(ZlibDecoder::new(&body[..]), max)?,
+            _ => return Err(BodyError::UnsupportedEncoding),
+        };
+    }
+    Ok(body)
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BodyError::TooLarge => write!(f, "Body too large"),
+            BodyError::Truncated => write!(f, "Body truncated"),
+            BodyError::BadContentLength => write!(f, "Bad Content-Length"),
+            BodyError::BadChunk => write!(f, "Bad chunked encoding"),
+            BodyError::BadTrailer => write!(f, "Bad trailer"),
+            BodyError::UnsupportedEncoding => write!(f, "Unsupported Content-Encoding"),
+            BodyError::BadEncoding => write!(f, "Bad Content-Encoding"),
+            BodyError::Io(ref e) => write!(f, "I/O error reading body: {}", e),
+        }
+    }
+}
+
+impl error::Error for BodyError {}
+
+impl From<io::Error> for BodyError {
+    fn from(e: io::Error) -> BodyError {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            BodyError::Truncated
+        } else {
+            BodyError::Io(e)
+        }
+    }
+}
+
+// ****************************************************************************
+//
+// Private Functions
+//
+// ****************************************************************************
+
+/// Read everything out of a decompressor, stopping if there's more than
+/// `max` octets of it.
+#[cfg(feature = "gzip")]
+fn inflate<R: io::Read>(decoder: R, max: usize) -> Result<Vec<u8>, BodyError> {
+    let mut result = Vec::new();
+    decoder.take(max as u64 + 1)
+           .read_to_end(&mut result)
+           .map_err(|_| BodyError::BadEncoding)?;
+    if result.len() > max {
+        return Err(BodyError::TooLarge);
+    }
+    Ok(result)
+}
+
+/// The length of the body of `r`, or an error if it's more than `max`.
+fn checked_length(r: &Request, max: usize) -> Result<usize, BodyError> {
+    let length = if r.headers().contains_key("Content-Length") {
+        get_content_length(r).map_err(|_| BodyError::BadContentLength)?
+    } else {
+        0
+    };
+    if length > max {
+        Err(BodyError::TooLarge)
+    } else {
+        Ok(length)
+    }
+}
+
+/// Get the size from a chunk-size line, passing any extensions after it to
+/// `on_extension`. The size must be plain hex digits - `from_str_radix`
+/// would also take a leading `+`, which nobody else would agree was a size.
+fn chunk_size<F>(line: &[u8], on_extension: &mut F) -> Result<usize, BodyError>
+    where F: FnMut(&str, Option<&str>)
+{
+    let line = str::from_utf8(line).map_err(|_| BodyError::BadChunk)?;
+    let (size, extensions) = match line.find(';') {
+        Some(idx) => (&line[..idx], Some(&line[idx + 1..])),
+        None => (line, None),
+    };
+    let size = size.trim_matches(is_whitespace);
+    if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(BodyError::BadChunk);
+    }
+    let size = usize::from_str_radix(size, 16).map_err(|_| BodyError::BadChunk)?;
+    if let Some(extensions) = extensions {
+        for extension in split_unquoted(extensions, ';').ok_or(BodyError::BadChunk)? {
+            let (name, value) = match extension.find('=') {
+                Some(idx) => (&extension[..idx], Some(&extension[idx + 1..])),
+                None => (extension, None),
+            };
+            let name = name.trim_matches(is_whitespace);
+            if name.is_empty() || !name.chars().all(is_tchar) {
+                return Err(BodyError::BadChunk);
+            }
+            match value.map(|v| v.trim_matches(is_whitespace)) {
+                Some(v) if v.starts_with('"') => {
+                    let v = unquote(v).ok_or(BodyError::BadChunk)?;
+                    on_extension(name, Some(&v))
+                }
+                Some(v) if !v.is_empty() && v.chars().all(is_tchar) => on_extension(name, Some(v)),
+                Some(_) => return Err(BodyError::BadChunk),
+                None => on_extension(name, None),
+            }
+        }
+    }
+    Ok(size)
+}
+
+/// Whether `c` is allowed in a token (RFC 7230 section 3.2.6).
+fn is_tchar(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+}
+
+/// Optional whitespace, as allowed around the parts of a chunk-size line.
+fn is_whitespace(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+/// Read a line from a chunked body, without its CRLF (or bare LF).
+fn read_line<R: io::Read>(source: &mut R) -> Result<Vec<u8>, BodyError> {
+    let mut line = Vec::new();
+    let mut octet = [0u8; 1];
+    loop {
+        source.read_exact(&mut octet)?;
+        match octet[0] {
+            b'\n' => break,
+            _ if line.len() >= MAX_CHUNK_LINE => return Err(BodyError::BadChunk),
+            c => line.push(c),
+        }
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(line)
+}
+
+/// Collect `length` octets, starting with `leftover` then using `reader`.
+fn read_length<R: io::Read>(length: usize,
+                            leftover: &[u8],
+                            reader: &mut R)
+                            -> Result<Vec<u8>, BodyError> {
+    if length == 0 {
+        return Ok(Vec::new());
+    }
+    let mut body = Vec::new();
+    append_exact(&mut leftover.chain(reader), length, &mut body)?;
+    Ok(body)
+}
+
+/// Read exactly `length` octets from `source` onto the end of `body`. The
+/// length comes from the client, so rather than making room for it all up
+/// front we let `body` grow as the data actually arrives. Fails with
+/// `BodyError::Truncated` if `source` runs out first.
+fn append_exact<R: io::Read>(source: &mut R,
+                             length: usize,
+                             body: &mut Vec<u8>)
+                             -> Result<(), BodyError> {
+    let got = source.take(length as u64).read_to_end(body)?;
+    if got < length {
+        return Err(BodyError::Truncated);
+    }
+    Ok(())
+}
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
@@ -0,0 +1,81 @@
+//! # Cookies
+//!
+//! Helpers for reading the cookies a client sends in the `Cookie` request
+//! header (RFC 6265 section 5.4).
+
+// ****************************************************************************
+//
+// Imports
+//
+// ****************************************************************************
+
+use request::Request;
+
+// ****************************************************************************
+//
+// Public Types
+//
+// ****************************************************************************
+
+// None
+
+// ****************************************************************************
+//
+// Private Types
+//
+// ****************************************************************************
+
+// None
+
+// ****************************************************************************
+//
+// Public Functions
+//
+// ****************************************************************************
+
+/// Get the `(name, value)` pairs from the request's `Cookie` header(s), in
+/// the order sent. If there are several `Cookie` headers, their cookies are
+/// concatenated. Names and values are trimmed of whitespace, and a value in
+/// double quotes has the quotes removed. Values are not percent-decoded, as
+/// cookies aren't percent-encoded (although some applications do it
+/// themselves). Entries without an `=` or with an empty name are skipped.
+pub fn cookies(r: &Request) -> Vec<(String, String)> {
+    r.headers()
+        .get_all("Cookie")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let name = parts.next()?.trim();
+            let value = parts.next()?.trim();
+            if name.is_empty() {
+                return None;
+            }
+            Some((name.to_string(), unquote(value).to_string()))
+        })
+        .collect()
+}
+
+// ****************************************************************************
+//
+// Private Functions
+//
+// ****************************************************************************
+
+/// Remove the double quotes from around a cookie value, if it has them.
+/// Cookie values have no escapes (RFC 6265 section 4.1.1), so nothing
+/// inside the quotes is touched.
+fn unquote(value: &str) -> &str {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
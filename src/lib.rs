@@ -2,6 +2,12 @@
 //!
 //! The rushttp library is an HTTP parser/encoder written in Rust.
 //! It can be used to write small web servers.
+//!
+//! The library never prints anything. With the `logging` feature enabled,
+//! it reports what it's doing (why a parse failed, connections the server
+//! turned away and so on) through the `log` crate, at `debug` and `trace`
+//! level, so you decide what to see. Without it, there's no logging code
+//! at all.
 
 // ****************************************************************************
 //
@@ -10,9 +16,48 @@
 // ****************************************************************************
 
 extern crate http;
+#[cfg(feature = "gzip")]
+extern crate flate2;
+#[cfg(feature = "logging")]
+#[macro_use]
+extern crate log;
+#[cfg(feature = "async")]
+extern crate tokio;
+
+// Without the `logging` feature, the log macros do nothing (but we still
+// check their arguments).
+#[cfg(not(feature = "logging"))]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+#[cfg(not(feature = "logging"))]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
 
+#[cfg(feature = "async")]
+pub mod async_io;
+pub mod auth;
+pub mod body;
+pub mod cookie;
+pub mod negotiation;
+pub mod query;
 pub mod request;
 pub mod response;
+pub mod routing;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 pub use http::*;
 
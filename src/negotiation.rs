@@ -0,0 +1,371 @@
+//! # Content Negotiation
+//!
+//! Helpers for working out which of the representations a server can offer
+//! the client would most like, based on the `Accept` family of headers.
+//! `Accept` is handled with `parse_accept` and `best_match`,
+//! `Accept-Encoding` with `preferred_encoding`, and `Accept-Language` with
+//! `parse_accept_language` and `best_language`.
+
+// ****************************************************************************
+//
+// Imports
+//
+// ****************************************************************************
+
+use std::cmp::Ordering;
+
+use request::Request;
+
+// ****************************************************************************
+//
+// Public Types
+//
+// ****************************************************************************
+
+/// One entry from an `Accept` header, e.g. `text/html;level=1;q=0.8`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaRange {
+    /// The top-level type (lower-cased), or `*`
+    pub type_: String,
+    /// The subtype (lower-cased), or `*`
+    pub subtype: String,
+    /// The `q` parameter, from 0.0 to 1.0. Defaults to 1.0.
+    pub quality: f32,
+    /// Any parameters other than `q`, in the order given
+    pub params: Vec<(String, String)>,
+}
+
+/// One entry from an `Accept-Language` header, e.g. `en-GB;q=0.8`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LanguageRange {
+    /// The language tag (lower-cased), e.g. `en-gb`, or `*`
+    pub tag: String,
+    /// The `q` parameter, from 0.0 to 1.0. Defaults to 1.0.
+    pub quality: f32,
+}
+
+// ****************************************************************************
+//
+// Private Types
+//
+// ****************************************************************************
+
+// None
+
+// ****************************************************************************
+//
+// Public Functions
+//
+// ****************************************************************************
+
+/// Parse the `Accept` header(s) of `r` into a list of media ranges, most
+/// preferred first. Ranges with equal quality are ordered most specific
+/// first (so `text/html` comes before `text/*`, which comes before `*/*`).
+/// Entries which can't be parsed are skipped. If there is no `Accept`
+/// header the client accepts anything, so you get a single `*/*`.
+pub fn parse_accept(r: &Request) -> Vec<MediaRange> {
+    if !r.headers().contains_key("Accept") {
+        return vec![MediaRange {
+                        type_: "*".to_string(),
+                        subtype: "*".to_string(),
+                        quality: 1.0,
+                        params: Vec::new(),
+                    }];
+    }
+    let mut ranges: Vec<MediaRange> = r.headers()
+        .get_all("Accept")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(MediaRange::parse)
+        .collect();
+    // A stable sort, so equally good ranges stay in the order the client sent
+    ranges.sort_by(|a, b| {
+        b.quality
+         .partial_cmp(&a.quality)
+         .unwrap_or(Ordering::Equal)
+         .then(b.specificity().cmp(&a.specificity()))
+    });
+    ranges
+}
+
+/// Pick whichever of the `offered` media types (e.g. `"application/json"`)
+/// the client prefers, given the ranges from `parse_accept`. Each offered
+/// type gets the quality of the most specific range which matches it, and
+/// the best one wins, with ties going to whichever was offered first.
+/// Returns `None` if the client accepts none of them (or gives them all
+/// `q=0`).
+pub fn best_match(ranges: &[MediaRange], offered: &[&str]) -> Option<String> {
+    let mut best: Option<(&str, f32)> = None;
+    for candidate in offered {
+        let media = match MediaRange::parse(candidate) {
+            Some(m) => m,
+            None => continue,
+        };
+        let mut quality: Option<(u8, f32)> = None;
+        for range in ranges.iter().filter(|r| r.matches(&media)) {
+            match quality {
+                Some((specificity, _)) if specificity >= range.specificity() => {}
+                _ => quality = Some((range.specificity(), range.quality)),
+            }
+        }
+        if let Some((_, q)) = quality {
+            match best {
+                Some((_, best_q)) if best_q >= q => {}
+                _ if q > 0.0 => best = Some((candidate, q)),
+                _ => {}
+            }
+        }
+    }
+    best.map(|(m, _)| m.to_string())
+}
+
+/// Pick whichever of the `supported` content-codings (e.g. `"gzip"`,
+/// `"identity"`) the client's `Accept-Encoding` header prefers, with ties
+/// going to whichever comes first in `supported`. `identity` (no encoding)
+/// is acceptable unless refused with `identity;q=0` or `*;q=0`, but loses to
+/// any coding the client actually asked for. Without an `Accept-Encoding`
+/// header only `identity` is acceptable. Returns `None` if nothing in
+/// `supported` is acceptable.
+pub fn preferred_encoding(r: &Request, supported: &[&str]) -> Option<String> {
+    let accepted = parse_accept_encoding(r);
+    let mut best: Option<(&str, f32, bool)> = None;
+    for coding in supported {
+        let (q, explicit) = match encoding_quality(accepted.as_ref().map(|a| &a[..]), coding) {
+            Some(x) => x,
+            None => continue,
+        };
+        if q <= 0.0 {
+            continue;
+        }
+        match best {
+            Some((_, best_q, best_explicit)) if (best_q, best_explicit) >= (q, explicit) => {}
+            _ => best = Some((coding, q, explicit)),
+        }
+    }
+    best.map(|(c, _, _)| c.to_string())
+}
+
+/// Whether the client will accept a gzip-encoded response.
+pub fn accepts_gzip(r: &Request) -> bool {
+    preferred_encoding(r, &["gzip"]).is_some()
+}
+
+/// Parse the `Accept-Language` header(s) of `r` into a list of language
+/// ranges, most preferred first. Ranges with equal quality stay in the order
+/// the client sent them. Entries which can't be parsed are skipped. If
+/// there is no `Accept-Language` header you get an empty list, which
+/// `best_language` treats as accepting any language.
+pub fn parse_accept_language(r: &Request) -> Vec<LanguageRange> {
+    let mut ranges: Vec<LanguageRange> = r.headers()
+        .get_all("Accept-Language")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(LanguageRange::parse)
+        .collect();
+    // A stable sort, so equally good ranges stay in the order the client sent
+    ranges.sort_by(|a, b| b.quality.partial_cmp(&a.quality).unwrap_or(Ordering::Equal));
+    ranges
+}
+
+/// Pick whichever of the `supported` languages (e.g. `"en-GB"`) the client
+/// prefers, given the ranges from `parse_accept_language`. A range matches
+/// a language if it's the same tag (ignoring case), if it's a prefix of it
+/// (`en` matches `en-GB`), if the language is a prefix of it (`en-US` falls
+/// back to `en`), or if it's `*`. Each language gets the quality of the
+/// closest range which matches it, in that order, and the best one wins,
+/// with ties going to whichever comes first in `supported`. With no ranges
+/// at all, the first supported language wins. Returns `None` if the client
+/// accepts none of them (or gives them all `q=0`).
+pub fn best_language(ranges: &[LanguageRange], supported: &[&str]) -> Option<String> {
+    if ranges.is_empty() {
+        return supported.first().map(|l| l.to_string());
+    }
+    let mut best: Option<(&str, f32)> = None;
+    for candidate in supported {
+        let mut quality: Option<(u8, f32)> = None;
+        for range in ranges {
+            let closeness = match range.closeness(candidate) {
+                Some(c) => c,
+                None => continue,
+            };
+            match quality {
+                Some((best_closeness, _)) if best_closeness >= closeness => {}
+                _ => quality = Some((closeness, range.quality)),
+            }
+        }
+        if let Some((_, q)) = quality {
+            match best {
+                Some((_, best_q)) if best_q >= q => {}
+                _ if q > 0.0 => best = Some((candidate, q)),
+                _ => {}
+            }
+        }
+    }
+    best.map(|(l, _)| l.to_string())
+}
+
+impl MediaRange {
+    /// Whether this range covers `media`, which should be a concrete type.
+    /// Any parameters on the range must also be present on `media`.
+    pub fn matches(&self, media: &MediaRange) -> bool {
+        (self.type_ == "*" || self.type_ == media.type_) &&
+        (self.subtype == "*" || self.subtype == media.subtype) &&
+        self.params.iter().all(|p| media.params.contains(p))
+    }
+
+    /// Parse one comma-separated entry of an `Accept` header.
+    fn parse(entry: &str) -> Option<MediaRange> {
+        let mut parts = entry.split(';');
+        let mut media = parts.next()?.trim().splitn(2, '/');
+        let type_ = media.next()?.trim().to_ascii_lowercase();
+        let subtype = media.next()?.trim().to_ascii_lowercase();
+        if type_.is_empty() || subtype.is_empty() || (type_ == "*" && subtype != "*") {
+            return None;
+        }
+        let mut quality = 1.0;
+        let mut params = Vec::new();
+        for param in parts {
+            let mut kv = param.splitn(2, '=');
+            let key = kv.next()?.trim().to_ascii_lowercase();
+            let value = kv.next()?.trim().trim_matches('"');
+            if key == "q" {
+                quality = parse_quality(value)?;
+            } else {
+                params.push((key, value.to_string()));
+            }
+        }
+        Some(MediaRange {
+            type_,
+            subtype,
+            quality,
+            params,
+        })
+    }
+
+    /// `*/*` < `text/*` < `text/html` < `text/html;level=1`
+    fn specificity(&self) -> u8 {
+        match (self.type_.as_str(), self.subtype.as_str()) {
+            ("*", _) => 0,
+            (_, "*") => 1,
+            _ if self.params.is_empty() => 2,
+            _ => 3,
+        }
+    }
+}
+
+impl LanguageRange {
+    /// Parse one comma-separated entry of an `Accept-Language` header.
+    fn parse(entry: &str) -> Option<LanguageRange> {
+        let mut parts = entry.split(';');
+        let tag = parts.next()?.trim().to_ascii_lowercase();
+        let subtag_ok = |t: &str| !t.is_empty() && t.bytes().all(|b| b.is_ascii_alphanumeric());
+        if tag != "*" && !tag.split('-').all(subtag_ok) {
+            return None;
+        }
+        let mut quality = 1.0;
+        for param in parts {
+            let mut kv = param.splitn(2, '=');
+            if kv.next()?.trim().eq_ignore_ascii_case("q") {
+                quality = parse_quality(kv.next()?.trim())?;
+            }
+        }
+        Some(LanguageRange { tag, quality })
+    }
+
+    /// How closely this range matches `language`, if at all: 3 for the same
+    /// tag, 2 if the range is a prefix of the language, 1 if the language is
+    /// a prefix of the range and 0 for `*`.
+    fn closeness(&self, language: &str) -> Option<u8> {
+        let language = language.to_ascii_lowercase();
+        if self.tag == language {
+            Some(3)
+        } else if is_prefix(&self.tag, &language) {
+            Some(2)
+        } else if is_prefix(&language, &self.tag) {
+            Some(1)
+        } else if self.tag == "*" {
+            Some(0)
+        } else {
+            None
+        }
+    }
+}
+
+// ****************************************************************************
+//
+// Private Functions
+//
+// ****************************************************************************
+
+/// Parse the `Accept-Encoding` header(s) of `r` into `(coding, quality)`
+/// pairs, with the codings lower-cased. `None` if there's no header at all.
+fn parse_accept_encoding(r: &Request) -> Option<Vec<(String, f32)>> {
+    if !r.headers().contains_key("Accept-Encoding") {
+        return None;
+    }
+    let codings = r.headers()
+        .get_all("Accept-Encoding")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let coding = parts.next()?.trim().to_ascii_lowercase();
+            if coding.is_empty() {
+                return None;
+            }
+            let mut quality = 1.0;
+            for param in parts {
+                let mut kv = param.splitn(2, '=');
+                if kv.next()?.trim().eq_ignore_ascii_case("q") {
+                    quality = parse_quality(kv.next()?.trim())?;
+                }
+            }
+            Some((coding, quality))
+        })
+        .collect();
+    Some(codings)
+}
+
+/// How much the client wants `coding`, and whether it said so explicitly
+/// (or via `*`). `None` if it's not acceptable at all.
+fn encoding_quality(accepted: Option<&[(String, f32)]>, coding: &str) -> Option<(f32, bool)> {
+    let identity = coding.eq_ignore_ascii_case("identity");
+    let accepted = match accepted {
+        Some(a) => a,
+        None if identity => return Some((1.0, false)),
+        None => return None,
+    };
+    let find = |name: &str| accepted.iter().find(|&(c, _)| c.eq_ignore_ascii_case(name));
+    if let Some(&(_, q)) = find(coding) {
+        Some((q, true))
+    } else if let Some(&(_, q)) = find("*") {
+        Some((q, true))
+    } else if identity {
+        Some((1.0, false))
+    } else {
+        None
+    }
+}
+
+/// Whether language tag `prefix` is `tag` with some subtags removed from
+/// the end, e.g. `en` is a prefix of `en-gb` but not of `eng`.
+fn is_prefix(prefix: &str, tag: &str) -> bool {
+    tag.len() > prefix.len() && tag.starts_with(prefix) && tag.as_bytes()[prefix.len()] == b'-'
+}
+
+/// Parse a `q` value, which must be between 0 and 1.
+fn parse_quality(value: &str) -> Option<f32> {
+    match value.parse::<f32>() {
+        Ok(q) if (0.0..=1.0).contains(&q) => Some(q),
+        _ => None,
+    }
+}
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
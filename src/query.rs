@@ -0,0 +1,171 @@
+//! # Query Strings
+//!
+//! Helpers for picking apart the query string of a request URI, e.g.
+//! `/search?q=rust+http&page=2`, into decoded `(name, value)` pairs, or
+//! into a type of your own which implements `FromQuery`.
+
+// ****************************************************************************
+//
+// Imports
+//
+// ****************************************************************************
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+use request::{percent_decode, Request};
+
+// ****************************************************************************
+//
+// Public Types
+//
+// ****************************************************************************
+
+/// The ways picking apart a query string can fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    /// There were more pairs than the caller was willing to accept
+    TooManyPairs,
+    /// A parameter the caller needs wasn't given
+    Missing(String),
+    /// A parameter's value couldn't be understood
+    BadValue(String),
+}
+
+/// Implement this for a type which can be built from a query string, then
+/// get one with `query_as`. In `from_query`, use `required` and `optional`
+/// to fetch and parse each field, e.g. `page: query::required(pairs,
+/// "page")?`.
+pub trait FromQuery: Sized {
+    /// Build the value from the decoded `(name, value)` pairs, in the order
+    /// given.
+    fn from_query(pairs: &[(String, String)]) -> Result<Self, QueryError>;
+}
+
+// ****************************************************************************
+//
+// Private Types
+//
+// ****************************************************************************
+
+// None
+
+// ****************************************************************************
+//
+// Public Functions
+//
+// ****************************************************************************
+
+/// Split the query string of `r` into `(name, value)` pairs, in the order
+/// given. Names and values are percent-decoded, with `+` meaning a space. A
+/// pair without an `=` gets an empty value, empty pairs (`a=1&&b=2`) are
+/// skipped, as are pairs with a malformed escape.
+///
+/// There's no limit on the number of pairs, so a client can make this
+/// allocate as much as the URI is long. If you parse the query of every
+/// request, consider `query_pairs_limited` instead.
+pub fn query_pairs(r: &Request) -> Vec<(String, String)> {
+    raw_pairs(r).filter_map(decode_pair).collect()
+}
+
+/// Like `query_pairs`, but fails with `QueryError::TooManyPairs` if there
+/// are more than `max_pairs` pairs, without decoding any more than that.
+pub fn query_pairs_limited(r: &Request,
+                           max_pairs: usize)
+                           -> Result<Vec<(String, String)>, QueryError> {
+    let mut pairs = Vec::new();
+    for (i, pair) in raw_pairs(r).enumerate() {
+        if i >= max_pairs {
+            return Err(QueryError::TooManyPairs);
+        }
+        if let Some(p) = decode_pair(pair) {
+            pairs.push(p);
+        }
+    }
+    Ok(pairs)
+}
+
+/// Decode the query string of `r` (as `query_pairs` does) into a `T`.
+pub fn query_as<T: FromQuery>(r: &Request) -> Result<T, QueryError> {
+    T::from_query(&query_pairs(r))
+}
+
+/// Get the first value of parameter `name` from `pairs`, parsed as a `T`.
+/// Fails with `QueryError::Missing` if it isn't there and
+/// `QueryError::BadValue` if it won't parse.
+pub fn required<T: FromStr>(pairs: &[(String, String)], name: &str) -> Result<T, QueryError> {
+    optional(pairs, name)?.ok_or_else(|| QueryError::Missing(name.to_string()))
+}
+
+/// Like `required`, but a missing parameter gives `Ok(None)`.
+pub fn optional<T: FromStr>(pairs: &[(String, String)],
+                            name: &str)
+                            -> Result<Option<T>, QueryError> {
+    match pairs.iter().find(|(k, _)| k == name) {
+        Some((_, v)) => v.parse().map(Some).map_err(|_| QueryError::BadValue(name.to_string())),
+        None => Ok(None),
+    }
+}
+
+/// The pairs exactly as `query_pairs` gives them.
+impl FromQuery for Vec<(String, String)> {
+    fn from_query(pairs: &[(String, String)]) -> Result<Self, QueryError> {
+        Ok(pairs.to_vec())
+    }
+}
+
+/// Each name mapped to its value. If a name appears more than once, the
+/// first value wins.
+impl FromQuery for HashMap<String, String> {
+    fn from_query(pairs: &[(String, String)]) -> Result<Self, QueryError> {
+        let mut map = HashMap::new();
+        for (k, v) in pairs {
+            map.entry(k.clone()).or_insert_with(|| v.clone());
+        }
+        Ok(map)
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            QueryError::TooManyPairs => write!(f, "Too many query parameters"),
+            QueryError::Missing(ref name) => write!(f, "Missing query parameter {}", name),
+            QueryError::BadValue(ref name) => write!(f, "Bad value for query parameter {}", name),
+        }
+    }
+}
+
+impl error::Error for QueryError {}
+
+// ****************************************************************************
+//
+// Private Functions
+//
+// ****************************************************************************
+
+/// The non-empty, still encoded, `&`-separated pieces of the query string.
+fn raw_pairs(r: &Request) -> impl Iterator<Item = &str> {
+    r.uri().query().unwrap_or("").split('&').filter(|p| !p.is_empty())
+}
+
+/// Split `name=value` and decode both halves.
+fn decode_pair(pair: &str) -> Option<(String, String)> {
+    let mut kv = pair.splitn(2, '=');
+    let name = kv.next().unwrap_or("");
+    let value = kv.next().unwrap_or("");
+    Some((decode_component(name)?, decode_component(value)?))
+}
+
+/// Query strings use `+` for a space as well as `%20`.
+fn decode_component(s: &str) -> Option<String> {
+    percent_decode(&s.replace('+', " "))
+}
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
@@ -9,10 +9,18 @@
 //
 // ****************************************************************************
 
+use std::error;
+use std::fmt;
+use std::io;
+use std::mem;
 use std::str;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use http;
 
+use response::HttpResponseStatus;
+
 // ****************************************************************************
 //
 // Public Types
@@ -22,6 +30,9 @@ use http;
 /// Our request type. We don't include the body in our request, so its type is set to `()`.
 pub type Request = http::Request<()>;
 
+/// A header name and its value, exactly as they arrived on the wire.
+pub type RawHeader = (String, Vec<u8>);
+
 /// Contains the internal state for the parser.
 #[derive(Debug)]
 pub struct Parser {
@@ -34,13 +45,54 @@ pub struct Parser {
     /// A collection of HTTP headers (key,value) pairs. We need them in-order
     /// as if the next line begins with a space, we need to append to the
     /// previous header's value.
-    headers: Vec<(String, Vec<u8>)>,
+    headers: Vec<RawHeader>,
     /// A temporary holder for the key while we read the value
     key: String,
+    /// Called when the header block is complete, before the request is built
+    headers_hook: Option<HeadersHook>,
+    /// Where to send header values too big to buffer
+    large_value_hook: Option<LargeValueHook>,
+    /// How many empty lines we've skipped before the request line
+    leading_empty_lines: usize,
+    /// How many continuation lines the current header has had
+    fold_lines: usize,
+    /// How many continuation lines we allow for any one header
+    max_fold_lines: usize,
+    /// How many headers we allow, if there's a limit
+    max_header_count: Option<usize>,
+    /// A verbatim copy of the request line, if we've been asked to keep one
+    raw_request_line: Option<Vec<u8>>,
+    /// The trailer fields which followed a chunked body
+    trailers: Vec<RawHeader>,
+    /// How many trailer fields we've seen, announced or not
+    trailer_count: usize,
+    /// How header names are stored in `headers` and `trailers`
+    casing: Casing,
+    /// Whether to copy runs of ordinary octets in bulk
+    fast_path: bool,
+    /// Whether to allow several spaces between request-line tokens
+    lenient_request_line: bool,
+    /// Whether GET, HEAD and DELETE requests may have a body
+    reject_bodiless_bodies: bool,
+    /// Whether to refuse transfer codings other than `chunked`
+    reject_unsupported_codings: bool,
+    /// Whether this is a CONNECT request, whose target is different
+    connect: bool,
+    /// What to do with header values which aren't UTF-8
+    value_encoding: HeaderValueEncoding,
+    /// Everything passed to `parse_owned` so far, end to end
+    arena: Vec<u8>,
+    /// How much of a `Content-Length` body we haven't seen yet
+    body_remaining: Option<usize>,
+    /// The most octets (headers and body) we'll take for one request
+    max_total_bytes: Option<usize>,
+    /// How many octets of the current request we've taken so far
+    total_bytes: usize,
 }
 
 /// Indicates whether the parser has seen enough, needs more data, or has abandoned the parse.
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum ParseResult {
     /// Parse abandoned - there was an unspecified problem with the input
     Error,
@@ -54,24 +106,221 @@ pub enum ParseResult {
     ErrorBadProtocol,
     /// Didn't like the URL,
     ErrorBadURL,
+    /// A header was folded over too many continuation lines
+    ErrorObsFold,
+    /// The input ended part way through the request
+    ErrorUnexpectedEof,
+    /// The client took too long to send anything
+    ErrorTimeout,
+    /// The protocol was well-formed (e.g. `HTTP/3.0`) but not one we speak
+    ErrorUnsupportedVersion,
+    /// A GET, HEAD or DELETE request had a body, and we'd said not to allow that
+    ErrorUnexpectedBody,
+    /// The `Transfer-Encoding` was repeated, unknown or didn't end in `chunked`
+    ErrorBadTransferEncoding,
+    /// The request was bigger than `Parser::max_total_bytes` allows
+    ErrorRequestTooLarge,
+    /// There were more headers than `Parser::max_header_count` allows
+    ErrorTooManyHeaders,
+    /// The body has a transfer coding we can't decode - see
+    /// `Parser::reject_unsupported_transfer_codings`
+    ErrorUnsupportedTransferEncoding,
     /// Parse in progress - need more input
     InProgress,
     /// Parse complete - request object available, and we also report
-    /// the number of octets taken from the given buffer. If there
-    /// are any octets remaining, they are probably body content.
+    /// the number of octets taken from the given buffer (the one passed to
+    /// this call, not everything so far). That's everything up to and
+    /// including the LF of the blank line which ends the headers, however
+    /// the lines ended, so the body (if any) starts at exactly
+    /// `&buffer[used..]`.
     Complete(Request, usize),
 }
 
+/// The result of `Parser::parse_request_line`.
+#[derive(Debug)]
+pub enum RequestLineResult {
+    /// Parse abandoned - the request line was bad
+    Error(ParseError),
+    /// Parse in progress - the buffer doesn't hold a whole request line
+    InProgress,
+    /// Parse complete - we have the method, URL and protocol version, and
+    /// also report the number of octets taken from the buffer (up to and
+    /// including the line ending). The headers start after that.
+    Complete(http::Method, http::Uri, http::Version, usize),
+}
+
+/// The result of `Parser::parse_trailers`.
+#[derive(Debug)]
+pub enum TrailersResult {
+    /// Parse abandoned - one of the trailer lines was bad
+    Error(ParseError),
+    /// Parse in progress - we haven't seen the blank line yet
+    InProgress,
+    /// Parse complete - the trailers are available from `Parser::trailers`,
+    /// and this many octets were taken from the buffer.
+    Complete(usize),
+}
+
+/// A request parsed by `parse_once`, which borrows everything from the
+/// buffer it was parsed from rather than copying it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BorrowedRequest<'b> {
+    /// The method, e.g. `GET`
+    pub method: &'b str,
+    /// The request target, e.g. `/index.html?x=1`
+    pub uri: &'b str,
+    /// The protocol version
+    pub version: http::Version,
+    /// The headers in the order received, with whitespace around the value
+    /// trimmed off
+    pub headers: Vec<(&'b str, &'b [u8])>,
+}
+
+/// A parsed request and its body, with simple accessors for the common
+/// parts, for when you just want to write a small server and don't want to
+/// learn the `http` crate. The `http::Request` is still there if you need
+/// it - see `into_inner`.
+#[derive(Debug)]
+pub struct ParsedRequest {
+    /// The request from the parser
+    request: Request,
+    /// The body, if it's been read
+    body: Vec<u8>,
+}
+
+/// The result of `parse_once`.
+#[derive(Debug)]
+pub enum OnceResult<'b> {
+    /// The request was bad
+    Error(ParseError),
+    /// The buffer doesn't hold a whole header block. With `parse_once`
+    /// there's no way to resume - use a `Parser` if the request might arrive
+    /// in pieces. With `Parser::parse_owned`, pass in the next buffer.
+    Incomplete,
+    /// The request, and the number of octets taken from the buffer
+    /// (anything after that is body).
+    Complete(BorrowedRequest<'b>, usize),
+}
+
+/// The reasons a parse can fail. Each of these has a matching `Error...`
+/// variant in `ParseResult`, apart from `Incomplete`, which stands in for
+/// `ParseResult::InProgress`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// There was an unspecified problem with the input
+    Malformed,
+    /// Didn't like one of the header names
+    BadHeader,
+    /// Didn't like one of the header values
+    BadHeaderValue,
+    /// Didn't like the method (e.g. GET)
+    BadMethod,
+    /// Didn't like the protocol (e.g. HTTP/1.1)
+    BadProtocol,
+    /// Didn't like the URL
+    BadURL,
+    /// A header was folded over too many continuation lines
+    ObsFold,
+    /// The input ended part way through the request
+    UnexpectedEof,
+    /// The client took too long to send anything
+    Timeout,
+    /// The protocol was well-formed (e.g. `HTTP/3.0`) but not one we speak
+    UnsupportedVersion,
+    /// A GET, HEAD or DELETE request had a body, and we'd said not to allow that
+    UnexpectedBody,
+    /// The `Transfer-Encoding` was repeated, unknown or didn't end in `chunked`
+    BadTransferEncoding,
+    /// The request was bigger than `Parser::max_total_bytes` allows
+    RequestTooLarge,
+    /// There were more headers than `Parser::max_header_count` allows
+    TooManyHeaders,
+    /// The body has a transfer coding we can't decode - see
+    /// `Parser::reject_unsupported_transfer_codings`
+    UnsupportedTransferEncoding,
+    /// Not really an error - the request isn't finished yet, so try again
+    /// with more input. Only comes from `ParseResult::into_result`.
+    Incomplete,
+}
+
+/// What a `Parser::on_headers_complete` hook wants done with the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderDecision {
+    /// Carry on and build the request
+    Continue,
+    /// Abandon the parse with the given error. `Incomplete` isn't an error,
+    /// so rejecting with it gives `Malformed` instead.
+    Reject(ParseError),
+}
+
+/// How the parser should store header names in its raw header list. The
+/// `http::HeaderMap` in the parsed request always lower-cases them anyway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Casing {
+    /// Exactly as the client sent them
+    AsReceived,
+    /// All lower case, e.g. `content-type`
+    Lowercase,
+    /// Each word capitalised, e.g. `Content-Type`
+    TitleCase,
+}
+
+/// What the parser should do with header values which aren't valid UTF-8.
+/// HTTP allows octets 0x80-0xFF in values (obs-text) without saying what
+/// they mean, but most applications expect text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderValueEncoding {
+    /// Keep the octets exactly as received
+    Raw,
+    /// Reject the request with `ErrorBadHeaderValue`
+    Strict,
+    /// Replace each invalid sequence with U+FFFD
+    Lossy,
+}
+
+/// The reasons `normalize_path` can refuse a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathError {
+    /// A `..` would climb above the root
+    Traversal,
+    /// A segment had an escaped separator (`%2F` or `%5C`), which whatever
+    /// uses the path might mistake for a real one
+    EncodedSeparator,
+    /// A percent-escape was malformed, or the path isn't UTF-8
+    BadEncoding,
+}
+
 // ****************************************************************************
 //
 // Private Types
 //
 // ****************************************************************************
 
+/// Wraps the user's headers-complete closure so the `Parser` can still be
+/// `Debug`.
+struct HeadersHook(Box<HeadersFn>);
+
+/// The signature of a headers-complete hook.
+type HeadersFn = dyn FnMut(&[RawHeader]) -> HeaderDecision + Send;
+
+/// The user's large-header-value closure, and how we're getting on.
+struct LargeValueHook {
+    /// How much of a value we'll buffer before handing it over
+    threshold: usize,
+    /// Gets the header name and each piece of the value
+    callback: Box<LargeValueFn>,
+    /// Whether the current value is being streamed
+    active: bool,
+}
+
+/// The signature of a large-header-value hook.
+type LargeValueFn = dyn FnMut(&str, &[u8]) + Send;
+
 #[derive(PartialEq, Debug)]
 enum ParseState {
     Method,
-    URL,
+    LeadingEOL,
+    Url,
     Protocol,
     ProtocolEOL,
     KeyStart,
@@ -88,12 +337,47 @@ enum ParseState {
 #[derive(Debug)]
 enum CharType {
     Other,
+    /// Either SP or HTAB - they mean the same everywhere in the header block
     Space,
     Colon,
     CR,
     LF,
 }
 
+// ****************************************************************************
+//
+// Private Data
+//
+// ****************************************************************************
+
+/// How many empty lines we'll skip before the request line, before
+/// deciding the client is up to no good.
+const MAX_LEADING_EMPTY_LINES: usize = 8;
+
+/// How many continuation lines a header may have, unless the user says
+/// otherwise.
+const DEFAULT_MAX_FOLD_LINES: usize = 8;
+
+/// The longest trailer field line we'll buffer. Nobody needs a big trailer,
+/// so unlike a header value this doesn't wait for `max_total_bytes`.
+const MAX_TRAILER_LINE: usize = 8 * 1024;
+
+/// How much `read_request_from` asks for in each read.
+const READ_BUFFER_SIZE: usize = 1024;
+
+const MONTHS: [&str; 12] =
+    ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Starting from Thursday, as 1970-01-01 was a Thursday.
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+/// The transfer codings registered with IANA, which are all we'll accept.
+const TRANSFER_CODINGS: [&str; 6] = ["chunked", "compress", "deflate", "gzip", "x-compress",
+                                     "x-gzip"];
+
+/// The longest multipart boundary RFC 2046 allows.
+const MAX_BOUNDARY_LEN: usize = 70;
+
 // ****************************************************************************
 //
 // Public Functions
@@ -115,6 +399,441 @@ pub fn get_content_length(r: &Request) -> Result<usize, &'static str> {
     }
 }
 
+/// Whether the request says it has a body, either with a non-zero
+/// `Content-Length` or with `Transfer-Encoding: chunked`. If not, there's
+/// no body to read, whatever the method.
+pub fn has_body(r: &Request) -> bool {
+    is_chunked(r) || get_content_length(r).map(|l| l > 0).unwrap_or(false)
+}
+
+/// Whether the client sent `Expect: 100-continue`, and so is waiting for a
+/// `100 Continue` response before it sends the body. HTTP/1.0 clients don't
+/// know about 100 Continue, so this is always false for them.
+pub fn expects_continue(r: &Request) -> bool {
+    r.version() != http::Version::HTTP_10 && r.version() != http::Version::HTTP_09 &&
+    r.headers()
+     .get_all("Expect")
+     .iter()
+     .any(|v| v.to_str().map(|s| s.trim().eq_ignore_ascii_case("100-continue")).unwrap_or(false))
+}
+
+/// Whether the client wants the connection kept open for another request
+/// after this one. HTTP/1.1 connections are persistent unless the client
+/// sent `Connection: close`; HTTP/1.0 ones only if it sent `Connection:
+/// keep-alive` (RFC 7230 section 6.3).
+pub fn keep_alive(r: &Request) -> bool {
+    match r.version() {
+        http::Version::HTTP_09 => false,
+        http::Version::HTTP_10 => header_has_token(r, "Connection", "keep-alive"),
+        _ => !header_has_token(r, "Connection", "close"),
+    }
+}
+
+/// The `boundary` parameter of a `multipart/*` request's `Content-Type`,
+/// without any quotes, ready for splitting the body into parts. Returns
+/// `None` if the media type isn't multipart, or the boundary is missing or
+/// isn't one RFC 2046 allows: 1 to 70 characters from a limited set (which
+/// includes space, but not at the end).
+pub fn multipart_boundary(r: &Request) -> Option<String> {
+    let value = r.headers().get("Content-Type")?.to_str().ok()?;
+    let mut parts = split_unquoted(value, ';')?.into_iter();
+    let media_type = parts.next()?.trim().to_ascii_lowercase();
+    if !media_type.starts_with("multipart/") {
+        return None;
+    }
+    let value = parts.filter_map(|p| {
+                         let mut pair = p.splitn(2, '=');
+                         let name = pair.next()?.trim();
+                         let value = pair.next()?.trim();
+                         if name.eq_ignore_ascii_case("boundary") { Some(value) } else { None }
+                     })
+                     .next()?;
+    let boundary = if value.starts_with('"') { unquote(value)? } else { value.to_string() };
+    let valid = !boundary.is_empty() && boundary.len() <= MAX_BOUNDARY_LEN &&
+                !boundary.ends_with(' ') && boundary.chars().all(is_bchar);
+    if valid {
+        Some(boundary)
+    } else {
+        None
+    }
+}
+
+/// The protocols the client would like to switch to, from its `Upgrade`
+/// header(s), in its order of preference - e.g. `["h2c", "websocket"]`.
+/// An `Upgrade` header only counts if the `Connection` header includes
+/// `upgrade` (ignoring case), as otherwise a proxy has passed on a header
+/// meant for it alone, so without that the list is empty. Answer with
+/// `HttpResponse::switching_protocols`, or just ignore it.
+pub fn requested_upgrades(r: &Request) -> Vec<String> {
+    if !header_has_token(r, "Connection", "upgrade") {
+        return Vec::new();
+    }
+    r.headers()
+     .get_all("Upgrade")
+     .iter()
+     .filter_map(|v| v.to_str().ok())
+     .flat_map(|v| v.split(','))
+     .map(|t| t.trim())
+     .filter(|t| !t.is_empty())
+     .map(|t| t.to_string())
+     .collect()
+}
+
+/// Whether `current_etag` matches any entity tag in the request's
+/// `If-None-Match` header(s), in which case a GET or HEAD can be answered
+/// with `304 Not Modified`. `*` matches anything. As RFC 7232 requires for
+/// `If-None-Match`, the comparison is weak, so `W/"x"` matches `"x"`.
+/// `current_etag` may be given with or without its quotes. An entity tag
+/// may contain commas, so we only split the list outside quotes. Returns
+/// false if there is no `If-None-Match` header.
+pub fn etag_matches(r: &Request, current_etag: &str) -> bool {
+    let current = opaque_tag(current_etag.trim());
+    r.headers()
+     .get_all("If-None-Match")
+     .iter()
+     .filter_map(|v| v.to_str().ok())
+     .filter_map(|v| split_unquoted(v, ','))
+     .flatten()
+     .map(|tag| tag.trim())
+     .any(|tag| tag == "*" || opaque_tag(tag) == current)
+}
+
+/// The `Host` header split into the host name and the port, if one was
+/// given. An IPv6 literal keeps its brackets, so `[::1]:8080` gives
+/// `("[::1]", Some(8080))`. Returns `None` if there is no `Host` header, or
+/// it's empty or malformed.
+pub fn host(r: &Request) -> Option<(String, Option<u16>)> {
+    let value = r.headers().get("Host")?.to_str().ok()?.trim();
+    let (name, port) = split_authority(value)?;
+    Some((name.to_string(), port))
+}
+
+/// The host the client wants, and the port if it gave one, from either
+/// the `Host` header or, for clients which send the whole URL in the
+/// request line instead (as HTTP/1.0 clients talking to a proxy do), the
+/// authority of that absolute-form target. If there are both, the `Host`
+/// header wins. As with `host`, an IPv6 literal keeps its brackets.
+/// Returns `None` if neither gives a host.
+pub fn effective_host(r: &Request) -> Option<(String, Option<u16>)> {
+    host(r).or_else(|| r.uri().host().map(|h| (h.to_string(), r.uri().port_u16())))
+}
+
+/// The host and port a CONNECT request wants a tunnel to, from its
+/// authority-form target (e.g. `CONNECT example.com:443 HTTP/1.1`). As with
+/// `host`, an IPv6 literal keeps its brackets. Returns `None` for any other
+/// method.
+pub fn connect_target(r: &Request) -> Option<(String, u16)> {
+    if r.method() != http::Method::CONNECT {
+        return None;
+    }
+    let target = r.uri().to_string();
+    let (name, port) = authority_target(&target)?;
+    Some((name.to_string(), port))
+}
+
+/// The address of the client which originally sent the request, as
+/// reported by a reverse proxy. These headers are trivially forged, so
+/// unless `trusted_proxy` is true (i.e. the request definitely came through
+/// a proxy you run, which sets them) this always returns `None` and you
+/// should use the socket's peer address instead.
+///
+/// We use the first `for=` in the RFC 7239 `Forwarded` header(s), or if
+/// there isn't one, the first address in `X-Forwarded-For`. Either may
+/// include a port, and IPv6 addresses may be in brackets. Returns `None` if
+/// neither header is present, or the proxy gave an obfuscated identifier
+/// (like `for=_hidden`) or `unknown` instead of an address.
+pub fn client_ip(r: &Request, trusted_proxy: bool) -> Option<IpAddr> {
+    if !trusted_proxy {
+        return None;
+    }
+    let forwarded_for = r.headers()
+        .get_all("Forwarded")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(&[',', ';'][..]))
+        .filter_map(|pair| {
+            let mut kv = pair.splitn(2, '=');
+            if kv.next()?.trim().eq_ignore_ascii_case("for") {
+                kv.next()
+            } else {
+                None
+            }
+        })
+        .next();
+    match forwarded_for {
+        Some(node) => node_ip(node),
+        None => {
+            let xff = r.headers().get("X-Forwarded-For")?.to_str().ok()?;
+            node_ip(xff.split(',').next()?)
+        }
+    }
+}
+
+/// The request method as a string, e.g. `"GET"`.
+pub fn method_str(r: &Request) -> &str {
+    r.method().as_str()
+}
+
+/// Whether this is a `GET` request.
+pub fn is_get(r: &Request) -> bool {
+    r.method() == http::Method::GET
+}
+
+/// Whether this is a `HEAD` request.
+pub fn is_head(r: &Request) -> bool {
+    r.method() == http::Method::HEAD
+}
+
+/// Whether this is a `POST` request.
+pub fn is_post(r: &Request) -> bool {
+    r.method() == http::Method::POST
+}
+
+/// Whether this is a `PUT` request.
+pub fn is_put(r: &Request) -> bool {
+    r.method() == http::Method::PUT
+}
+
+/// Whether this is a `DELETE` request.
+pub fn is_delete(r: &Request) -> bool {
+    r.method() == http::Method::DELETE
+}
+
+/// Whether this is an `OPTIONS` request.
+pub fn is_options(r: &Request) -> bool {
+    r.method() == http::Method::OPTIONS
+}
+
+/// Whether this is a `PATCH` request.
+pub fn is_patch(r: &Request) -> bool {
+    r.method() == http::Method::PATCH
+}
+
+/// Returns the path component of the request's URI with any percent-escapes
+/// decoded. Returns `None` if an escape is malformed or the decoded path
+/// isn't valid UTF-8.
+pub fn decoded_path(r: &Request) -> Option<String> {
+    percent_decode(r.uri().path())
+}
+
+/// How many segments deep the request's path is, e.g. 3 for `/a/b/c`,
+/// which makes a cheap check for absurdly deep paths. Empty segments (from
+/// `//` or a trailing `/`) and `.` don't count, and `..` takes one off
+/// (but never below zero, for the root), so `/a/../b/./c` is 2 deep. The
+/// path is split on `/` before any percent-escapes are decoded, so `%2F`
+/// doesn't start a new segment, but `%2E%2E` is still `..`.
+pub fn path_depth(r: &Request) -> usize {
+    let mut depth: usize = 0;
+    for segment in r.uri().path().split('/') {
+        match percent_decode(segment).as_deref() {
+            Some("") | Some(".") => {}
+            Some("..") => depth = depth.saturating_sub(1),
+            _ => depth += 1,
+        }
+    }
+    depth
+}
+
+/// The request's path, percent-decoded, with `.` and `..` segments
+/// resolved and empty segments removed, so `/static/./css//../site.css`
+/// becomes `/static/site.css`. A trailing `/` is kept. This is the path a
+/// static file server should use, as what's left can't climb out of the
+/// directory being served: a `..` which would go above the root fails with
+/// `PathError::Traversal`. The path is split into segments before they're
+/// decoded, so `..%2F..` is one segment, not two - and as it would become a
+/// real separator if the result were used as a file path, a segment with
+/// an escaped `/` or `\` fails with `PathError::EncodedSeparator`.
+pub fn normalize_path(r: &Request) -> Result<String, PathError> {
+    let path = r.uri().path();
+    let mut segments: Vec<String> = Vec::new();
+    for segment in path.split('/') {
+        let segment = percent_decode(segment).ok_or(PathError::BadEncoding)?;
+        if segment.contains(&['/', '\\'][..]) {
+            return Err(PathError::EncodedSeparator);
+        }
+        match segment.as_str() {
+            "" | "." => {}
+            ".." => {
+                segments.pop().ok_or(PathError::Traversal)?;
+            }
+            _ => segments.push(segment),
+        }
+    }
+    let mut result = String::with_capacity(path.len());
+    for segment in &segments {
+        result.push('/');
+        result.push_str(segment);
+    }
+    if result.is_empty() || path.ends_with('/') {
+        result.push('/');
+    }
+    Ok(result)
+}
+
+/// Write a request back out to `sink` (say, to forward it upstream): the
+/// request line, the headers in `HeaderMap` order and the blank line which
+/// ends the header block. Returns the number of octets written. Parsing the
+/// result gives an equivalent request, although the headers may come out in
+/// a different order.
+pub fn serialize_request<W: io::Write>(r: &Request, sink: &mut W) -> io::Result<usize> {
+    let line = format!("{} {} {}\r\n", r.method(), r.uri(), version_str(r.version()));
+    sink.write_all(line.as_bytes())?;
+    let mut total = line.len();
+    for (k, v) in r.headers() {
+        for part in &[k.as_str().as_bytes(), b": ", v.as_bytes(), b"\r\n"] {
+            sink.write_all(part)?;
+            total += part.len();
+        }
+    }
+    sink.write_all(b"\r\n")?;
+    Ok(total + 2)
+}
+
+/// A compact, one-line description of a request for logging, such as
+/// `GET /index.html HTTP/1.1 [3 headers]`.
+pub fn summarize(r: &Request) -> String {
+    let count = r.headers().len();
+    format!("{} {} {} [{} header{}]",
+            r.method(),
+            r.uri(),
+            version_str(r.version()),
+            count,
+            if count == 1 { "" } else { "s" })
+}
+
+/// An access log line in Common Log Format, e.g.
+/// `127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] "GET / HTTP/1.1" 200 2326`,
+/// for a request from `client` answered with `status` and a body of `bytes`
+/// octets (logged as `-` if unknown) at time `when`. Times are in UTC.
+pub fn log_line(r: &Request,
+                client: IpAddr,
+                status: u16,
+                bytes: Option<u64>,
+                when: SystemTime)
+                -> String {
+    let bytes = match bytes {
+        Some(b) => b.to_string(),
+        None => "-".to_string(),
+    };
+    format!("{} - - [{}] \"{} {} {}\" {} {}",
+            client,
+            clf_date(when),
+            r.method(),
+            r.uri(),
+            version_str(r.version()),
+            status,
+            bytes)
+}
+
+/// Read a request from `reader`, returning it along with any octets read
+/// after the end of the header block (the start of the body, or of the
+/// next request). Parse failures come back as `io::ErrorKind::InvalidData`
+/// errors wrapping a `ParseError`.
+///
+/// If `ttfb_timeout` is set and the first read takes longer than that to
+/// produce anything, we give up with `ParseError::Timeout` (as
+/// `io::ErrorKind::TimedOut`) without parsing what it returned - a client
+/// that connects and then sits there is probably up to no good. This can
+/// only tell how long the read took once it returns, so for a socket you
+/// should also set a read timeout; a read which times out counts too.
+pub fn read_request_from<R: io::Read>(reader: &mut R,
+                                      ttfb_timeout: Option<Duration>)
+                                      -> io::Result<(Request, Vec<u8>)> {
+    read_request_until(reader, &mut Parser::new(), &[], ttfb_timeout, None)?
+        .ok_or_else(|| ParseError::UnexpectedEof.into())
+}
+
+/// Like `read_request_from`, but the whole header block must arrive within
+/// `header_deadline` of us starting, or we give up with
+/// `ParseError::Timeout` (as `io::ErrorKind::TimedOut`). That stops a
+/// slowloris client holding the connection open by dribbling out headers,
+/// each read arriving well within the socket's read timeout. The deadline
+/// is over once the header block is complete - the body can take as long
+/// as whatever reads it allows. As with `ttfb_timeout`, we only find out
+/// once a read returns, so set a read timeout on a socket too.
+pub fn read_request_with_deadline<R: io::Read>(reader: &mut R,
+                                               ttfb_timeout: Option<Duration>,
+                                               header_deadline: Duration)
+                                               -> io::Result<(Request, Vec<u8>)> {
+    read_request_until(reader, &mut Parser::new(), &[], ttfb_timeout, Some(header_deadline))?
+        .ok_or_else(|| ParseError::UnexpectedEof.into())
+}
+
+/// Parse a request which is entirely contained in `buffer`, in one go. The
+/// result borrows from `buffer`, so apart from the list of headers nothing
+/// is allocated. That's quicker than a `Parser`, but there's no partial
+/// parsing - if the header block isn't all there you get `Incomplete` and
+/// have to start again. Folded (obs-fold) headers can't be represented
+/// without copying, so they are rejected with `ParseError::ObsFold`.
+pub fn parse_once<'b>(buffer: &'b [u8]) -> OnceResult<'b> {
+    split_request(buffer, false)
+}
+
+impl<'b> BorrowedRequest<'b> {
+    /// The value of the first header called `name` (ignoring case).
+    pub fn header(&self, name: &str) -> Option<&'b [u8]> {
+        self.headers.iter().find(|h| h.0.eq_ignore_ascii_case(name)).map(|h| h.1)
+    }
+}
+
+impl ParsedRequest {
+    /// Wrap a request from the parser, which has no body (or whose body you
+    /// haven't read).
+    pub fn new(request: Request) -> ParsedRequest {
+        ParsedRequest::with_body(request, Vec::new())
+    }
+
+    /// Wrap a request from the parser along with its body, as read by
+    /// `body::read_body` or `body::read_chunked_body`.
+    pub fn with_body(request: Request, body: Vec<u8>) -> ParsedRequest {
+        ParsedRequest { request, body }
+    }
+
+    /// The method, e.g. `GET`.
+    pub fn method(&self) -> &str {
+        self.request.method().as_str()
+    }
+
+    /// The path of the request target, still percent-encoded, e.g.
+    /// `/search`. See `decoded_path` and `normalize_path` for a decoded one.
+    pub fn path(&self) -> &str {
+        self.request.uri().path()
+    }
+
+    /// The query string, without the `?`, if there is one.
+    pub fn query(&self) -> Option<&str> {
+        self.request.uri().query()
+    }
+
+    /// The protocol version, e.g. `HTTP/1.1`.
+    pub fn version(&self) -> &'static str {
+        version_str(self.request.version())
+    }
+
+    /// The value of the first header called `name` (ignoring case), if
+    /// there is one and it's valid UTF-8.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.request.headers().get(name).and_then(|v| v.to_str().ok())
+    }
+
+    /// The body, which is empty if there isn't one (or it wasn't read).
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// The underlying `http::Request`, for anything the accessors don't
+    /// cover. The body is dropped.
+    pub fn into_inner(self) -> Request {
+        self.request
+    }
+}
+
+impl From<Request> for ParsedRequest {
+    fn from(request: Request) -> ParsedRequest {
+        ParsedRequest::new(request)
+    }
+}
+
 impl Parser {
     /// Ensures a default Parser can be created and that it has the correct
     /// starting values for a parse.
@@ -125,18 +844,378 @@ impl Parser {
             headers: Vec::new(),
             builder: http::request::Builder::new(),
             key: String::new(),
+            headers_hook: None,
+            large_value_hook: None,
+            leading_empty_lines: 0,
+            fold_lines: 0,
+            max_fold_lines: DEFAULT_MAX_FOLD_LINES,
+            max_header_count: None,
+            raw_request_line: None,
+            trailers: Vec::new(),
+            trailer_count: 0,
+            casing: Casing::AsReceived,
+            fast_path: false,
+            lenient_request_line: false,
+            reject_bodiless_bodies: false,
+            reject_unsupported_codings: false,
+            connect: false,
+            value_encoding: HeaderValueEncoding::Raw,
+            arena: Vec::new(),
+            body_remaining: None,
+            max_total_bytes: None,
+            total_bytes: 0,
+        }
+    }
+
+    /// Like `new`, but pre-allocates room for `temp_cap` octets of any one
+    /// token (method, URL, header name or value) and for `headers_cap`
+    /// headers, so a typical request parses without the parser's buffers
+    /// having to grow. Use `reset` to re-use the parser and its buffers.
+    pub fn with_capacity(temp_cap: usize, headers_cap: usize) -> Parser {
+        let mut parser = Parser::new();
+        parser.temp.reserve(temp_cap);
+        parser.headers.reserve(headers_cap);
+        parser
+    }
+
+    /// Get ready to parse another request. The parser's buffers keep their
+    /// capacity (except the `parse_owned` arena, which is freed) and any
+    /// options or hooks stay as they were.
+    pub fn reset(&mut self) {
+        self.state = ParseState::Method;
+        self.temp.clear();
+        self.headers.clear();
+        self.builder = http::request::Builder::new();
+        self.key.clear();
+        self.leading_empty_lines = 0;
+        self.fold_lines = 0;
+        self.connect = false;
+        if let Some(ref mut line) = self.raw_request_line {
+            line.clear();
+        }
+        self.trailers.clear();
+        self.trailer_count = 0;
+        self.arena = Vec::new();
+        self.body_remaining = None;
+        self.total_bytes = 0;
+        if let Some(ref mut hook) = self.large_value_hook {
+            hook.active = false;
+        }
+    }
+
+    /// Set how many continuation (obs-fold) lines any one header may have.
+    /// Without a limit, a client could fold a header over thousands of lines
+    /// to make us buffer a huge value. The default is 8.
+    pub fn max_fold_lines(&mut self, max: usize) -> &mut Parser {
+        self.max_fold_lines = max;
+        self
+    }
+
+    /// Set how many headers a request may have. The count is checked as
+    /// soon as each header starts, so a client sending thousands of headers
+    /// is turned away with `ErrorTooManyHeaders` at the first one over the
+    /// limit, rather than after we've buffered them all. Continuation
+    /// (obs-fold) lines don't count. There's no limit by default.
+    pub fn max_header_count(&mut self, max: usize) -> &mut Parser {
+        self.max_header_count = Some(max);
+        self
+    }
+
+    /// Set a budget for the whole request - the header block, the body and
+    /// any trailers together - so you can say "nothing over 10 MB" in one
+    /// place. Octets taken by `parse` and `parse_trailers` count against
+    /// it, as do those reported with `body_received`, and going over gives
+    /// `ErrorRequestTooLarge`. A `Content-Length` body which wouldn't fit
+    /// is refused as soon as the headers are parsed, before it's read.
+    /// `read_chunked_body` won't read past the budget either. The count
+    /// starts again on `reset`. There's no limit by default.
+    pub fn max_total_bytes(&mut self, max: usize) -> &mut Parser {
+        self.max_total_bytes = Some(max);
+        self
+    }
+
+    /// How many more octets the current request may have before it goes
+    /// over `max_total_bytes`, or `None` if there's no limit.
+    pub fn bytes_remaining(&self) -> Option<usize> {
+        self.max_total_bytes.map(|max| max.saturating_sub(self.total_bytes))
+    }
+
+    /// Reject GET, HEAD and DELETE requests which have a body (that is, a
+    /// `Transfer-Encoding` header or a non-zero `Content-Length`) with
+    /// `ErrorUnexpectedBody`. RFC 7230 allows them, but a body nobody
+    /// expects is a favourite tool for request smuggling, so many servers
+    /// refuse. Off by default.
+    pub fn reject_body_on_bodiless_methods(&mut self, reject: bool) -> &mut Parser {
+        self.reject_bodiless_bodies = reject;
+        self
+    }
+
+    /// Reject requests whose `Transfer-Encoding` includes any coding other
+    /// than `chunked` and `identity` (such as `gzip`) with
+    /// `ErrorUnsupportedTransferEncoding`, for which RFC 7230 section 3.3.1
+    /// says to send `501 Not Implemented` (see `ParseError::as_status`).
+    /// Nothing in this crate can undo those codings, so without this such a
+    /// request fails with the less helpful `ErrorBadTransferEncoding`, or
+    /// gets through with its body still encoded. Off by default.
+    pub fn reject_unsupported_transfer_codings(&mut self, reject: bool) -> &mut Parser {
+        self.reject_unsupported_codings = reject;
+        self
+    }
+
+    /// Set what happens to header values which aren't valid UTF-8. This
+    /// applies to the parsed request and the raw header list alike. The
+    /// default is `HeaderValueEncoding::Raw`.
+    pub fn header_value_encoding(&mut self, encoding: HeaderValueEncoding) -> &mut Parser {
+        self.value_encoding = encoding;
+        self
+    }
+
+    /// Set how header names are stored in the raw header list (see
+    /// `into_raw_headers`, `trailers` and `on_headers_complete`). The
+    /// default is `Casing::AsReceived`.
+    pub fn canonicalize_header_names(&mut self, casing: Casing) -> &mut Parser {
+        self.casing = casing;
+        self
+    }
+
+    /// Keep a verbatim copy of the request line (e.g. `GET / HTTP/1.1`,
+    /// without the line ending) for logging. Off by default, as it costs a
+    /// copy. See `raw_request_line`.
+    pub fn keep_raw_request_line(&mut self, keep: bool) -> &mut Parser {
+        self.raw_request_line = if keep { Some(Vec::new()) } else { None };
+        self
+    }
+
+    /// The request line exactly as the client sent it, once it has been
+    /// parsed. Always `None` unless `keep_raw_request_line` was turned on.
+    pub fn raw_request_line(&self) -> Option<&str> {
+        match self.state {
+            ParseState::Method |
+            ParseState::LeadingEOL |
+            ParseState::Url |
+            ParseState::Protocol => None,
+            _ => self.raw_request_line.as_ref().and_then(|l| str::from_utf8(l).ok()),
+        }
+    }
+
+    /// Parse just the request line (e.g. `GET /index.html HTTP/1.1`) at the
+    /// start of `buffer`, stopping at its line ending. The headers are left
+    /// untouched, so this is a cheap way to peek at a request for logging or
+    /// filtering. The caller can then hand the whole buffer to a `Parser` if
+    /// they want the rest.
+    pub fn parse_request_line(buffer: &[u8]) -> RequestLineResult {
+        let mut parser = Parser::new();
+        for i in 0..buffer.len() {
+            // Feed one octet at a time, so we can stop as soon as the
+            // request line is done.
+            let r = parser.parse(&buffer[i..i + 1]);
+            if let Some(e) = r.error() {
+                return RequestLineResult::Error(e);
+            }
+            if parser.state == ParseState::KeyStart {
+                return match parser.builder.body(()) {
+                    Ok(r) => {
+                        RequestLineResult::Complete(r.method().clone(),
+                                                    r.uri().clone(),
+                                                    r.version(),
+                                                    i + 1)
+                    }
+                    Err(_) => RequestLineResult::Error(ParseError::Malformed),
+                };
+            }
+        }
+        RequestLineResult::InProgress
+    }
+
+    /// Install a hook which is called once the header block has been read,
+    /// but before the request is built. It gets to inspect the headers (in
+    /// the order received) and may reject the request - say, because it is
+    /// missing an `Authorization` header - before any body is read.
+    pub fn on_headers_complete<F>(&mut self, hook: F) -> &mut Parser
+        where F: FnMut(&[RawHeader]) -> HeaderDecision + Send + 'static
+    {
+        self.headers_hook = Some(HeadersHook(Box::new(hook)));
+        self
+    }
+
+    /// Install a hook for header values too big to want in memory, such as
+    /// huge JWT or SAML assertions a proxy needs to pass on. Once a value
+    /// (counting any folded continuation lines) reaches `threshold` octets,
+    /// the parser stops buffering it and instead calls `hook` with the
+    /// header name and each piece of the value (of at most `threshold`
+    /// octets) as it arrives, so the parser never holds more than
+    /// `threshold` octets of it. Values under the threshold are buffered as
+    /// normal. A streamed header still appears in the request,
+    /// but with an empty value. Only works with `parse`.
+    pub fn on_large_header_value<F>(&mut self, threshold: usize, hook: F) -> &mut Parser
+        where F: FnMut(&str, &[u8]) + Send + 'static
+    {
+        assert!(threshold > 0, "the large header value threshold must be positive");
+        self.large_value_hook = Some(LargeValueHook {
+            threshold,
+            callback: Box::new(hook),
+            active: false,
+        });
+        self
+    }
+
+    /// An alternative to `parse` which avoids copying the request. The
+    /// parser takes ownership of each `buffer` you give it, keeping them end
+    /// to end in an internal arena, and the request it returns borrows from
+    /// that arena - so it stays valid for as long as you don't touch the
+    /// parser again. If the header block isn't all there yet you get
+    /// `OnceResult::Incomplete`; call again with the next buffer. Anything
+    /// after the header block is left in the arena, and the count in
+    /// `OnceResult::Complete` tells you where the body starts. Call `reset`
+    /// before parsing another request, which frees the arena.
+    ///
+    /// Each buffer is run through the state machine just as `parse` would
+    /// run it, so all the parser's options and limits apply, and the whole
+    /// arena counts against `max_total_bytes`. Once the header block is
+    /// complete it's split up in place, like `parse_once`, so folded
+    /// (obs-fold) headers are rejected and header names are as received,
+    /// whatever `header_casing` says. Don't mix it with `parse` on the same
+    /// request.
+    pub fn parse_owned(&mut self, buffer: Vec<u8>) -> OnceResult<'_> {
+        let start = self.arena.len();
+        if matches!(self.max_total_bytes, Some(max) if start.saturating_add(buffer.len()) > max) {
+            return OnceResult::Error(ParseError::RequestTooLarge);
+        }
+        if self.arena.is_empty() {
+            self.arena = buffer;
+        } else {
+            self.arena.extend_from_slice(&buffer);
+        }
+        let arena = mem::take(&mut self.arena);
+        let result = self.parse(&arena[start..]).into_result();
+        self.arena = arena;
+        match result {
+            Ok(_) => split_request(&self.arena, self.lenient_request_line),
+            Err(ParseError::Incomplete) => OnceResult::Incomplete,
+            Err(e) => OnceResult::Error(e),
+        }
+    }
+
+    /// How many more octets of body we expect, so an event loop can size
+    /// its next read. This is `None` until `parse` has returned `Complete`,
+    /// and for chunked bodies, whose length we can't know in advance.
+    /// Otherwise it's the `Content-Length` (or zero, without one), less any
+    /// body octets which came in the buffer that completed the headers and
+    /// anything since reported with `body_received`.
+    pub fn bytes_needed(&self) -> Option<usize> {
+        self.body_remaining
+    }
+
+    /// Tell the parser we've read `count` more octets of body, so
+    /// `bytes_needed` and `bytes_remaining` stay accurate.
+    pub fn body_received(&mut self, count: usize) -> &mut Parser {
+        if let Some(ref mut remaining) = self.body_remaining {
+            *remaining = remaining.saturating_sub(count);
         }
+        self.total_bytes = self.total_bytes.saturating_add(count);
+        self
+    }
+
+    /// How many header fields we've collected for the current request so
+    /// far. Continuation lines don't count, as they're part of the field
+    /// before them.
+    pub fn headers_len(&self) -> usize {
+        self.headers.len()
+    }
+
+    /// The `parse_owned` arena, which starts with the request's header block
+    /// and carries on with whatever followed it.
+    pub fn arena(&self) -> &[u8] {
+        &self.arena
+    }
+
+    /// Turn on bulk scanning. Rather than running the state machine for
+    /// every octet of a URL, header name or header value, the parser looks
+    /// ahead for the octet which ends it and copies everything before that
+    /// in one go. The results are exactly the same either way, but requests
+    /// with lots of long headers parse noticeably quicker. Off by default.
+    pub fn fast_path(&mut self, enabled: bool) -> &mut Parser {
+        self.fast_path = enabled;
+        self
+    }
+
+    /// Allow more than one space between the method, URL and protocol of
+    /// the request line, as sent by some non-conforming clients. RFC 7230
+    /// section 3.1.1 says a server SHOULD reject these, so by default we do
+    /// (usually with `ErrorBadURL`, as the second space looks like an empty
+    /// URL). Only spaces are collapsed, not tabs.
+    pub fn lenient_request_line(&mut self, enabled: bool) -> &mut Parser {
+        self.lenient_request_line = enabled;
+        self
     }
 
     /// Perform the HTTP parse.
     /// This reads the buffer octet by octet, collating strings into
     /// temporary vectors. If any sort of error occurs, we bail out.
+    ///
+    /// Parsing stops at the blank line which ends the header block, and the
+    /// count in `Complete` runs up to and including it. Anything after that,
+    /// even more blank lines, is left for you: it's the body if the request
+    /// has one, or else the start of the next request, which skips a few
+    /// stray blank lines before its request line.
     pub fn parse(&mut self, buffer: &[u8]) -> ParseResult {
+        let result = match self.parse_buffer(buffer) {
+            ParseResult::InProgress => {
+                self.total_bytes = self.total_bytes.saturating_add(buffer.len());
+                if self.within_budget(0) {
+                    ParseResult::InProgress
+                } else {
+                    ParseResult::ErrorRequestTooLarge
+                }
+            }
+            ParseResult::Complete(r, used) => {
+                self.total_bytes = self.total_bytes.saturating_add(used);
+                let body = if is_chunked(&r) { 0 } else { get_content_length(&r).unwrap_or(0) };
+                if self.within_budget(body) {
+                    ParseResult::Complete(r, used)
+                } else {
+                    ParseResult::ErrorRequestTooLarge
+                }
+            }
+            e => e,
+        };
+        match result {
+            ParseResult::Complete(ref r, used) => {
+                trace!("rushttp: parsed {} {} ({} octets)", r.method(), r.uri(), used)
+            }
+            ParseResult::InProgress => {}
+            ref e => debug!("rushttp: parse failed in {:?}: {:?}", self.state, e),
+        }
+        result
+    }
+
+    /// `parse` for text, which saves writing `b"..."` or `.as_bytes()` in
+    /// tests. It only takes UTF-8, of course, so use `parse` for anything
+    /// with obs-text (octets 0x80-0xFF) that isn't.
+    pub fn parse_str(&mut self, s: &str) -> ParseResult {
+        self.parse(s.as_bytes())
+    }
+
+    /// Do the work for `parse`.
+    fn parse_buffer(&mut self, buffer: &[u8]) -> ParseResult {
         let mut read = 0;
-        for b in buffer {
-            let c = *b;
-            read = read + 1;
+        while read < buffer.len() {
+            if self.fast_path {
+                read += self.scan_run(&buffer[read..]);
+                if read == buffer.len() {
+                    break;
+                }
+            }
+            let c = buffer[read];
+            read += 1;
             let ct = get_char_type(c);
+            if let Some(ref mut line) = self.raw_request_line {
+                let in_line = matches!(self.state,
+                                       ParseState::Method | ParseState::Url | ParseState::Protocol);
+                if in_line && !matches!(ct, CharType::CR | CharType::LF) {
+                    line.push(c);
+                }
+            }
             // switch on state, then switch on char type
             match self.state {
                 ParseState::Method => {
@@ -144,50 +1223,77 @@ impl Parser {
                         CharType::Other => self.temp.push(c),
                         CharType::Space => {
                             match http::Method::from_bytes(&self.temp) {
-                                Ok(s) => self.builder.method(s),
+                                Ok(s) => {
+                                    self.connect = s == http::Method::CONNECT;
+                                    self.builder.method(s)
+                                }
                                 Err(_) => return ParseResult::ErrorBadMethod,
                             };
                             self.temp.clear();
-                            self.state = ParseState::URL
+                            self.state = ParseState::Url
+                        }
+                        // RFC 7230 section 3.5 says we should ignore at
+                        // least one empty line before the request line.
+                        CharType::CR if self.temp.is_empty() => self.state = ParseState::LeadingEOL,
+                        CharType::LF if self.temp.is_empty() => {
+                            if !self.skip_leading_empty_line() {
+                                return ParseResult::Error;
+                            }
+                        }
+                        CharType::Colon | CharType::CR | CharType::LF => {
+                            return ParseResult::ErrorBadMethod
+                        }
+                    }
+                }
+                ParseState::LeadingEOL => {
+                    match ct {
+                        CharType::LF => {
+                            if !self.skip_leading_empty_line() {
+                                return ParseResult::Error;
+                            }
+                            self.state = ParseState::Method
                         }
-                        CharType::Colon | CharType::CR | CharType::LF => return ParseResult::Error,
+                        _ => return ParseResult::Error,
                     }
                 }
-                ParseState::URL => {
+                ParseState::Url => {
                     match ct {
+                        // A tab isn't a separator here, and no control
+                        // character (or DEL) may appear in a URL. Nor may
+                        // a fragment, which is for the client alone.
+                        _ if is_bad_url_octet(c) => return ParseResult::ErrorBadURL,
                         CharType::Other | CharType::Colon => self.temp.push(c),
+                        CharType::Space if self.extra_space(c) => {}
                         CharType::Space => {
+                            // CONNECT takes a bare host:port and nothing else
+                            if self.connect &&
+                               str::from_utf8(&self.temp).ok().and_then(authority_target).is_none() {
+                                return ParseResult::ErrorBadURL;
+                            }
                             match http::Uri::from_shared(self.temp.split_off(0).into()) {
                                 Ok(s) => self.builder.uri(s),
                                 Err(_) => return ParseResult::ErrorBadURL,
                             };
                             self.state = ParseState::Protocol
                         }
-                        CharType::CR | CharType::LF => return ParseResult::Error,
+                        // The request line ended without a protocol
+                        CharType::CR | CharType::LF => return ParseResult::ErrorBadProtocol,
                     }
                 }
                 ParseState::Protocol => {
                     match ct {
                         CharType::Other => self.temp.push(c),
-                        CharType::CR => {
-                            match str::from_utf8(&self.temp) {
-                                Ok("HTTP/1.0") => self.builder.version(http::Version::HTTP_10),
-                                Ok("HTTP/1.1") => self.builder.version(http::Version::HTTP_11),
-                                Ok(_) => return ParseResult::ErrorBadProtocol,
-                                Err(_) => return ParseResult::ErrorBadProtocol,
+                        CharType::Space if self.extra_space(c) => {}
+                        CharType::CR | CharType::LF => {
+                            match parse_version(&self.temp) {
+                                Ok(v) => self.builder.version(v),
+                                Err(e) => return e.into(),
                             };
                             self.temp.clear();
-                            self.state = ParseState::ProtocolEOL
-                        }
-                        CharType::LF => {
-                            match str::from_utf8(&self.temp) {
-                                Ok("HTTP/1.0") => self.builder.version(http::Version::HTTP_10),
-                                Ok("HTTP/1.1") => self.builder.version(http::Version::HTTP_11),
-                                Ok(_) => return ParseResult::ErrorBadProtocol,
-                                Err(_) => return ParseResult::ErrorBadProtocol,
-                            };
-                            self.temp.clear();
-                            self.state = ParseState::KeyStart
+                            self.state = match ct {
+                                CharType::CR => ParseState::ProtocolEOL,
+                                _ => ParseState::KeyStart,
+                            }
                         }
                         CharType::Space | CharType::Colon => return ParseResult::ErrorBadProtocol,
                     }
@@ -195,24 +1301,38 @@ impl Parser {
                 ParseState::ProtocolEOL => {
                     match ct {
                         CharType::LF => self.state = ParseState::KeyStart,
-                        _ => return ParseResult::Error,
+                        _ => return ParseResult::ErrorBadProtocol,
                     }
                 }
                 ParseState::KeyStart => {
                     match ct {
-                        CharType::Space => self.state = ParseState::WrappedValueStart,
-                        CharType::LF => {
-                            match self.build_request() {
-                                Ok(s) => return ParseResult::Complete(s, read),
-                                Err(_) => return ParseResult::Error,
+                        // Whitespace (a space or a tab) at the start of a
+                        // line is always a continuation (obs-fold) of the
+                        // previous header's value - it can't start a name.
+                        CharType::Space => {
+                            self.fold_lines += 1;
+                            if self.fold_lines > self.max_fold_lines {
+                                return ParseResult::ErrorObsFold;
                             }
+                            self.state = ParseState::WrappedValueStart
+                        }
+                        CharType::LF => {
+                            return self.complete(read, buffer.len());
                         }
                         CharType::CR => self.state = ParseState::FinalEOL,
                         CharType::Other => {
+                            let count = self.headers.len();
+                            if matches!(self.max_header_count, Some(max) if count >= max) {
+                                return ParseResult::ErrorTooManyHeaders;
+                            }
                             self.temp.push(c);
+                            self.fold_lines = 0;
+                            if let Some(ref mut hook) = self.large_value_hook {
+                                hook.active = false;
+                            }
                             self.state = ParseState::Key
                         }
-                        CharType::Colon => return ParseResult::Error,
+                        CharType::Colon => return ParseResult::ErrorBadHeader,
                     }
                 }
                 ParseState::Key => {
@@ -220,33 +1340,52 @@ impl Parser {
                         CharType::Other => self.temp.push(c),
                         CharType::Colon => {
                             match String::from_utf8(self.temp.split_off(0)) {
-                                Ok(s) => self.key = s,
+                                Ok(s) => self.key = self.casing.apply(s),
                                 Err(_) => return ParseResult::ErrorBadHeader,
                             }
                             self.state = ParseState::ValueStart
                         }
-                        CharType::Space | CharType::LF | CharType::CR => return ParseResult::Error,
+                        CharType::Space | CharType::LF | CharType::CR => {
+                            return ParseResult::ErrorBadHeader
+                        }
                     }
                 }
                 ParseState::ValueStart => {
                     match ct {
+                        // Skip the optional whitespace before the value
                         CharType::Space => {}
                         CharType::Other => {
                             self.temp.push(c);
                             self.state = ParseState::Value
                         }
-                        CharType::LF | CharType::CR | CharType::Colon => return ParseResult::Error,
+                        // The line ended before any value, which is fine -
+                        // the value is the empty string
+                        CharType::CR => {
+                            self.headers.push((self.key.clone(), Vec::new()));
+                            self.state = ParseState::ValueEOL
+                        }
+                        CharType::LF => {
+                            self.headers.push((self.key.clone(), Vec::new()));
+                            self.state = ParseState::KeyStart
+                        }
+                        CharType::Colon => return ParseResult::ErrorBadHeaderValue,
                     }
                 }
                 ParseState::Value => {
                     match ct {
-                        CharType::Other | CharType::Space | CharType::Colon => self.temp.push(c),
+                        // Spaces and tabs inside the value are kept verbatim
+                        CharType::Other | CharType::Space | CharType::Colon => {
+                            self.temp.push(c);
+                            self.stream_value(false);
+                        }
                         CharType::CR => {
+                            self.stream_value(true);
                             let hdr = (self.key.clone(), self.temp.split_off(0));
                             self.headers.push(hdr);
                             self.state = ParseState::ValueEOL
                         }
                         CharType::LF => {
+                            self.stream_value(true);
                             let hdr = (self.key.clone(), self.temp.split_off(0));
                             self.headers.push(hdr);
                             self.state = ParseState::KeyStart
@@ -256,49 +1395,56 @@ impl Parser {
                 ParseState::ValueEOL => {
                     match ct {
                         CharType::LF => self.state = ParseState::KeyStart,
-                        _ => return ParseResult::Error,
+                        // A CR which isn't part of a CRLF can't appear in a
+                        // value, and letting it through invites request
+                        // smuggling via peers which treat it as a line end.
+                        _ => return ParseResult::ErrorBadHeaderValue,
                     }
                 }
                 ParseState::WrappedValueStart => {
                     match ct {
+                        // The fold and any whitespace around it become a
+                        // single space
                         CharType::Space => {}
                         CharType::Other | CharType::Colon => {
-                            self.temp.push(0x20); // single space
+                            self.temp.push(b' ');
                             self.temp.push(c);
                             self.state = ParseState::WrappedValue
                         }
                         CharType::CR => self.state = ParseState::WrappedValueEOL,
-                        CharType::LF => return ParseResult::Error,
+                        CharType::LF => return ParseResult::ErrorBadHeaderValue,
                     }
                 }
                 ParseState::WrappedValue => {
                     match ct {
-                        CharType::Other | CharType::Colon | CharType::Space => self.temp.push(c),
+                        CharType::Other | CharType::Colon | CharType::Space => {
+                            self.temp.push(c);
+                            self.stream_value(false);
+                        }
                         CharType::CR => {
+                            self.stream_value(true);
                             match self.headers.last_mut() {
                                 Some(x) => x.1.append(&mut self.temp),
-                                None => return ParseResult::Error,
+                                // There's no header for this line to continue
+                                None => return ParseResult::ErrorBadHeader,
                             }
                             self.state = ParseState::WrappedValueEOL
                         }
-                        CharType::LF => return ParseResult::Error,
+                        CharType::LF => return ParseResult::ErrorBadHeaderValue,
                     }
                 }
                 ParseState::WrappedValueEOL => {
                     match ct {
                         CharType::LF => self.state = ParseState::KeyStart,
-                        _ => return ParseResult::Error,
+                        _ => return ParseResult::ErrorBadHeaderValue,
                     }
                 }
                 ParseState::FinalEOL => {
                     match ct {
                         CharType::LF => {
-                            match self.build_request() {
-                                Ok(s) => return ParseResult::Complete(s, read),
-                                Err(_) => return ParseResult::Error,
-                            }
+                            return self.complete(read, buffer.len());
                         }
-                        _ => return ParseResult::Error,
+                        _ => return ParseResult::ErrorBadHeader,
                     }
                 }
             }
@@ -306,15 +1452,479 @@ impl Parser {
         ParseResult::InProgress
     }
 
-    fn build_request(&mut self) -> Result<Request, ParseResult> {
-        for (k, v) in self.headers.drain(..) {
+    /// Tell the parser that the input has ended (e.g. the client closed the
+    /// connection) after `parse` returned `InProgress`. That's normally an
+    /// `ErrorUnexpectedEof`, but as some HTTP/1.0 clients close the
+    /// connection instead of sending the blank line which ends the header
+    /// block, a request which is otherwise whole is accepted for HTTP/1.0.
+    /// In that case the `Complete` result reports zero octets taken.
+    pub fn finish(&mut self) -> ParseResult {
+        match self.state {
+            ParseState::KeyStart | ParseState::FinalEOL => {}
+            _ => return ParseResult::ErrorUnexpectedEof,
+        }
+        match self.complete(0, 0) {
+            ParseResult::Complete(r, n) => {
+                if r.version() == http::Version::HTTP_10 {
+                    ParseResult::Complete(r, n)
+                } else {
+                    ParseResult::ErrorUnexpectedEof
+                }
+            }
+            e => e,
+        }
+    }
+
+    /// Parse the trailer section which follows the last (zero-length) chunk
+    /// of a chunked request body, once `parse` has returned the request.
+    /// Like the header block, it ends with a blank line. Only fields which
+    /// the client announced in the request's `Trailer` header are kept -
+    /// anything else is silently ignored, as the recipient may not merge
+    /// unannounced trailers into the request. Folded lines aren't allowed.
+    /// You'd normally let `body::read_chunked_body` call this for you.
+    ///
+    /// A trailer line longer than 8 KiB gives `RequestTooLarge`, and more
+    /// trailer fields (announced or not) than `max_header_count` allows
+    /// gives `TooManyHeaders`.
+    pub fn parse_trailers(&mut self, buffer: &[u8]) -> TrailersResult {
+        for (i, &c) in buffer.iter().enumerate() {
+            self.total_bytes = self.total_bytes.saturating_add(1);
+            if !self.within_budget(0) {
+                return TrailersResult::Error(ParseError::RequestTooLarge);
+            }
+            if c != b'\n' {
+                // Allow for the CR before the LF
+                if self.temp.len() > MAX_TRAILER_LINE {
+                    return TrailersResult::Error(ParseError::RequestTooLarge);
+                }
+                self.temp.push(c);
+                continue;
+            }
+            if self.temp.last() == Some(&b'\r') {
+                self.temp.pop();
+            }
+            if self.temp.is_empty() {
+                return TrailersResult::Complete(i + 1);
+            }
+            self.trailer_count += 1;
+            if let Some(max) = self.max_header_count {
+                if self.trailer_count > max {
+                    return TrailersResult::Error(ParseError::TooManyHeaders);
+                }
+            }
+            let trailer = match parse_once_header(&self.temp) {
+                Ok((name, value)) => (self.casing.apply(name.to_string()), value.to_vec()),
+                Err(e) => return TrailersResult::Error(e),
+            };
+            if self.trailer_announced(&trailer.0) {
+                self.trailers.push(trailer);
+            }
+            self.temp.clear();
+        }
+        TrailersResult::InProgress
+    }
+
+    /// The trailers found by `parse_trailers`, in the order received.
+    pub fn trailers(&self) -> &[RawHeader] {
+        &self.trailers
+    }
+
+    /// Consume the parser and return the headers exactly as received - in
+    /// wire order, with duplicates kept as separate entries and the values
+    /// as raw bytes. Folded (continuation) lines have already been joined
+    /// onto the header they continue.
+    pub fn into_raw_headers(self) -> Vec<RawHeader> {
+        self.headers
+    }
+
+    /// How much room the temporary and header buffers have.
+    #[cfg(test)]
+    pub(crate) fn capacities(&self) -> (usize, usize) {
+        (self.temp.capacity(), self.headers.capacity())
+    }
+
+    /// Whether `c` is a space we can skip because it follows the one which
+    /// ended the previous request-line token, in lenient mode.
+    fn extra_space(&self, c: u8) -> bool {
+        self.lenient_request_line && c == b' ' && self.temp.is_empty()
+    }
+
+    /// For `fast_path`: copy the octets at the start of `rest` which the
+    /// current state would just append to `temp`, stopping at the first one
+    /// which needs the state machine. Returns how many were copied.
+    fn scan_run(&mut self, rest: &[u8]) -> usize {
+        let end = match self.state {
+            ParseState::Url => {
+                rest.iter()
+                    .position(|&c| c == b' ' || c == b'\r' || c == b'\n' || is_bad_url_octet(c))
+            }
+            ParseState::Key => {
+                rest.iter().position(|&c| !matches!(get_char_type(c), CharType::Other))
+            }
+            ParseState::Value | ParseState::WrappedValue => {
+                rest.iter().position(|&c| c == b'\r' || c == b'\n')
+            }
+            _ => return 0,
+        };
+        let mut run = &rest[..end.unwrap_or(rest.len())];
+        if let Some(ref hook) = self.large_value_hook {
+            if matches!(self.state, ParseState::Value | ParseState::WrappedValue) {
+                // Don't buffer more than the hook allows
+                let room = hook.threshold.saturating_sub(self.held_value_len());
+                run = &run[..run.len().min(room)];
+            }
+        }
+        self.temp.extend_from_slice(run);
+        if matches!(self.state, ParseState::Value | ParseState::WrappedValue) {
+            self.stream_value(false);
+        }
+        if self.state == ParseState::Url {
+            if let Some(ref mut line) = self.raw_request_line {
+                line.extend_from_slice(run);
+            }
+        }
+        run.len()
+    }
+
+    /// Hand the header value buffered so far to the large value hook, if
+    /// there is one and the value has got too big. Once a value is being
+    /// streamed, the rest of it goes the same way, so at the `end` of each
+    /// line we hand over whatever is left.
+    fn stream_value(&mut self, end: bool) {
+        let held = self.held_value_len();
+        if let Some(ref mut hook) = self.large_value_hook {
+            if held >= hook.threshold || (end && hook.active) {
+                if self.state == ParseState::WrappedValue {
+                    // The earlier lines go first
+                    if let Some(&mut (_, ref mut earlier)) = self.headers.last_mut() {
+                        if !earlier.is_empty() {
+                            (hook.callback)(&self.key, earlier);
+                            earlier.clear();
+                        }
+                    }
+                }
+                if !self.temp.is_empty() {
+                    (hook.callback)(&self.key, &self.temp);
+                    self.temp.clear();
+                }
+                hook.active = true;
+            }
+        }
+    }
+
+    /// How much of the current header value we're holding. For a folded
+    /// value, that's the earlier lines (already in the header they continue)
+    /// as well as this one.
+    fn held_value_len(&self) -> usize {
+        match self.state {
+            ParseState::WrappedValue => {
+                self.temp.len() + self.headers.last().map_or(0, |(_, v)| v.len())
+            }
+            _ => self.temp.len(),
+        }
+    }
+
+    /// Whether `name` was listed in a `Trailer` header.
+    fn trailer_announced(&self, name: &str) -> bool {
+        self.headers
+            .iter()
+            .filter(|&(k, _)| k.eq_ignore_ascii_case("Trailer"))
+            .filter_map(|(_, v)| str::from_utf8(v).ok())
+            .flat_map(|v| v.split(','))
+            .any(|t| t.trim().eq_ignore_ascii_case(name))
+    }
+
+    /// Count an empty line before the request line. Returns false if there
+    /// have been too many of them.
+    fn skip_leading_empty_line(&mut self) -> bool {
+        self.leading_empty_lines += 1;
+        self.leading_empty_lines <= MAX_LEADING_EMPTY_LINES
+    }
+
+    /// Whether `extra` more octets would still fit in `max_total_bytes`.
+    fn within_budget(&self, extra: usize) -> bool {
+        match self.max_total_bytes {
+            Some(max) => self.total_bytes.saturating_add(extra) <= max,
+            None => true,
+        }
+    }
+
+    /// We've seen the end of the headers, having read `read` of the
+    /// `available` octets in the buffer.
+    fn complete(&mut self, read: usize, available: usize) -> ParseResult {
+        match self.value_encoding {
+            HeaderValueEncoding::Raw => {}
+            HeaderValueEncoding::Strict => {
+                if self.headers.iter().any(|(_, v)| str::from_utf8(v).is_err()) {
+                    return ParseResult::ErrorBadHeaderValue;
+                }
+            }
+            HeaderValueEncoding::Lossy => {
+                for (_, v) in &mut self.headers {
+                    if str::from_utf8(v).is_err() {
+                        *v = String::from_utf8_lossy(v).into_owned().into_bytes();
+                    }
+                }
+            }
+        }
+        if let Some(ref mut hook) = self.headers_hook {
+            match (hook.0)(&self.headers) {
+                HeaderDecision::Continue => {}
+                // The header block is finished, so waiting for more input
+                // would wait forever
+                HeaderDecision::Reject(ParseError::Incomplete) => return ParseResult::Error,
+                HeaderDecision::Reject(e) => return e.into(),
+            }
+        }
+        let transfer_encodings = self.headers
+                                     .iter()
+                                     .filter(|(k, _)| k.eq_ignore_ascii_case("Transfer-Encoding"))
+                                     .map(|(_, v)| &v[..]);
+        if self.reject_unsupported_codings &&
+           has_unsupported_coding(transfer_encodings.clone()) {
+            return ParseResult::ErrorUnsupportedTransferEncoding;
+        }
+        if !valid_transfer_encoding(transfer_encodings) {
+            return ParseResult::ErrorBadTransferEncoding;
+        }
+        match self.build_request() {
+            Ok(ref s) if self.reject_bodiless_bodies && has_unexpected_body(s) => {
+                ParseResult::ErrorUnexpectedBody
+            }
+            Ok(s) => {
+                self.body_remaining = if is_chunked(&s) {
+                    None
+                } else {
+                    let length = get_content_length(&s).unwrap_or(0);
+                    Some(length.saturating_sub(available - read))
+                };
+                ParseResult::Complete(s, read)
+            }
+            Err(ref e) if e.is::<http::header::InvalidHeaderName>() => ParseResult::ErrorBadHeader,
+            Err(ref e) if e.is::<http::header::InvalidHeaderValue>() => {
+                ParseResult::ErrorBadHeaderValue
+            }
+            Err(_) => ParseResult::Error,
+        }
+    }
+
+    fn build_request(&mut self) -> Result<Request, http::Error> {
+        // We know how many headers there are, so make room for them all now
+        // rather than letting the map grow (and rehash) as we go
+        if let Some(map) = self.builder.headers_mut() {
+            map.reserve(self.headers.len());
+        }
+        for (k, v) in &self.headers {
             self.builder.header(&k[..], &v[..]);
         }
-        self.builder.body(()).map_err(|_| ParseResult::Error)
+        self.builder.body(())
+    }
+
+}
+
+impl ParseError {
+    /// The status to send back when a request fails with this error. A
+    /// method we don't recognise gets `501 Not Implemented` (RFC 7231
+    /// section 6.6.2) - not a 405, which would need an `Allow` header, and
+    /// only the application knows which methods it allows. A protocol we
+    /// can't make sense of or don't speak gets `505 HTTP Version Not
+    /// Supported`, and limits give the status for whichever limit it was.
+    /// Anything else is the client's fault, so `400 Bad Request`.
+    pub fn as_status(&self) -> HttpResponseStatus {
+        match *self {
+            ParseError::BadMethod => HttpResponseStatus::NotImplemented,
+            ParseError::BadProtocol |
+            ParseError::UnsupportedVersion => HttpResponseStatus::HTTPVersionNotSupported,
+            ParseError::Timeout => HttpResponseStatus::RequestTimeout,
+            ParseError::RequestTooLarge => HttpResponseStatus::PayloadTooLarge,
+            ParseError::TooManyHeaders => HttpResponseStatus::RequestHeaderFieldsTooLarge,
+            ParseError::UnsupportedTransferEncoding => HttpResponseStatus::NotImplemented,
+            ParseError::Malformed |
+            ParseError::BadHeader |
+            ParseError::BadHeaderValue |
+            ParseError::BadURL |
+            ParseError::ObsFold |
+            ParseError::UnexpectedEof |
+            ParseError::UnexpectedBody |
+            ParseError::BadTransferEncoding |
+            ParseError::Incomplete => HttpResponseStatus::BadRequest,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match *self {
+            ParseError::Malformed => "Malformed request",
+            ParseError::BadHeader => "Bad header name",
+            ParseError::BadHeaderValue => "Bad header value",
+            ParseError::BadMethod => "Bad method",
+            ParseError::BadProtocol => "Bad protocol",
+            ParseError::BadURL => "Bad URL",
+            ParseError::ObsFold => "Too many folded header lines",
+            ParseError::UnexpectedEof => "Request ended unexpectedly",
+            ParseError::Timeout => "Timed out waiting for the request",
+            ParseError::UnsupportedVersion => "Unsupported HTTP version",
+            ParseError::UnexpectedBody => "Unexpected request body",
+            ParseError::BadTransferEncoding => "Bad transfer encoding",
+            ParseError::RequestTooLarge => "Request too large",
+            ParseError::TooManyHeaders => "Too many headers",
+            ParseError::UnsupportedTransferEncoding => "Unsupported transfer encoding",
+            ParseError::Incomplete => "Incomplete request",
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match *self {
+            PathError::Traversal => "Path climbs above the root",
+            PathError::EncodedSeparator => "Path has an encoded separator",
+            PathError::BadEncoding => "Path is badly encoded",
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl error::Error for PathError {}
+
+/// Lets a parse error be returned with `?` from a function which returns
+/// `io::Result`, alongside errors from the socket. A timeout is
+/// `ErrorKind::TimedOut`, and everything else `ErrorKind::InvalidData`.
+impl From<ParseError> for io::Error {
+    fn from(e: ParseError) -> io::Error {
+        let kind = match e {
+            ParseError::Timeout => io::ErrorKind::TimedOut,
+            ParseError::Incomplete => io::ErrorKind::WouldBlock,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+impl From<ParseError> for ParseResult {
+    fn from(e: ParseError) -> ParseResult {
+        match e {
+            ParseError::Malformed => ParseResult::Error,
+            ParseError::BadHeader => ParseResult::ErrorBadHeader,
+            ParseError::BadHeaderValue => ParseResult::ErrorBadHeaderValue,
+            ParseError::BadMethod => ParseResult::ErrorBadMethod,
+            ParseError::BadProtocol => ParseResult::ErrorBadProtocol,
+            ParseError::BadURL => ParseResult::ErrorBadURL,
+            ParseError::ObsFold => ParseResult::ErrorObsFold,
+            ParseError::UnexpectedEof => ParseResult::ErrorUnexpectedEof,
+            ParseError::Timeout => ParseResult::ErrorTimeout,
+            ParseError::UnsupportedVersion => ParseResult::ErrorUnsupportedVersion,
+            ParseError::UnexpectedBody => ParseResult::ErrorUnexpectedBody,
+            ParseError::BadTransferEncoding => ParseResult::ErrorBadTransferEncoding,
+            ParseError::RequestTooLarge => ParseResult::ErrorRequestTooLarge,
+            ParseError::TooManyHeaders => ParseResult::ErrorTooManyHeaders,
+            ParseError::UnsupportedTransferEncoding => {
+                ParseResult::ErrorUnsupportedTransferEncoding
+            }
+            ParseError::Incomplete => ParseResult::InProgress,
+        }
+    }
+}
+
+impl ParseResult {
+    /// Whether we have a whole request.
+    pub fn is_complete(&self) -> bool {
+        matches!(*self, ParseResult::Complete(..))
+    }
+
+    /// Whether the parser needs more input.
+    pub fn is_in_progress(&self) -> bool {
+        matches!(*self, ParseResult::InProgress)
+    }
+
+    /// Whether the parse failed.
+    pub fn is_error(&self) -> bool {
+        self.error().is_some()
+    }
+
+    /// Turn this into a `Result`, so you can use `?`. The parser needing
+    /// more input isn't a failure, so `InProgress` becomes
+    /// `Err(ParseError::Incomplete)`, which you'll want to check for and
+    /// handle by reading some more.
+    pub fn into_result(self) -> Result<(Request, usize), ParseError> {
+        match self {
+            ParseResult::Complete(r, used) => Ok((r, used)),
+            ParseResult::InProgress => Err(ParseError::Incomplete),
+            e => Err(e.error().unwrap_or(ParseError::Malformed)),
+        }
     }
 
+    /// If this result is an error, which one.
+    fn error(&self) -> Option<ParseError> {
+        match *self {
+            ParseResult::Error => Some(ParseError::Malformed),
+            ParseResult::ErrorBadHeader => Some(ParseError::BadHeader),
+            ParseResult::ErrorBadHeaderValue => Some(ParseError::BadHeaderValue),
+            ParseResult::ErrorBadMethod => Some(ParseError::BadMethod),
+            ParseResult::ErrorBadProtocol => Some(ParseError::BadProtocol),
+            ParseResult::ErrorBadURL => Some(ParseError::BadURL),
+            ParseResult::ErrorObsFold => Some(ParseError::ObsFold),
+            ParseResult::ErrorUnexpectedEof => Some(ParseError::UnexpectedEof),
+            ParseResult::ErrorTimeout => Some(ParseError::Timeout),
+            ParseResult::ErrorUnsupportedVersion => Some(ParseError::UnsupportedVersion),
+            ParseResult::ErrorUnexpectedBody => Some(ParseError::UnexpectedBody),
+            ParseResult::ErrorBadTransferEncoding => Some(ParseError::BadTransferEncoding),
+            ParseResult::ErrorRequestTooLarge => Some(ParseError::RequestTooLarge),
+            ParseResult::ErrorTooManyHeaders => Some(ParseError::TooManyHeaders),
+            ParseResult::ErrorUnsupportedTransferEncoding => {
+                Some(ParseError::UnsupportedTransferEncoding)
+            }
+            ParseResult::InProgress | ParseResult::Complete(..) => None,
+        }
+    }
+}
+
+impl Casing {
+    /// Re-case a header name.
+    fn apply(self, mut name: String) -> String {
+        match self {
+            Casing::AsReceived => {}
+            Casing::Lowercase => name.make_ascii_lowercase(),
+            Casing::TitleCase => {
+                let mut start = true;
+                name = name.chars()
+                           .map(|c| {
+                               let c = if start {
+                                   c.to_ascii_uppercase()
+                               } else {
+                                   c.to_ascii_lowercase()
+                               };
+                               start = c == '-';
+                               c
+                           })
+                           .collect();
+            }
+        }
+        name
+    }
 }
 
+impl fmt::Debug for HeadersHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HeadersHook")
+    }
+}
+
+impl fmt::Debug for LargeValueHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LargeValueHook({})", self.threshold)
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Parser {
+        Parser::new()
+    }
+}
 
 // ****************************************************************************
 //
@@ -322,6 +1932,492 @@ impl Parser {
 //
 // ****************************************************************************
 
+/// Whether the comma-separated list in header(s) `name` contains `token`
+/// (ignoring case).
+fn header_has_token(r: &Request, name: &str, token: &str) -> bool {
+    r.headers()
+     .get_all(name)
+     .iter()
+     .filter_map(|v| v.to_str().ok())
+     .flat_map(|v| v.split(','))
+     .any(|t| t.trim().eq_ignore_ascii_case(token))
+}
+
+/// Whether `c` may appear in a multipart boundary (RFC 2046 section 5.1.1).
+fn is_bchar(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "'()+_,-./:=? ".contains(c)
+}
+
+/// Whether a method which doesn't normally have a body has one anyway.
+fn has_unexpected_body(r: &Request) -> bool {
+    let bodiless = matches!(*r.method(),
+                            http::Method::GET | http::Method::HEAD | http::Method::DELETE);
+    bodiless &&
+    (r.headers().contains_key("Transfer-Encoding") ||
+     r.headers().get_all("Content-Length").iter().any(|v| v != "0"))
+}
+
+/// Whether any of the `Transfer-Encoding` header values has a coding other
+/// than `chunked` or `identity`. Values which aren't UTF-8 are left for
+/// `valid_transfer_encoding` to reject.
+fn has_unsupported_coding<'v, I>(values: I) -> bool
+    where I: Iterator<Item = &'v [u8]>
+{
+    values.filter_map(|v| str::from_utf8(v).ok())
+          .flat_map(|v| v.split(','))
+          .map(|c| c.trim())
+          .filter(|c| !c.is_empty())
+          .any(|c| !c.eq_ignore_ascii_case("chunked") && !c.eq_ignore_ascii_case("identity"))
+}
+
+/// Check the `Transfer-Encoding` header values, if any, against the tricks
+/// used to smuggle a second request past a proxy which reads them
+/// differently: there may be only one `Transfer-Encoding` header, every
+/// coding must be one we know (or `identity`, which changes nothing), and
+/// `chunked` must appear exactly once, as the last coding (RFC 7230 section
+/// 3.3.3), so we know where the body ends.
+fn valid_transfer_encoding<'v, I>(mut values: I) -> bool
+    where I: Iterator<Item = &'v [u8]>
+{
+    let value = match (values.next(), values.next()) {
+        (None, _) => return true,
+        (Some(v), None) => v,
+        (Some(_), Some(_)) => return false,
+    };
+    let value = match str::from_utf8(value) {
+        Ok(v) => v,
+        Err(_) => return false,
+    };
+    let codings: Vec<&str> = value.split(',').map(|c| c.trim()).filter(|c| !c.is_empty()).collect();
+    let known = |c: &&str| {
+        c.eq_ignore_ascii_case("identity") ||
+        TRANSFER_CODINGS.iter().any(|k| c.eq_ignore_ascii_case(k))
+    };
+    let chunked = |c: &&str| c.eq_ignore_ascii_case("chunked");
+    codings.iter().all(known) && codings.iter().filter(|c| chunked(c)).count() == 1 &&
+    matches!(codings.last(), Some(c) if chunked(c))
+}
+
+/// The address in a `Forwarded` node or `X-Forwarded-For` entry, e.g.
+/// `192.0.2.43`, `"[2001:db8::1]:4711"` or `2001:db8::1`.
+fn node_ip(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Some(rest) = node.strip_prefix('[') {
+        let end = rest.find(']')?;
+        return rest[..end].parse::<Ipv6Addr>().ok().map(IpAddr::V6);
+    }
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    // An IPv4 address with a port
+    let colon = node.find(':')?;
+    node[..colon].parse::<Ipv4Addr>().ok().map(IpAddr::V4)
+}
+
+/// Split `host[:port]` (as in a `Host` header or authority-form request
+/// target) into the host name and port. An IPv6 literal must be in
+/// brackets, which are kept. `None` if it's malformed.
+fn split_authority(value: &str) -> Option<(&str, Option<u16>)> {
+    let (name, port) = if value.starts_with('[') {
+        let end = value.find(']')? + 1;
+        match &value[end..] {
+            "" => (&value[..end], None),
+            rest => (&value[..end], Some(rest.strip_prefix(':')?)),
+        }
+    } else {
+        match value.rfind(':') {
+            Some(colon) => (&value[..colon], Some(&value[colon + 1..])),
+            None => (value, None),
+        }
+    };
+    if name.is_empty() || (name.contains(':') && !name.starts_with('[')) {
+        return None;
+    }
+    let port = match port {
+        Some("") | None => None,
+        Some(p) => Some(p.parse::<u16>().ok()?),
+    };
+    Some((name, port))
+}
+
+/// Check an authority-form request target (`host:port`, which only CONNECT
+/// uses) and split it up. The port is required.
+fn authority_target(target: &str) -> Option<(&str, u16)> {
+    if target.contains(&['/', '?', '#', '@'][..]) {
+        return None;
+    }
+    match split_authority(target)? {
+        (name, Some(port)) => Some((name, port)),
+        (_, None) => None,
+    }
+}
+
+/// The bit of an entity tag between the quotes, ignoring any weak `W/`
+/// prefix, so tags can be compared weakly.
+fn opaque_tag(tag: &str) -> &str {
+    let tag = tag.strip_prefix("W/").unwrap_or(tag);
+    tag.strip_prefix('"').and_then(|t| t.strip_suffix('"')).unwrap_or(tag)
+}
+
+/// Read a request for `read_request_from`, `read_request_with_deadline` and
+/// the server, using `parser`, which has its limits set already. Parsing
+/// starts with `pending`, the octets left over from the previous request
+/// on the connection. Anything which arrives after the `header_deadline`
+/// isn't even parsed. Gives `None` if the stream ends before we've had
+/// anything at all.
+pub(crate) fn read_request_until<R: io::Read + ?Sized>(reader: &mut R,
+                                                       parser: &mut Parser,
+                                                       pending: &[u8],
+                                                       ttfb_timeout: Option<Duration>,
+                                                       header_deadline: Option<Duration>)
+                                                       -> io::Result<Option<(Request, Vec<u8>)>> {
+    if !pending.is_empty() {
+        match parser.parse(pending) {
+            ParseResult::Complete(r, used) => return Ok(Some((r, pending[used..].to_vec()))),
+            ParseResult::InProgress => {}
+            e => return Err(e.error().unwrap_or(ParseError::Malformed).into()),
+        }
+    }
+    let mut buffer = [0u8; READ_BUFFER_SIZE];
+    let start = Instant::now();
+    let past_deadline = || matches!(header_deadline, Some(limit) if start.elapsed() > limit);
+    // Once the client has sent something, it's no longer the first read
+    let mut first = pending.is_empty();
+    loop {
+        let n = match reader.read(&mut buffer) {
+            Ok(n) => n,
+            Err(ref e) if (first || past_deadline()) && is_timeout(e) => {
+                return Err(ParseError::Timeout.into())
+            }
+            Err(e) => return Err(e),
+        };
+        if past_deadline() {
+            return Err(ParseError::Timeout.into());
+        }
+        if first {
+            if let Some(limit) = ttfb_timeout {
+                if start.elapsed() > limit {
+                    return Err(ParseError::Timeout.into());
+                }
+            }
+            if n == 0 {
+                return Ok(None);
+            }
+            first = false;
+        }
+        let result = if n == 0 {
+            parser.finish()
+        } else {
+            parser.parse(&buffer[..n])
+        };
+        match result {
+            ParseResult::Complete(r, used) => return Ok(Some((r, buffer[used..n].to_vec()))),
+            ParseResult::InProgress => {}
+            e => return Err(e.error().unwrap_or(ParseError::Malformed).into()),
+        }
+    }
+}
+
+/// Whether a read failed because the socket's read timeout expired (which
+/// shows up as `WouldBlock` on some platforms).
+pub(crate) fn is_timeout(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock
+}
+
+/// Format a time as `10/Oct/2000:13:55:36 +0000`, the way Common Log
+/// Format wants it. Times before 1970 are logged as 1970.
+fn clf_date(when: SystemTime) -> String {
+    let secs = when.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let secs = secs % 86400;
+    format!("{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+            day,
+            MONTHS[month as usize - 1],
+            year,
+            secs / 3600,
+            (secs / 60) % 60,
+            secs % 60)
+}
+
+/// Format a time as `Sun, 06 Nov 1994 08:49:37 GMT`, the preferred
+/// HTTP-date format (RFC 7231 section 7.1.1.1). Times before 1970 come out
+/// as 1970.
+pub(crate) fn http_date(when: SystemTime) -> String {
+    let secs = when.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let days = secs / 86400;
+    let (year, month, day) = civil_from_days(days as i64);
+    let secs = secs % 86400;
+    format!("{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+            WEEKDAYS[(days % 7) as usize],
+            day,
+            MONTHS[month as usize - 1],
+            year,
+            secs / 3600,
+            (secs / 60) % 60,
+            secs % 60)
+}
+
+/// Turn a count of days since 1970-01-01 into a (year, month, day) date,
+/// using Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Parse the protocol at the end of a request line, which must match
+/// `HTTP/<digit>.<digit>` exactly (case matters). Anything else is
+/// `BadProtocol`, while a version other than 1.0 or 1.1 is
+/// `UnsupportedVersion`.
+fn parse_version(token: &[u8]) -> Result<http::Version, ParseError> {
+    match *token {
+        [b'H', b'T', b'T', b'P', b'/', major, b'.', minor]
+            if major.is_ascii_digit() && minor.is_ascii_digit() => {
+            match (major, minor) {
+                (b'1', b'0') => Ok(http::Version::HTTP_10),
+                (b'1', b'1') => Ok(http::Version::HTTP_11),
+                _ => Err(ParseError::UnsupportedVersion),
+            }
+        }
+        _ => Err(ParseError::BadProtocol),
+    }
+}
+
+/// How a protocol version appears on the request line.
+pub(crate) fn version_str(version: http::Version) -> &'static str {
+    if version == http::Version::HTTP_09 {
+        "HTTP/0.9"
+    } else if version == http::Version::HTTP_10 {
+        "HTTP/1.0"
+    } else if version == http::Version::HTTP_2 {
+        "HTTP/2.0"
+    } else {
+        "HTTP/1.1"
+    }
+}
+
+/// Whether the last transfer coding applied to the body is `chunked`.
+pub(crate) fn is_chunked(r: &Request) -> bool {
+    r.headers()
+     .get_all("Transfer-Encoding")
+     .iter()
+     .next_back()
+     .and_then(|v| v.to_str().ok())
+     .and_then(|v| v.rsplit(',').next())
+     .map(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+     .unwrap_or(false)
+}
+
+/// Splits a buffer into lines ending in LF or CRLF, without the line ending.
+/// A final line without a line ending isn't returned.
+struct Lines<'b> {
+    buffer: &'b [u8],
+    /// Where the next line starts
+    offset: usize,
+}
+
+impl<'b> Iterator for Lines<'b> {
+    type Item = &'b [u8];
+
+    fn next(&mut self) -> Option<&'b [u8]> {
+        let rest = &self.buffer[self.offset..];
+        let end = rest.iter().position(|&b| b == b'\n')?;
+        self.offset += end + 1;
+        let line = &rest[..end];
+        Some(if line.last() == Some(&b'\r') { &line[..end - 1] } else { line })
+    }
+}
+
+/// Do the work for `parse_once` and `Parser::parse_owned`. With `lenient`,
+/// runs of spaces in the request line count as one.
+fn split_request<'b>(buffer: &'b [u8], lenient: bool) -> OnceResult<'b> {
+    let mut lines = Lines {
+        buffer,
+        offset: 0,
+    };
+    let mut request_line = match lines.next() {
+        Some(l) => l,
+        None => return OnceResult::Incomplete,
+    };
+    let mut skipped = 0;
+    while request_line.is_empty() {
+        skipped += 1;
+        if skipped > MAX_LEADING_EMPTY_LINES {
+            return OnceResult::Error(ParseError::Malformed);
+        }
+        request_line = match lines.next() {
+            Some(l) => l,
+            None => return OnceResult::Incomplete,
+        };
+    }
+    let (method, uri, version) = match parse_once_request_line(request_line, lenient) {
+        Ok(x) => x,
+        Err(e) => return OnceResult::Error(e),
+    };
+    let mut headers = Vec::new();
+    loop {
+        let line = match lines.next() {
+            Some(l) => l,
+            None => return OnceResult::Incomplete,
+        };
+        if line.is_empty() {
+            break;
+        }
+        match parse_once_header(line) {
+            Ok(h) => headers.push(h),
+            Err(e) => return OnceResult::Error(e),
+        }
+    }
+    let transfer_encodings = headers.iter()
+                                    .filter(|h| h.0.eq_ignore_ascii_case("Transfer-Encoding"))
+                                    .map(|h| h.1);
+    if !valid_transfer_encoding(transfer_encodings) {
+        return OnceResult::Error(ParseError::BadTransferEncoding);
+    }
+    let request = BorrowedRequest {
+        method,
+        uri,
+        version,
+        headers,
+    };
+    OnceResult::Complete(request, lines.offset)
+}
+
+/// Split up a request line for `parse_once`.
+fn parse_once_request_line(line: &[u8],
+                           lenient: bool)
+                           -> Result<(&str, &str, http::Version), ParseError> {
+    let line = str::from_utf8(line).map_err(|_| ParseError::Malformed)?;
+    let mut parts = line.split(' ').filter(|part| !(lenient && part.is_empty()));
+    let method = parts.next().unwrap_or("");
+    if method.is_empty() || http::Method::from_bytes(method.as_bytes()).is_err() {
+        return Err(ParseError::BadMethod);
+    }
+    let uri = parts.next().unwrap_or("");
+    if uri.is_empty() || uri.bytes().any(|b| b == b' ' || is_bad_url_octet(b)) ||
+       (method == "CONNECT" && authority_target(uri).is_none()) {
+        return Err(ParseError::BadURL);
+    }
+    let version = parse_version(parts.next().unwrap_or("").as_bytes())?;
+    if parts.next().is_some() {
+        return Err(ParseError::BadProtocol);
+    }
+    Ok((method, uri, version))
+}
+
+/// Octets which can't appear in a request target, even though they don't
+/// end it: control characters (other than the CR and LF which end the
+/// line), DEL, and `#`, as RFC 7230 doesn't allow a fragment.
+fn is_bad_url_octet(c: u8) -> bool {
+    (c < 0x20 && c != b'\r' && c != b'\n') || c == 0x7F || c == b'#'
+}
+
+/// Split up a header line for `parse_once`.
+fn parse_once_header(line: &[u8]) -> Result<(&str, &[u8]), ParseError> {
+    if line[0] == b' ' || line[0] == b'\t' {
+        return Err(ParseError::ObsFold);
+    }
+    let colon = line.iter().position(|&b| b == b':').ok_or(ParseError::BadHeader)?;
+    let name = str::from_utf8(&line[..colon]).map_err(|_| ParseError::BadHeader)?;
+    if name.is_empty() || http::header::HeaderName::from_bytes(name.as_bytes()).is_err() {
+        return Err(ParseError::BadHeader);
+    }
+    let mut value = &line[colon + 1..];
+    while let Some((&b, rest)) = value.split_first() {
+        if b != b' ' && b != b'\t' {
+            break;
+        }
+        value = rest;
+    }
+    while let Some((&b, rest)) = value.split_last() {
+        if b != b' ' && b != b'\t' {
+            break;
+        }
+        value = rest;
+    }
+    if value.iter().any(|&b| (b < 0x20 && b != b'\t') || b == 0x7F) {
+        return Err(ParseError::BadHeaderValue);
+    }
+    Ok((name, value))
+}
+
+/// Split `s` on `sep`, except where `sep` is inside a quoted-string. Gives
+/// `None` if a quoted-string isn't closed.
+pub(crate) fn split_unquoted(s: &str, sep: char) -> Option<Vec<&str>> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (idx, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            _ if c == sep && !quoted => {
+                parts.push(&s[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    if quoted {
+        return None;
+    }
+    parts.push(&s[start..]);
+    Some(parts)
+}
+
+/// Remove the quotes and backslash escapes from a quoted-string, which
+/// must be the whole of `s`.
+pub(crate) fn unquote(s: &str) -> Option<String> {
+    if s.len() < 2 || !s.starts_with('"') || !s.ends_with('"') {
+        return None;
+    }
+    let mut result = String::with_capacity(s.len() - 2);
+    let mut chars = s[1..s.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.push(chars.next()?),
+            '"' => return None,
+            c => result.push(c),
+        }
+    }
+    Some(result)
+}
+
+/// Decode `%XX` escapes in `s`. Returns `None` on a truncated or non-hex
+/// escape, or if the result isn't valid UTF-8.
+pub(crate) fn percent_decode(s: &str) -> Option<String> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hi = bytes.next().and_then(hex_value)?;
+            let lo = bytes.next().and_then(hex_value)?;
+            out.push((hi << 4) | lo);
+        } else {
+            out.push(b);
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// The value of a single ASCII hex digit.
+fn hex_value(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
 
 /// Map an octet (in US-ASCII) to a character
 /// class, so we can decide what to do with it.
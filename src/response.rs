@@ -10,9 +10,28 @@
 // ****************************************************************************
 
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error;
 use std::fmt;
 use std::io;
 use std::borrow::Cow;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "gzip")]
+use std::io::Write;
+
+#[cfg(feature = "gzip")]
+use flate2::write::GzEncoder;
+#[cfg(feature = "gzip")]
+use flate2::Compression;
+use http;
+
+#[cfg(feature = "gzip")]
+use negotiation::accepts_gzip;
+use request::{http_date, version_str};
+#[cfg(feature = "gzip")]
+use request::Request;
 
 // ****************************************************************************
 //
@@ -20,11 +39,15 @@ use std::borrow::Cow;
 //
 // ****************************************************************************
 
-#[derive(Debug, Clone, Copy)]
+/// An HTTP status code. Statuses compare (and sort) by their numeric code,
+/// so they can key a `BTreeMap` as well as a `HashMap`. Keep the variants
+/// in numeric order, as that's what the derived `Ord` uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum HttpResponseStatus {
     Continue = 100,
     SwitchingProtocols = 101,
     Processing = 102,
+    EarlyHints = 103,
     OK = 200,
     Created = 201,
     Accepted = 202,
@@ -96,9 +119,80 @@ pub struct HttpResponse<'a> {
     /// The protocol the client is using in the response
     pub protocol: Cow<'a, str>,
     /// Any headers supplied by the server in the response
-    pub headers: HashMap<Cow<'a, str>, Cow<'a, str>>,
+    pub headers: HashMap<Cow<'a, str>, HeaderVal<'a>>,
+    /// Header fields which are each written on a line of their own, after
+    /// `headers`, even if they share a name
+    pub header_lines: Vec<(Cow<'a, str>, HeaderVal<'a>)>,
     /// The response body
-    pub body: Cow<'a, str>,
+    pub body: Body<'a>,
+}
+
+/// The body of a response. Usually text, but files such as images aren't,
+/// so we can also hold raw bytes. Either way, the body is written out
+/// exactly as given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Body<'a> {
+    /// A UTF-8 body
+    Text(Cow<'a, str>),
+    /// A body which may not be UTF-8
+    Bytes(Cow<'a, [u8]>),
+}
+
+/// The value of a response header. Usually text, but HTTP allows octets
+/// 0x80-0xFF in header values (obs-text) and a proxy may need to pass those
+/// on untouched, so we can also hold raw bytes. Either way, the value is
+/// written out exactly as given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderVal<'a> {
+    /// A UTF-8 header value
+    Text(Cow<'a, str>),
+    /// A header value which may not be UTF-8
+    Bytes(Cow<'a, [u8]>),
+}
+
+/// How `write_with_options` ends each line of the status line and headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    /// `\r\n`, as HTTP requires
+    CrLf,
+    /// Just `\n`. Not valid HTTP, but easier to read when debugging.
+    Lf,
+}
+
+/// Options for `HttpResponse::write_with_options`. The default writes
+/// exactly what `write` does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteOptions {
+    /// How to end lines. Defaults to `LineEnding::CrLf`.
+    pub line_ending: LineEnding,
+    /// Write the headers in order of name (ignoring case), as `write_sorted`
+    /// does. Defaults to false.
+    pub sort_headers: bool,
+    /// Add a `Content-Length` for the body, unless there's already one (or
+    /// a `Transfer-Encoding`). Defaults to false.
+    pub content_length: bool,
+    /// Add a `Date` header with the current time, unless there's already
+    /// one. Defaults to false.
+    pub date: bool,
+}
+
+/// Returned when a number or string isn't a status code we know about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidStatus {
+    /// What we were given
+    value: String,
+}
+
+/// Writes a response body using chunked transfer encoding. Obtained from
+/// `HttpResponse::write_chunked`, which has already sent the headers. Each
+/// call to `write_chunk` sends one chunk, and the body is terminated with
+/// `finish` or `finish_with_trailers`.
+#[derive(Debug)]
+pub struct ChunkedResponseWriter<'s, T: io::Write + 's> {
+    /// Where the chunks go
+    sink: &'s mut T,
+    /// The trailers we promised in the `Trailer` header
+    trailers: Vec<String>,
 }
 
 // ****************************************************************************
@@ -109,6 +203,26 @@ pub struct HttpResponse<'a> {
 
 // None
 
+// ****************************************************************************
+//
+// Private Data
+//
+// ****************************************************************************
+
+/// Room for the status line and a handful of headers.
+const HEAD_CAPACITY: usize = 256;
+
+/// The biggest body we'll copy in with the headers to send in one write.
+const MAX_COALESCED_BODY: usize = 64 * 1024;
+
+/// The FNV-1a 64-bit offset basis and prime, for `fnv1a`.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// The smallest body `compress_for` thinks is worth compressing.
+#[cfg(feature = "gzip")]
+const MIN_COMPRESSED_BODY: usize = 1024;
+
 // ****************************************************************************
 //
 // Public Functions
@@ -124,35 +238,714 @@ impl<'a> HttpResponse<'a> {
 
     pub fn new_with_body<S, T>(status: HttpResponseStatus, protocol: S, body: T) -> HttpResponse<'a>
         where S: Into<Cow<'a, str>>,
-              T: Into<Cow<'a, str>>
+              T: Into<Body<'a>>
     {
         HttpResponse {
-            status: status,
+            status,
             protocol: protocol.into(),
             headers: HashMap::new(),
+            header_lines: Vec::new(),
             body: body.into(),
         }
     }
 
-    pub fn write<T: io::Write>(&self, sink: &mut T) -> io::Result<usize> {
-        let header: String = format!("{} {}\r\n", self.protocol, self.status);
-        let mut total: usize = 0;
-        total += try!(sink.write(header.as_bytes()));
-        for (k, v) in &self.headers {
-            let line = format!("{}: {}\r\n", k, v);
-            total += try!(sink.write(line.as_bytes()));
+    /// A `200 OK` HTTP/1.1 response with a plain text body.
+    pub fn ok_text<T: Into<Cow<'a, str>>>(body: T) -> HttpResponse<'a> {
+        HttpResponse::ok_with_type("text/plain; charset=utf-8", Body::Text(body.into()))
+    }
+
+    /// A `200 OK` HTTP/1.1 response with an HTML body.
+    pub fn ok_html<T: Into<Cow<'a, str>>>(body: T) -> HttpResponse<'a> {
+        HttpResponse::ok_with_type("text/html; charset=utf-8", Body::Text(body.into()))
+    }
+
+    /// A `200 OK` HTTP/1.1 response with a JSON body. The body must already
+    /// be serialised - we just send it.
+    pub fn ok_json<T: Into<Cow<'a, str>>>(body: T) -> HttpResponse<'a> {
+        HttpResponse::ok_with_type("application/json", Body::Text(body.into()))
+    }
+
+    /// A `200 OK` HTTP/1.1 response serving the contents of a file. The
+    /// `Content-Type` is guessed from the extension of `path` (see
+    /// `mime_type`), and `Last-Modified` is set from `mtime_secs`, the
+    /// file's modification time in seconds since the Unix epoch. Reading the
+    /// file is up to you.
+    pub fn from_file_bytes(path: &str, bytes: Vec<u8>, mtime_secs: u64) -> HttpResponse<'a> {
+        let length = bytes.len().to_string();
+        let modified = UNIX_EPOCH + Duration::from_secs(mtime_secs);
+        let mut response = HttpResponse::ok_with_type(mime_type(path), Body::from(bytes));
+        response.add_header("Content-Length", length);
+        response.add_header("Last-Modified", http_date(modified));
+        response
+    }
+
+    /// A `405 Method Not Allowed` response, with the `Allow` header (which
+    /// RFC 7231 requires) listing the `allowed` methods in the order given.
+    pub fn method_not_allowed(allowed: &[&str]) -> HttpResponse<'a> {
+        let mut response = HttpResponse::new(HttpResponseStatus::MethodNotAllowed, "HTTP/1.1");
+        response.add_header("Allow", allowed.join(", "));
+        response
+    }
+
+    /// A `401 Unauthorized` response challenging the client to send
+    /// `Authorization: Basic ...` credentials for `realm`.
+    pub fn unauthorized_basic(realm: &str) -> HttpResponse<'a> {
+        HttpResponse::unauthorized("Basic", realm)
+    }
+
+    /// A `401 Unauthorized` response challenging the client to send
+    /// `Authorization: Bearer ...` credentials for `realm`.
+    pub fn unauthorized_bearer(realm: &str) -> HttpResponse<'a> {
+        HttpResponse::unauthorized("Bearer", realm)
+    }
+
+    /// A `304 Not Modified` response, for when a conditional request (see
+    /// `request::etag_matches`) finds the client's copy is current. It has
+    /// no body; you should still send the `ETag` (see `set_etag`).
+    pub fn not_modified() -> HttpResponse<'a> {
+        HttpResponse::new(HttpResponseStatus::NotModified, "HTTP/1.1")
+    }
+
+    /// A `101 Switching Protocols` response agreeing to switch the
+    /// connection to protocol `to` (one of `request::requested_upgrades`),
+    /// with the `Upgrade` and `Connection: Upgrade` headers. Once it's
+    /// written, the connection belongs to the new protocol.
+    pub fn switching_protocols(to: &str) -> HttpResponse<'a> {
+        let mut response = HttpResponse::new(HttpResponseStatus::SwitchingProtocols, "HTTP/1.1");
+        response.add_header("Upgrade", to.to_string());
+        response.add_header("Connection", "Upgrade");
+        response
+    }
+
+    /// A `103 Early Hints` response telling the client about resources it
+    /// can start fetching while we work on the final response. Each
+    /// `(url, rel)` pair becomes a `Link` header line of its own, like
+    /// `Link: </style.css>; rel=preload`. Send it with `write_interim`.
+    pub fn early_hints(links: &[(&str, &str)]) -> HttpResponse<'a> {
+        let mut response = HttpResponse::new(HttpResponseStatus::EarlyHints, "HTTP/1.1");
+        for &(url, rel) in links {
+            response.add_header_line("Link", format!("<{}>; rel={}", url, rel));
         }
-        total += try!(sink.write(b"\r\n"));
-        total += try!(sink.write(self.body.as_bytes()));
-        return Ok(total);
+        response
+    }
+
+    /// A `206 Partial Content` response for octets `start` to `end`
+    /// (inclusive, as in the `Range` header) of a resource which is `total`
+    /// octets long. You supply the body, which should be just that range.
+    pub fn partial_content(start: u64, end: u64, total: u64) -> HttpResponse<'a> {
+        debug_assert!(start <= end && end < total, "bad range {}-{}/{}", start, end, total);
+        let mut response = HttpResponse::new(HttpResponseStatus::PartialContent, "HTTP/1.1");
+        response.add_header("Content-Range", format!("bytes {}-{}/{}", start, end, total));
+        response
+    }
+
+    /// A `206 Partial Content` response for several ranges of a resource
+    /// which is `total` octets long, as a `multipart/byteranges` body (RFC
+    /// 7233 appendix A). Each `(start, end)` range is inclusive, as in the
+    /// `Range` header, and `data(start, end)` should return those octets.
+    /// Every part gets its own `Content-Type` (the resource's
+    /// `content_type`) and `Content-Range`. The boundary is made from a hash
+    /// of the data, and checked not to appear in any of it.
+    pub fn multipart_byteranges<F>(ranges: &[(u64, u64)],
+                                   total: u64,
+                                   content_type: &str,
+                                   data: F)
+                                   -> HttpResponse<'a>
+        where F: Fn(u64, u64) -> Vec<u8>
+    {
+        for &(start, end) in ranges {
+            debug_assert!(start <= end && end < total, "bad range {}-{}/{}", start, end, total);
+        }
+        let parts: Vec<Vec<u8>> = ranges.iter().map(|&(start, end)| data(start, end)).collect();
+        let mut seed = parts.iter().fold(FNV_OFFSET_BASIS, |hash, p| hash ^ fnv1a(p));
+        let boundary = loop {
+            let candidate = format!("rushttp-{:016x}", seed);
+            if !parts.iter().any(|p| contains(p, candidate.as_bytes())) {
+                break candidate;
+            }
+            seed = seed.wrapping_add(1);
+        };
+        let mut body = Vec::new();
+        for (&(start, end), part) in ranges.iter().zip(&parts) {
+            body.extend_from_slice(format!("--{}\r\nContent-Type: {}\r\n\
+                                            Content-Range: bytes {}-{}/{}\r\n\r\n",
+                                           boundary,
+                                           content_type,
+                                           start,
+                                           end,
+                                           total)
+                                       .as_bytes());
+            body.extend_from_slice(part);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+        let mut response =
+            HttpResponse::new_with_body(HttpResponseStatus::PartialContent, "HTTP/1.1", body);
+        response.add_header("Content-Type", format!("multipart/byteranges; boundary={}", boundary));
+        response
+    }
+
+    /// A `416 Range Not Satisfiable` response, for when the requested range
+    /// lies outside a resource which is `resource_len` octets long. RFC 7233
+    /// says to send the actual length in a `Content-Range` header.
+    pub fn range_not_satisfiable(resource_len: u64) -> HttpResponse<'a> {
+        let mut response = HttpResponse::new(HttpResponseStatus::RangeNotSatisfiable, "HTTP/1.1");
+        response.add_header("Content-Range", format!("bytes */{}", resource_len));
+        response
+    }
+
+    /// Set the protocol from a typed version, rather than a string. The
+    /// convenience constructors (`ok_text` and friends) are all HTTP/1.1, so
+    /// use this to answer an HTTP/1.0 client in kind:
+    /// `HttpResponse::ok_text("hi").with_version(request.version())`.
+    pub fn with_version(mut self, version: http::Version) -> HttpResponse<'a> {
+        self.protocol = Cow::Borrowed(version_str(version));
+        self
+    }
+
+    /// The protocol version this response is for, if `protocol` is one we
+    /// recognise.
+    pub fn version(&self) -> Option<http::Version> {
+        match &*self.protocol {
+            "HTTP/0.9" => Some(http::Version::HTTP_09),
+            "HTTP/1.0" => Some(http::Version::HTTP_10),
+            "HTTP/1.1" => Some(http::Version::HTTP_11),
+            "HTTP/2.0" => Some(http::Version::HTTP_2),
+            _ => None,
+        }
+    }
+
+    /// Write the response to `sink`, returning the number of octets
+    /// written. Short writes are retried, so either the whole response is
+    /// written or you get an error. If there's no `Connection` header, an
+    /// HTTP/1.0 response gets `Connection: close`, while an HTTP/1.1
+    /// response gets nothing (as keep-alive is the default). The sink is
+    /// flushed afterwards, so it's safe to close the connection as soon as
+    /// this returns, even if the sink is buffered.
+    ///
+    /// `204 No Content` and `304 Not Modified` responses can't have a body,
+    /// so any body is left out, as is any `Content-Length` on a 204.
+    pub fn write<T: io::Write>(&self, sink: &mut T) -> io::Result<usize> {
+        self.write_response(sink, &WriteOptions::default(), false)
+    }
+
+    /// Like `write`, but with control over the details - see
+    /// `WriteOptions`.
+    pub fn write_with_options<T: io::Write>(&self,
+                                            sink: &mut T,
+                                            options: &WriteOptions)
+                                            -> io::Result<usize> {
+        self.write_response(sink, options, false)
+    }
+
+    /// Like `write`, but for a response to a request with the given
+    /// `method`. The response to a HEAD request never has a body, but
+    /// does get a `Content-Length` saying how long the body would have
+    /// been (unless you've set one yourself).
+    pub fn write_for_request<T: io::Write>(&self,
+                                           method: &http::Method,
+                                           sink: &mut T)
+                                           -> io::Result<usize> {
+        self.write_response(sink, &WriteOptions::default(), *method == http::Method::HEAD)
+    }
+
+    /// Exactly how many octets `write` would send, worked out without
+    /// writing anything, so you can size a buffer or account for bandwidth
+    /// up front.
+    pub fn serialized_len(&self) -> usize {
+        self.serialized_len_with_options(&WriteOptions::default())
+    }
+
+    /// Exactly how many octets `write_with_options` would send with these
+    /// `options`, including any `Content-Length` and `Date` they add.
+    pub fn serialized_len_with_options(&self, options: &WriteOptions) -> usize {
+        let eol = options.line_ending.as_bytes().len();
+        let extra = self.extra_headers(options, false);
+        let extra: Vec<(&str, &str)> = extra.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        let status_line = self.protocol.len() + 1 + self.status.to_string().len() + eol;
+        let headers: usize = self.fields(&extra)
+                                 .iter()
+                                 .map(|(k, v)| k.len() + 2 + v.len() + eol)
+                                 .sum();
+        let body = if self.is_bodiless() { 0 } else { self.body.len() };
+        status_line + headers + eol + body
+    }
+
+    /// Like `write`, but the headers are written in order of name (ignoring
+    /// case) rather than in whatever order the header map holds them. Handy
+    /// when you need byte-for-byte repeatable output, e.g. in tests.
+    pub fn write_sorted<T: io::Write>(&self, sink: &mut T) -> io::Result<usize> {
+        let options = WriteOptions {
+            sort_headers: true,
+            ..WriteOptions::default()
+        };
+        self.write_response(sink, &options, false)
+    }
+
+    /// The whole response as it would be written by `write`.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        // Writing to a Vec can't fail
+        self.write(&mut out).expect("write to Vec failed");
+        out
+    }
+
+    /// Write an informational (1xx) response, such as `100 Continue` or
+    /// `103 Early Hints`, ahead of the final response. These are just a
+    /// status line and headers - any body is ignored.
+    pub fn write_interim<T: io::Write>(&self, sink: &mut T) -> io::Result<usize> {
+        debug_assert!(self.status.as_u16() / 100 == 1,
+                      "{} is not an interim status",
+                      self.status);
+        write_all(sink, &self.head(&[], &WriteOptions::default()))
+    }
+
+    /// Write just the status line, headers and the blank line after them,
+    /// leaving you to write the body straight to `sink` - e.g. streaming it
+    /// from a file rather than holding it all in `body`, which is ignored
+    /// here. If you know how long the body will be, set `Content-Length`
+    /// first; if not, use `write_chunked` instead. Nothing is flushed.
+    pub fn write_head<T: io::Write>(&self, sink: &mut T) -> io::Result<usize> {
+        write_all(sink, &self.head(&[], &WriteOptions::default()))
     }
 
+    /// Write the status line and headers, plus `Transfer-Encoding: chunked`
+    /// and a `Trailer` header listing `trailers` (if there are any). Any
+    /// `Content-Length` is left out, as a chunked response mustn't have one.
+    /// The body, if not empty, is sent as the first chunk. The returned writer
+    /// is used to send the rest of the body.
+    pub fn write_chunked<'s, T: io::Write>(&self,
+                                           sink: &'s mut T,
+                                           trailers: &[&str])
+                                           -> io::Result<ChunkedResponseWriter<'s, T>> {
+        let trailer_list = trailers.join(", ");
+        let head = if trailers.is_empty() {
+            self.head(&[("Transfer-Encoding", "chunked")], &WriteOptions::default())
+        } else {
+            self.head(&[("Transfer-Encoding", "chunked"), ("Trailer", &trailer_list)],
+                      &WriteOptions::default())
+        };
+        sink.write_all(&head)?;
+        let mut writer = ChunkedResponseWriter {
+            sink,
+            trailers: trailers.iter().map(|t| t.to_string()).collect(),
+        };
+        writer.write_chunk(self.body.as_bytes())?;
+        Ok(writer)
+    }
+
+    /// Set a header, replacing any existing value. The value can be text
+    /// (`&str`, `String`) or raw bytes (`&[u8]`, `Vec<u8>`).
     pub fn add_header<S, T>(&mut self, key: S, value: T)
         where S: Into<Cow<'a, str>>,
-              T: Into<Cow<'a, str>>
+              T: Into<HeaderVal<'a>>
     {
         self.headers.insert(key.into(), value.into());
     }
+
+    /// Add a header on a line of its own, keeping any others with the same
+    /// name. This is for headers which can't be combined into one, like
+    /// `Set-Cookie`, or which read better one per line, like `Link`.
+    pub fn add_header_line<S, T>(&mut self, key: S, value: T)
+        where S: Into<Cow<'a, str>>,
+              T: Into<HeaderVal<'a>>
+    {
+        self.header_lines.push((key.into(), value.into()));
+    }
+
+    /// Add a value to a header, for headers like `Vary` or `Cache-Control`
+    /// which are comma-separated lists. If we already have the header (with
+    /// any capitalisation), the new value goes on the end after `", "`.
+    /// Otherwise this is the same as `add_header`.
+    pub fn append_header<S, T>(&mut self, key: S, value: T)
+        where S: Into<Cow<'a, str>>,
+              T: Into<HeaderVal<'a>>
+    {
+        let key = key.into();
+        let value = value.into();
+        let existing = self.headers.iter_mut().find(|(k, _)| k.eq_ignore_ascii_case(&key));
+        match existing {
+            Some((_, old)) => {
+                let mut joined = old.as_bytes().to_vec();
+                joined.extend_from_slice(b", ");
+                joined.extend_from_slice(value.as_bytes());
+                *old = match String::from_utf8(joined) {
+                    Ok(s) => HeaderVal::Text(Cow::Owned(s)),
+                    Err(e) => HeaderVal::Bytes(Cow::Owned(e.into_bytes())),
+                };
+            }
+            None => {
+                self.headers.insert(key, value);
+            }
+        }
+    }
+
+    /// Set the `ETag` header to the strong entity tag `tag`, adding the
+    /// quotes, so `set_etag("v1")` gives `ETag: "v1"`. Entity tags can't
+    /// contain `"` or control characters, so those are dropped.
+    pub fn set_etag(&mut self, tag: &str) {
+        let tag: String = tag.chars().filter(|c| *c != '"' && !c.is_control()).collect();
+        self.add_header("ETag", format!("\"{}\"", tag));
+    }
+
+    /// Compress the body with gzip and set `Content-Encoding: gzip`. If
+    /// you've set a `Content-Length`, it's updated to match. Only do this
+    /// if the client said it can take gzip (see `negotiation::accepts_gzip`)
+    /// - or let `compress_for` decide for you.
+    #[cfg(feature = "gzip")]
+    pub fn gzip_body(&mut self) {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        // Writing to a Vec can't fail
+        encoder.write_all(self.body.as_bytes()).expect("gzip to Vec failed");
+        let compressed = encoder.finish().expect("gzip to Vec failed");
+        if self.has_header("Content-Length") {
+            self.headers.retain(|k, _| !k.eq_ignore_ascii_case("Content-Length"));
+            self.add_header("Content-Length", compressed.len().to_string());
+        }
+        self.body = Body::Bytes(Cow::Owned(compressed));
+        self.add_header("Content-Encoding", "gzip");
+    }
+
+    /// Compress the body with `gzip_body` if the request `r` says the
+    /// client accepts gzip and the body is big enough for it to be worth
+    /// it (at least 1 KiB), adding `Accept-Encoding` to `Vary` so caches
+    /// know the body depends on it. A body which already has a
+    /// `Content-Encoding`, or a response which can't have a body, is left
+    /// alone.
+    #[cfg(feature = "gzip")]
+    pub fn compress_for(&mut self, r: &Request) {
+        if self.body.len() < MIN_COMPRESSED_BODY || self.is_bodiless() ||
+           self.has_header("Content-Encoding") || !accepts_gzip(r) {
+            return;
+        }
+        self.gzip_body();
+        self.append_header("Vary", "Accept-Encoding");
+    }
+
+    /// A `200 OK` HTTP/1.1 response with the given body and `Content-Type`.
+    fn ok_with_type(content_type: &'static str, body: Body<'a>) -> HttpResponse<'a> {
+        let mut response = HttpResponse::new_with_body(HttpResponseStatus::OK, "HTTP/1.1", body);
+        response.add_header("Content-Type", content_type);
+        response
+    }
+
+    /// A `401 Unauthorized` HTTP/1.1 response with a `WWW-Authenticate`
+    /// challenge for the given scheme and realm.
+    fn unauthorized(scheme: &str, realm: &str) -> HttpResponse<'a> {
+        let mut response = HttpResponse::new(HttpResponseStatus::Unauthorized, "HTTP/1.1");
+        response.add_header("WWW-Authenticate",
+                            format!("{} realm={}", scheme, quoted_string(realm)));
+        response
+    }
+
+    /// The status line and all the headers, followed by any `extra` headers
+    /// and the blank line which ends the header block, ready to be sent in
+    /// one write. Only the line ending and sorting in `options` matter here.
+    fn head(&self, extra: &[(&str, &str)], options: &WriteOptions) -> Vec<u8> {
+        let eol = options.line_ending.as_bytes();
+        let mut out: Vec<u8> = Vec::with_capacity(HEAD_CAPACITY);
+        out.extend_from_slice(format!("{} {}", self.protocol, self.status).as_bytes());
+        out.extend_from_slice(eol);
+        let mut headers = self.fields(extra);
+        if options.sort_headers {
+            headers.sort_by_key(|&(k, _)| k.to_ascii_lowercase());
+        }
+        for (k, v) in headers {
+            out.extend_from_slice(k.as_bytes());
+            out.extend_from_slice(b": ");
+            out.extend_from_slice(v);
+            out.extend_from_slice(eol);
+        }
+        out.extend_from_slice(eol);
+        out
+    }
+
+    /// Every header field to be written: ours (less any `Content-Length` on
+    /// a 204 or when `extra` makes it chunked), then our header lines, then
+    /// `extra`, then the default `Connection`, if there is one.
+    fn fields<'s>(&'s self, extra: &[(&'s str, &'s str)]) -> Vec<(&'s str, &'s [u8])> {
+        // RFC 7230 section 3.3.2 - a 204 mustn't have a Content-Length - and
+        // section 3.3.3 - nor should anything with a Transfer-Encoding, as a
+        // recipient which believed the length could be tricked into reading
+        // the rest of the body as another message
+        let no_length = matches!(self.status, HttpResponseStatus::NoContent) ||
+                        extra.iter().any(|&(k, _)| k.eq_ignore_ascii_case("Transfer-Encoding"));
+        let mut headers: Vec<(&str, &[u8])> =
+            self.headers
+                .iter()
+                .chain(self.header_lines.iter().map(|(k, v)| (k, v)))
+                .filter(|&(k, _)| !(no_length && k.eq_ignore_ascii_case("Content-Length")))
+                .map(|(k, v)| (&**k, v.as_bytes()))
+                .collect();
+        headers.extend(extra.iter().map(|&(k, v)| (k, v.as_bytes())));
+        if let Some(connection) = self.default_connection() {
+            headers.push(("Connection", connection.as_bytes()));
+        }
+        headers
+    }
+
+    /// The headers `write_response` adds to ours, given `options` and
+    /// whether it's the response to a HEAD request.
+    fn extra_headers(&self, options: &WriteOptions, head: bool) -> Vec<(&'static str, String)> {
+        let mut extra = Vec::new();
+        if (head || options.content_length) && !self.is_bodiless() &&
+           !self.has_header("Content-Length") && !self.has_header("Transfer-Encoding") {
+            extra.push(("Content-Length", self.body.len().to_string()));
+        }
+        if options.date && !self.has_header("Date") {
+            extra.push(("Date", http_date(SystemTime::now())));
+        }
+        extra
+    }
+
+    /// Whether the status says there's no body, whatever we've been given.
+    fn is_bodiless(&self) -> bool {
+        matches!(self.status, HttpResponseStatus::NoContent | HttpResponseStatus::NotModified)
+    }
+
+    /// Write the whole response, then flush. If `head`, it's the response
+    /// to a HEAD request, so it gets a `Content-Length` but no body. To save
+    /// on system calls the body goes out in the same write as the headers,
+    /// unless it's so big that copying it would cost more than a second
+    /// write.
+    fn write_response<T: io::Write>(&self,
+                                    sink: &mut T,
+                                    options: &WriteOptions,
+                                    head: bool)
+                                    -> io::Result<usize> {
+        let extra = self.extra_headers(options, head);
+        let extra: Vec<(&str, &str)> = extra.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        let mut out = self.head(&extra, options);
+        let body = if head || self.is_bodiless() { &[][..] } else { self.body.as_bytes() };
+        let total = if body.len() <= MAX_COALESCED_BODY {
+            out.extend_from_slice(body);
+            write_all(sink, &out)?
+        } else {
+            write_all(sink, &out)? + write_all(sink, body)?
+        };
+        sink.flush()?;
+        Ok(total)
+    }
+
+    /// Whether we have a header with this name (ignoring case).
+    pub(crate) fn has_header(&self, name: &str) -> bool {
+        self.headers
+            .keys()
+            .chain(self.header_lines.iter().map(|(k, _)| k))
+            .any(|k| k.eq_ignore_ascii_case(name))
+    }
+
+    /// The `Connection` header to send if the user hasn't set one. HTTP/1.1
+    /// connections are persistent unless told otherwise, so they don't need
+    /// one. HTTP/1.0 connections aren't, and this library doesn't do
+    /// HTTP/1.0 keep-alive, so we tell the client we're closing.
+    fn default_connection(&self) -> Option<&'static str> {
+        if self.has_header("Connection") {
+            return None;
+        }
+        match self.version() {
+            Some(http::Version::HTTP_10) => Some("close"),
+            _ => None,
+        }
+    }
+}
+
+impl Default for WriteOptions {
+    fn default() -> WriteOptions {
+        WriteOptions {
+            line_ending: LineEnding::CrLf,
+            sort_headers: false,
+            content_length: false,
+            date: false,
+        }
+    }
+}
+
+impl LineEnding {
+    /// The octets which end a line.
+    fn as_bytes(self) -> &'static [u8] {
+        match self {
+            LineEnding::CrLf => b"\r\n",
+            LineEnding::Lf => b"\n",
+        }
+    }
+}
+
+impl<'a> HeaderVal<'a> {
+    /// The value as octets, exactly as it will be written.
+    pub fn as_bytes(&self) -> &[u8] {
+        match *self {
+            HeaderVal::Text(ref s) => s.as_bytes(),
+            HeaderVal::Bytes(ref b) => b,
+        }
+    }
+
+    /// The value as a string, if it's valid UTF-8.
+    pub fn to_str(&self) -> Option<&str> {
+        match *self {
+            HeaderVal::Text(ref s) => Some(s),
+            HeaderVal::Bytes(ref b) => ::std::str::from_utf8(b).ok(),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for HeaderVal<'a> {
+    fn from(s: &'a str) -> HeaderVal<'a> {
+        HeaderVal::Text(Cow::Borrowed(s))
+    }
+}
+
+impl<'a> From<String> for HeaderVal<'a> {
+    fn from(s: String) -> HeaderVal<'a> {
+        HeaderVal::Text(Cow::Owned(s))
+    }
+}
+
+impl<'a> From<Cow<'a, str>> for HeaderVal<'a> {
+    fn from(s: Cow<'a, str>) -> HeaderVal<'a> {
+        HeaderVal::Text(s)
+    }
+}
+
+impl<'a> From<&'a [u8]> for HeaderVal<'a> {
+    fn from(b: &'a [u8]) -> HeaderVal<'a> {
+        HeaderVal::Bytes(Cow::Borrowed(b))
+    }
+}
+
+impl<'a> From<Vec<u8>> for HeaderVal<'a> {
+    fn from(b: Vec<u8>) -> HeaderVal<'a> {
+        HeaderVal::Bytes(Cow::Owned(b))
+    }
+}
+
+impl<'a> PartialEq<str> for HeaderVal<'a> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<'a, 'b> PartialEq<&'b str> for HeaderVal<'a> {
+    fn eq(&self, other: &&'b str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<'a> Body<'a> {
+    /// The body as octets, exactly as it will be written.
+    pub fn as_bytes(&self) -> &[u8] {
+        match *self {
+            Body::Text(ref s) => s.as_bytes(),
+            Body::Bytes(ref b) => b,
+        }
+    }
+
+    /// The body as a string, if it's valid UTF-8.
+    pub fn to_str(&self) -> Option<&str> {
+        match *self {
+            Body::Text(ref s) => Some(s),
+            Body::Bytes(ref b) => ::std::str::from_utf8(b).ok(),
+        }
+    }
+
+    /// The length of the body in octets.
+    pub fn len(&self) -> usize {
+        self.as_bytes().len()
+    }
+
+    /// Whether the body is empty.
+    pub fn is_empty(&self) -> bool {
+        self.as_bytes().is_empty()
+    }
+}
+
+impl<'a> From<&'a str> for Body<'a> {
+    fn from(s: &'a str) -> Body<'a> {
+        Body::Text(Cow::Borrowed(s))
+    }
+}
+
+impl<'a> From<String> for Body<'a> {
+    fn from(s: String) -> Body<'a> {
+        Body::Text(Cow::Owned(s))
+    }
+}
+
+impl<'a> From<Cow<'a, str>> for Body<'a> {
+    fn from(s: Cow<'a, str>) -> Body<'a> {
+        Body::Text(s)
+    }
+}
+
+impl<'a> From<&'a [u8]> for Body<'a> {
+    fn from(b: &'a [u8]) -> Body<'a> {
+        Body::Bytes(Cow::Borrowed(b))
+    }
+}
+
+impl<'a> From<Vec<u8>> for Body<'a> {
+    fn from(b: Vec<u8>) -> Body<'a> {
+        Body::Bytes(Cow::Owned(b))
+    }
+}
+
+impl<'a> PartialEq<str> for Body<'a> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<'a, 'b> PartialEq<&'b str> for Body<'a> {
+    fn eq(&self, other: &&'b str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<'s, T: io::Write> ChunkedResponseWriter<'s, T> {
+    /// Send `data` as a single chunk. Empty slices are skipped, as an empty
+    /// chunk would end the body.
+    pub fn write_chunk(&mut self, data: &[u8]) -> io::Result<()> {
+        if !data.is_empty() {
+            write!(self.sink, "{:x}\r\n", data.len())?;
+            self.sink.write_all(data)?;
+            self.sink.write_all(b"\r\n")?;
+        }
+        Ok(())
+    }
+
+    /// Send the final zero-length chunk with no trailers.
+    pub fn finish(self) -> io::Result<()> {
+        self.finish_with_trailers(&[])
+    }
+
+    /// Send the final zero-length chunk followed by the given trailers, and
+    /// flush the sink. Every trailer must have been listed when the writer
+    /// was created, so that the client saw it in the `Trailer` header.
+    pub fn finish_with_trailers(self, trailers: &[(&str, &str)]) -> io::Result<()> {
+        for &(k, _) in trailers {
+            if !self.trailers.iter().any(|t| t.eq_ignore_ascii_case(k)) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          format!("Trailer {} was not announced", k)));
+            }
+        }
+        self.sink.write_all(b"0\r\n")?;
+        for &(k, v) in trailers {
+            write!(self.sink, "{}: {}\r\n", k, v)?;
+        }
+        self.sink.write_all(b"\r\n")?;
+        self.sink.flush()
+    }
+}
+
+impl<'s, T: io::Write> io::Write for ChunkedResponseWriter<'s, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_chunk(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
 }
 
 impl fmt::Display for HttpResponseStatus {
@@ -167,11 +960,86 @@ impl fmt::Display for HttpResponseStatus {
 }
 
 impl HttpResponseStatus {
+    /// Look up a status by its numeric code.
+    pub fn from_u16(code: u16) -> Option<HttpResponseStatus> {
+        match code {
+            100 => Some(HttpResponseStatus::Continue),
+            101 => Some(HttpResponseStatus::SwitchingProtocols),
+            102 => Some(HttpResponseStatus::Processing),
+            103 => Some(HttpResponseStatus::EarlyHints),
+            200 => Some(HttpResponseStatus::OK),
+            201 => Some(HttpResponseStatus::Created),
+            202 => Some(HttpResponseStatus::Accepted),
+            203 => Some(HttpResponseStatus::NonAuthoritativeInformation),
+            204 => Some(HttpResponseStatus::NoContent),
+            205 => Some(HttpResponseStatus::ResetContent),
+            206 => Some(HttpResponseStatus::PartialContent),
+            207 => Some(HttpResponseStatus::MultiStatus),
+            208 => Some(HttpResponseStatus::AlreadyReported),
+            226 => Some(HttpResponseStatus::ImUsed),
+            300 => Some(HttpResponseStatus::MultipleChoices),
+            301 => Some(HttpResponseStatus::MovedPermanently),
+            302 => Some(HttpResponseStatus::Found),
+            303 => Some(HttpResponseStatus::SeeOther),
+            304 => Some(HttpResponseStatus::NotModified),
+            305 => Some(HttpResponseStatus::UseProxy),
+            306 => Some(HttpResponseStatus::SwitchProxy),
+            307 => Some(HttpResponseStatus::TemporaryRedirect),
+            308 => Some(HttpResponseStatus::PermanentRedirect),
+            400 => Some(HttpResponseStatus::BadRequest),
+            401 => Some(HttpResponseStatus::Unauthorized),
+            402 => Some(HttpResponseStatus::PaymentRequired),
+            403 => Some(HttpResponseStatus::Forbidden),
+            404 => Some(HttpResponseStatus::NotFound),
+            405 => Some(HttpResponseStatus::MethodNotAllowed),
+            406 => Some(HttpResponseStatus::NotAcceptable),
+            407 => Some(HttpResponseStatus::ProxyAuthenticationRequired),
+            408 => Some(HttpResponseStatus::RequestTimeout),
+            409 => Some(HttpResponseStatus::Conflict),
+            410 => Some(HttpResponseStatus::Gone),
+            411 => Some(HttpResponseStatus::LengthRequired),
+            412 => Some(HttpResponseStatus::PreconditionFailed),
+            413 => Some(HttpResponseStatus::PayloadTooLarge),
+            414 => Some(HttpResponseStatus::URITooLong),
+            415 => Some(HttpResponseStatus::UnsupportedMediaType),
+            416 => Some(HttpResponseStatus::RangeNotSatisfiable),
+            417 => Some(HttpResponseStatus::ExpectationFailed),
+            418 => Some(HttpResponseStatus::IAmATeapot),
+            421 => Some(HttpResponseStatus::MisdirectedRequest),
+            422 => Some(HttpResponseStatus::UnprocessableEntity),
+            423 => Some(HttpResponseStatus::Locked),
+            424 => Some(HttpResponseStatus::FailedDependency),
+            426 => Some(HttpResponseStatus::UpgradeRequired),
+            428 => Some(HttpResponseStatus::PreconditionRequired),
+            429 => Some(HttpResponseStatus::TooManyRequests),
+            431 => Some(HttpResponseStatus::RequestHeaderFieldsTooLarge),
+            451 => Some(HttpResponseStatus::UnavailableForLegalReasons),
+            500 => Some(HttpResponseStatus::InternalServerError),
+            501 => Some(HttpResponseStatus::NotImplemented),
+            502 => Some(HttpResponseStatus::BadGateway),
+            503 => Some(HttpResponseStatus::ServiceUnavailable),
+            504 => Some(HttpResponseStatus::GatewayTimeout),
+            505 => Some(HttpResponseStatus::HTTPVersionNotSupported),
+            506 => Some(HttpResponseStatus::VariantAlsoNegotiates),
+            507 => Some(HttpResponseStatus::InsufficientStorage),
+            508 => Some(HttpResponseStatus::LoopDetected),
+            510 => Some(HttpResponseStatus::NotExtended),
+            511 => Some(HttpResponseStatus::NetworkAuthenticationRequired),
+            _ => None,
+        }
+    }
+
+    /// The numeric status code, e.g. 404.
+    pub fn as_u16(&self) -> u16 {
+        *self as u16
+    }
+
     pub fn as_string(&self) -> &str {
         match *self {
             HttpResponseStatus::Continue => "Continue",
             HttpResponseStatus::SwitchingProtocols => "Switching Protocols",
             HttpResponseStatus::Processing => "Processing",
+            HttpResponseStatus::EarlyHints => "Early Hints",
             HttpResponseStatus::OK => "OK",
             HttpResponseStatus::Created => "Created",
             HttpResponseStatus::Accepted => "Accepted",
@@ -233,13 +1101,129 @@ impl HttpResponseStatus {
         }
     }
 }
+
+impl From<HttpResponseStatus> for u16 {
+    fn from(status: HttpResponseStatus) -> u16 {
+        status.as_u16()
+    }
+}
+
+impl TryFrom<u16> for HttpResponseStatus {
+    type Error = InvalidStatus;
+
+    fn try_from(code: u16) -> Result<HttpResponseStatus, InvalidStatus> {
+        HttpResponseStatus::from_u16(code).ok_or(InvalidStatus { value: code.to_string() })
+    }
+}
+
+impl FromStr for HttpResponseStatus {
+    type Err = InvalidStatus;
+
+    /// Parses a numeric code, like `"404"`.
+    fn from_str(s: &str) -> Result<HttpResponseStatus, InvalidStatus> {
+        s.parse::<u16>()
+         .ok()
+         .and_then(HttpResponseStatus::from_u16)
+         .ok_or(InvalidStatus { value: s.to_string() })
+    }
+}
+
+impl fmt::Display for InvalidStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unknown HTTP status code {:?}", self.value)
+    }
+}
+
+impl error::Error for InvalidStatus {}
+
+/// Guess the media type of a file from the extension on `path` (ignoring
+/// case), for use as a `Content-Type`. Text types say they're UTF-8.
+/// Anything we don't recognise is `application/octet-stream`.
+pub fn mime_type(path: &str) -> &'static str {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let extension = match name.rfind('.') {
+        Some(idx) if idx > 0 => name[idx + 1..].to_ascii_lowercase(),
+        _ => return "application/octet-stream",
+    };
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "txt" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "json" => "application/json",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "webp" => "image/webp",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+/// A strong entity tag for a body of `bytes`, ready to go in an `ETag`
+/// header (quotes included) - `"af63dc4c8601ec8c"` for `a`, say. It's a
+/// hash of the content, so the same bytes always give the same tag, across
+/// runs and across servers, and a change to the bytes almost certainly
+/// changes it. It's not cryptographic, so a malicious client could find a
+/// collision.
+pub fn strong_etag(bytes: &[u8]) -> String {
+    format!("\"{:016x}\"", fnv1a(bytes))
+}
+
+/// Like `strong_etag`, but weak (`W/"..."`), for when equivalent content
+/// might not be byte-for-byte identical, e.g. a body you'll compress.
+pub fn weak_etag(bytes: &[u8]) -> String {
+    format!("W/{}", strong_etag(bytes))
+}
+
 // ****************************************************************************
 //
 // Private Functions
 //
 // ****************************************************************************
 
-// None
+/// The 64-bit FNV-1a hash of `bytes`. Unlike `DefaultHasher`, it's the same
+/// on every platform and with every version of Rust.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, &b| (hash ^ u64::from(b)).wrapping_mul(FNV_PRIME))
+}
+
+/// Whether `needle` appears anywhere in `haystack`.
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// Write all of `data`, returning how much that was.
+fn write_all<T: io::Write>(sink: &mut T, data: &[u8]) -> io::Result<usize> {
+    sink.write_all(data)?;
+    Ok(data.len())
+}
+
+/// Render `value` as an RFC 7230 quoted-string, escaping any `"` or `\`.
+/// Control characters aren't allowed in header values, so they're dropped.
+fn quoted_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for c in value.chars().filter(|c| *c == '\t' || !c.is_control()) {
+        if c == '"' || c == '\\' {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result.push('"');
+    result
+}
 
 // ****************************************************************************
 //
@@ -0,0 +1,194 @@
+//! # Request Routing
+//!
+//! A tiny router which picks a handler based on the request method and path.
+//! Patterns are made of `/`-separated segments. A segment starting with `:`
+//! captures whatever is in that position, and a final `*` matches the rest of
+//! the path, which must have at least one more segment (though it may be
+//! empty). For example, `/users/:id` matches `/users/42` with `id` set to
+//! `42`, and `/static/*` matches `/static/` and anything under it, but not
+//! `/static`.
+
+// ****************************************************************************
+//
+// Imports
+//
+// ****************************************************************************
+
+use std::collections::HashMap;
+use std::fmt;
+
+use http;
+
+use request::{percent_decode, Request};
+use response::{HttpResponse, HttpResponseStatus};
+
+// ****************************************************************************
+//
+// Public Types
+//
+// ****************************************************************************
+
+/// The values captured from a path by `:param` segments, keyed on the
+/// parameter name. Whatever a trailing `*` matched is stored under `*`.
+pub type Params = HashMap<String, String>;
+
+/// Maps `(method, path pattern)` pairs to handlers.
+#[derive(Debug, Default)]
+pub struct Router {
+    /// Checked in the order they were added
+    routes: Vec<Route>,
+}
+
+// ****************************************************************************
+//
+// Private Types
+//
+// ****************************************************************************
+
+/// The signature of a route handler.
+type RouteHandler = dyn Fn(&Request, &Params) -> HttpResponse<'static> + Send + Sync;
+
+/// One entry in the routing table.
+struct Route {
+    method: http::Method,
+    pattern: Vec<Segment>,
+    handler: Box<RouteHandler>,
+}
+
+/// One `/`-separated piece of a path pattern.
+#[derive(Debug, PartialEq)]
+enum Segment {
+    /// Must match exactly
+    Literal(String),
+    /// Matches any single segment, which is captured under this name
+    Param(String),
+    /// Matches all remaining segments
+    Wildcard,
+}
+
+// ****************************************************************************
+//
+// Public Functions
+//
+// ****************************************************************************
+
+impl Router {
+    /// Create an empty router. Everything will get a 404.
+    pub fn new() -> Router {
+        Router { routes: Vec::new() }
+    }
+
+    /// Add a route. Routes are tried in the order they are added, and the
+    /// first one which matches both method and path wins.
+    pub fn route<F>(&mut self, method: http::Method, pattern: &str, handler: F) -> &mut Router
+        where F: Fn(&Request, &Params) -> HttpResponse<'static> + Send + Sync + 'static
+    {
+        self.routes.push(Route {
+            method,
+            pattern: parse_pattern(pattern),
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Pass the request to the matching handler. If no route matches the
+    /// path you get a 404. If routes match the path but not the method you
+    /// get a 405, with an `Allow` header listing the methods which would
+    /// have worked.
+    pub fn dispatch(&self, r: &Request) -> HttpResponse<'static> {
+        let path: Vec<&str> = split_path(r.uri().path());
+        let mut allowed: Vec<&str> = Vec::new();
+        for route in &self.routes {
+            if let Some(params) = match_pattern(&route.pattern, &path) {
+                if route.method == *r.method() {
+                    return (route.handler)(r, &params);
+                }
+                if !allowed.contains(&route.method.as_str()) {
+                    allowed.push(route.method.as_str());
+                }
+            }
+        }
+        if allowed.is_empty() {
+            let body = format!("Error {}: No route for {}\r\n",
+                               HttpResponseStatus::NotFound,
+                               r.uri().path());
+            let mut response = HttpResponse::new_with_body(HttpResponseStatus::NotFound,
+                                                           "HTTP/1.1",
+                                                           body);
+            response.add_header("Content-Type", "text/plain; charset=utf-8");
+            response
+        } else {
+            let mut response = HttpResponse::method_not_allowed(&allowed);
+            response.body = format!("Error {}: Method {} not allowed\r\n",
+                                    HttpResponseStatus::MethodNotAllowed,
+                                    r.method())
+                                .into();
+            response.add_header("Content-Type", "text/plain; charset=utf-8");
+            response
+        }
+    }
+}
+
+impl fmt::Debug for Route {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Route({} {:?})", self.method, self.pattern)
+    }
+}
+
+// ****************************************************************************
+//
+// Private Functions
+//
+// ****************************************************************************
+
+/// Split a path into segments, dropping the leading `/`.
+fn split_path(path: &str) -> Vec<&str> {
+    path.trim_start_matches('/').split('/').collect()
+}
+
+/// Turn a pattern string into segments.
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    split_path(pattern)
+        .into_iter()
+        .map(|s| match s.strip_prefix(':') {
+            _ if s == "*" => Segment::Wildcard,
+            Some(name) => Segment::Param(name.to_string()),
+            None => Segment::Literal(s.to_string()),
+        })
+        .collect()
+}
+
+/// See if the path segments match the pattern. Each segment is decoded on
+/// its own, so an escaped `/` (`%2F`) never acts as a separator.
+fn match_pattern(pattern: &[Segment], path: &[&str]) -> Option<Params> {
+    let mut params = Params::new();
+    for (i, segment) in pattern.iter().enumerate() {
+        match *segment {
+            Segment::Wildcard => {
+                // `/static/*` doesn't match plain `/static`
+                let rest = path.get(i..).filter(|rest| !rest.is_empty())?;
+                params.insert("*".to_string(), percent_decode(&rest.join("/"))?);
+                return Some(params);
+            }
+            Segment::Literal(ref l) => {
+                if percent_decode(path.get(i)?)? != *l {
+                    return None;
+                }
+            }
+            Segment::Param(ref name) => {
+                params.insert(name.clone(), percent_decode(path.get(i)?)?);
+            }
+        }
+    }
+    if path.len() == pattern.len() {
+        Some(params)
+    } else {
+        None
+    }
+}
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
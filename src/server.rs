@@ -0,0 +1,622 @@
+//! # HTTP Server
+//!
+//! A small blocking HTTP server built on the `request` parser and the
+//! `response` encoder. Connections are accepted on the calling thread and
+//! handed to a fixed-size pool of worker threads, so a flood of connections
+//! queues up rather than spawning an unbounded number of threads.
+//!
+//! The server listens on TCP (IPv4 or IPv6) or, on Unix, on a Unix domain
+//! socket. Parsing and responding don't care which.
+//!
+//! HTTP/1.1 connections are kept open for further requests (which may be
+//! pipelined) unless the client asks us to close, or the request has a
+//! body - handlers only see the headers, so we can't tell where the next
+//! request would start.
+
+// ****************************************************************************
+//
+// Imports
+//
+// ****************************************************************************
+
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use http::{Method, Version};
+
+use request::{has_body, is_timeout, keep_alive, read_request_until, ParseError, Parser, Request};
+use response::{HttpResponse, HttpResponseStatus};
+
+// ****************************************************************************
+//
+// Public Types
+//
+// ****************************************************************************
+
+/// A blocking HTTP server with a bounded pool of worker threads.
+#[derive(Debug)]
+pub struct Server {
+    /// The socket we accept connections on
+    listener: Listener,
+    /// How many worker threads handle connections
+    workers: usize,
+    /// How long we wait for the client to send us something
+    read_timeout: Option<Duration>,
+    /// How long a kept-alive connection may wait for its next request
+    idle_timeout: Option<Duration>,
+    /// How many connections we'll handle (or queue) at once
+    max_connections: Option<usize>,
+    /// How many requests we'll answer on one connection before closing it
+    max_requests: Option<usize>,
+    /// How long the client has to send a whole header block
+    header_timeout: Option<Duration>,
+    /// How many header fields a request may have
+    max_header_count: Option<usize>,
+    /// How big a request may be, body included
+    max_request_bytes: Option<usize>,
+    /// Set when someone asks the accept loop to stop
+    shutdown: Arc<AtomicBool>,
+}
+
+/// Lets another thread stop a running `Server`.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    /// Shared with the `Server`
+    flag: Arc<AtomicBool>,
+    /// Where the server is listening, so we can wake up the accept loop
+    addr: ListenAddr,
+}
+
+// ****************************************************************************
+//
+// Private Types
+//
+// ****************************************************************************
+
+/// A connection handler, shared between all the workers.
+type Handler = dyn Fn(Request) -> HttpResponse<'static> + Send + Sync;
+
+/// The `Server` settings each worker needs for its connections.
+#[derive(Debug, Clone)]
+struct Limits {
+    /// How long we wait for the client to send us something
+    read_timeout: Option<Duration>,
+    /// How long we wait between requests on a kept-alive connection
+    idle_timeout: Option<Duration>,
+    /// How many requests we'll answer on one connection
+    max_requests: Option<usize>,
+    /// How long the client has to send a whole header block
+    header_timeout: Option<Duration>,
+    /// How many header fields a request may have
+    max_header_count: Option<usize>,
+    /// How big a request may be, body included
+    max_request_bytes: Option<usize>,
+    /// Set when the server is shutting down
+    shutdown: Arc<AtomicBool>,
+}
+
+/// The kinds of socket we can listen on.
+#[derive(Debug)]
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+/// Where a `Listener` is listening.
+#[derive(Debug, Clone)]
+enum ListenAddr {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+/// Whatever sort of stream a client connected on. The workers only need to
+/// read, write and hang up.
+trait Connection: Read + Write + Send {
+    /// Give up on reads which take longer than `timeout`
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+    /// Hang up, in both directions
+    fn shutdown(&self) -> io::Result<()>;
+}
+
+// ****************************************************************************
+//
+// Private Data
+//
+// ****************************************************************************
+
+const DEFAULT_READ_TIMEOUT_SECONDS: u64 = 300;
+
+const DEFAULT_IDLE_TIMEOUT_SECONDS: u64 = 5;
+
+const DEFAULT_HEADER_TIMEOUT_SECONDS: u64 = 30;
+
+const DEFAULT_MAX_HEADER_COUNT: usize = 100;
+
+const DEFAULT_MAX_REQUEST_BYTES: usize = 1024 * 1024;
+
+/// How often an idle connection checks whether the server is shutting down.
+const SHUTDOWN_POLL_MILLISECONDS: u64 = 100;
+
+const READ_BUFFER_SIZE: usize = 1024;
+
+// ****************************************************************************
+//
+// Public Functions
+//
+// ****************************************************************************
+
+impl Server {
+    /// Bind to the given address. `workers` is the number of threads which
+    /// will handle connections, and must be at least one.
+    pub fn bind<A: ToSocketAddrs>(addr: A, workers: usize) -> io::Result<Server> {
+        Ok(Server::new(Listener::Tcp(TcpListener::bind(addr)?), workers))
+    }
+
+    /// Bind to `port` on every local address, IPv4 and IPv6. This binds the
+    /// IPv6 wildcard address `[::]`, which also accepts IPv4 connections
+    /// (as IPv4-mapped addresses) on systems where `IPV6_V6ONLY` is off by
+    /// default, like Linux and macOS. If IPv6 isn't available at all, we
+    /// fall back to `0.0.0.0`.
+    pub fn bind_dual_stack(port: u16, workers: usize) -> io::Result<Server> {
+        match Server::bind((Ipv6Addr::UNSPECIFIED, port), workers) {
+            Ok(s) => Ok(s),
+            Err(_) => Server::bind((Ipv4Addr::UNSPECIFIED, port), workers),
+        }
+    }
+
+    /// Listen on a Unix domain socket at `path`, which mustn't exist yet.
+    #[cfg(unix)]
+    pub fn bind_unix<P: AsRef<Path>>(path: P, workers: usize) -> io::Result<Server> {
+        Ok(Server::new(Listener::Unix(UnixListener::bind(path)?), workers))
+    }
+
+    /// Set how long a connection may sit idle before we give up on it.
+    /// `None` means wait forever.
+    pub fn read_timeout(&mut self, timeout: Option<Duration>) -> &mut Server {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Set how long a kept-alive connection may wait for the client to start
+    /// its next request before we hang up. This is much shorter than the
+    /// read timeout by default (five seconds), as an idle client ties up a
+    /// worker. `None` means wait forever, or until the server shuts down.
+    pub fn idle_timeout(&mut self, timeout: Option<Duration>) -> &mut Server {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Limit how many connections may be open at once, counting those being
+    /// handled and those waiting for a worker. Past the limit, new
+    /// connections immediately get a `503 Service Unavailable` and are
+    /// closed. `None` (the default) means no limit, although the accept
+    /// loop still stops accepting while the workers' queue is full.
+    pub fn max_connections(&mut self, max: Option<usize>) -> &mut Server {
+        self.max_connections = max;
+        self
+    }
+
+    /// Limit how many requests are answered on any one connection. The
+    /// response to the last one says `Connection: close`, and then we hang
+    /// up, so a client can't hold a worker forever and connections are
+    /// recycled now and then. `None` (the default) means no limit. Every
+    /// connection gets at least one request.
+    pub fn max_requests_per_connection(&mut self, max: Option<usize>) -> &mut Server {
+        self.max_requests = max;
+        self
+    }
+
+    /// Set how long the client has, from when it starts a request, to send
+    /// the whole header block. That stops a slowloris client holding a
+    /// worker by dribbling out its headers, each octet arriving well within
+    /// the read timeout. Clients which run out of time get a `408 Request
+    /// Timeout`. The default is 30 seconds, and `None` means no limit.
+    pub fn header_timeout(&mut self, timeout: Option<Duration>) -> &mut Server {
+        self.header_timeout = timeout;
+        self
+    }
+
+    /// Limit how many header fields a request may have, as with
+    /// `Parser::max_header_count`. Requests with more get a `431 Request
+    /// Header Fields Too Large`. The default is 100, and `None` means no
+    /// limit.
+    pub fn max_header_count(&mut self, max: Option<usize>) -> &mut Server {
+        self.max_header_count = max;
+        self
+    }
+
+    /// Limit how big a request may be, as with `Parser::max_total_bytes`.
+    /// Handlers never see the body, but a `Content-Length` which would take
+    /// the request over the limit still counts, and such requests get a
+    /// `413 Payload Too Large`. The default is 1 MiB. `None` means no
+    /// limit, which lets a client send an endless header block.
+    pub fn max_request_bytes(&mut self, max: Option<usize>) -> &mut Server {
+        self.max_request_bytes = max;
+        self
+    }
+
+    /// The address we're listening on. Useful if you bound to port 0.
+    /// Fails if we're listening on a Unix domain socket.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self.listener {
+            Listener::Tcp(ref l) => l.local_addr(),
+            #[cfg(unix)]
+            Listener::Unix(_) => {
+                Err(io::Error::new(io::ErrorKind::InvalidInput, "not a TCP listener"))
+            }
+        }
+    }
+
+    /// Get a handle which can stop the server from another thread (e.g. a
+    /// signal handler).
+    pub fn shutdown_handle(&self) -> io::Result<ShutdownHandle> {
+        Ok(ShutdownHandle {
+            flag: self.shutdown.clone(),
+            addr: self.listener.addr()?,
+        })
+    }
+
+    /// Run the accept loop until shut down. Every request is passed to
+    /// `handler` and whatever it returns is sent back to the client. Requests
+    /// which fail to parse get an error page instead.
+    pub fn serve<F>(self, handler: F) -> io::Result<()>
+        where F: Fn(Request) -> HttpResponse<'static> + Send + Sync + 'static
+    {
+        let handler: Arc<Handler> = Arc::new(handler);
+        // A rendezvous-ish channel - if every worker is busy and the queue is
+        // full, the accept loop blocks rather than piling up connections.
+        let (tx, rx) = mpsc::sync_channel::<Box<dyn Connection>>(self.workers);
+        let rx = Arc::new(Mutex::new(rx));
+        // Only this thread adds to the count, and only workers take away, so
+        // checking it and then adding can't take us past the limit.
+        let active = Arc::new(AtomicUsize::new(0));
+        let mut threads = Vec::with_capacity(self.workers);
+        for _ in 0..self.workers {
+            let rx = rx.clone();
+            let handler = handler.clone();
+            let active = active.clone();
+            let limits = Limits {
+                read_timeout: self.read_timeout,
+                idle_timeout: self.idle_timeout,
+                max_requests: self.max_requests,
+                header_timeout: self.header_timeout,
+                max_header_count: self.max_header_count,
+                max_request_bytes: self.max_request_bytes,
+                shutdown: self.shutdown.clone(),
+            };
+            threads.push(thread::spawn(move || worker(&rx, &*handler, &active, &limits)));
+        }
+
+        loop {
+            let stream = self.listener.accept();
+            if self.shutdown.load(Ordering::SeqCst) {
+                break;
+            }
+            // A failed accept only affects that one client
+            if let Ok(stream) = stream {
+                let count = active.load(Ordering::SeqCst);
+                if matches!(self.max_connections, Some(max) if count >= max) {
+                    debug!("rushttp: too many connections, rejecting one");
+                    reject_connection(stream);
+                    continue;
+                }
+                active.fetch_add(1, Ordering::SeqCst);
+                if tx.send(stream).is_err() {
+                    break;
+                }
+            }
+        }
+
+        // Closing the channel tells the workers to finish up
+        drop(tx);
+        for t in threads {
+            let _ = t.join();
+        }
+        Ok(())
+    }
+
+    fn new(listener: Listener, workers: usize) -> Server {
+        assert!(workers > 0, "a Server needs at least one worker");
+        Server {
+            listener,
+            workers,
+            read_timeout: Some(Duration::from_secs(DEFAULT_READ_TIMEOUT_SECONDS)),
+            idle_timeout: Some(Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SECONDS)),
+            max_connections: None,
+            max_requests: None,
+            header_timeout: Some(Duration::from_secs(DEFAULT_HEADER_TIMEOUT_SECONDS)),
+            max_header_count: Some(DEFAULT_MAX_HEADER_COUNT),
+            max_request_bytes: Some(DEFAULT_MAX_REQUEST_BYTES),
+            shutdown: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl ShutdownHandle {
+    /// Ask the server to stop. Requests already started are still answered,
+    /// but kept-alive connections are closed rather than waiting for another,
+    /// and `Server::serve` returns once they are done.
+    pub fn shutdown(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+        // The accept loop is blocked in accept(), so poke it.
+        match self.addr {
+            ListenAddr::Tcp(addr) => {
+                let _ = TcpStream::connect(addr);
+            }
+            #[cfg(unix)]
+            ListenAddr::Unix(ref path) => {
+                let _ = UnixStream::connect(path);
+            }
+        }
+    }
+}
+
+impl Listener {
+    /// Wait for the next client.
+    fn accept(&self) -> io::Result<Box<dyn Connection>> {
+        match *self {
+            Listener::Tcp(ref l) => Ok(Box::new(l.accept()?.0)),
+            #[cfg(unix)]
+            Listener::Unix(ref l) => Ok(Box::new(l.accept()?.0)),
+        }
+    }
+
+    /// Where to connect to reach us. A wildcard address (`0.0.0.0` or
+    /// `[::]`) isn't something you can connect to, so we use loopback.
+    fn addr(&self) -> io::Result<ListenAddr> {
+        match *self {
+            Listener::Tcp(ref l) => {
+                let mut addr = l.local_addr()?;
+                if addr.ip().is_unspecified() {
+                    match addr {
+                        SocketAddr::V4(_) => addr.set_ip(Ipv4Addr::LOCALHOST.into()),
+                        SocketAddr::V6(_) => addr.set_ip(Ipv6Addr::LOCALHOST.into()),
+                    }
+                }
+                Ok(ListenAddr::Tcp(addr))
+            }
+            #[cfg(unix)]
+            Listener::Unix(ref l) => {
+                let addr = l.local_addr()?;
+                let path = addr.as_pathname().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "unnamed Unix socket")
+                })?;
+                Ok(ListenAddr::Unix(path.to_path_buf()))
+            }
+        }
+    }
+}
+
+impl Limits {
+    /// A parser with our limits set.
+    fn parser(&self) -> Parser {
+        let mut parser = Parser::new();
+        if let Some(max) = self.max_header_count {
+            parser.max_header_count(max);
+        }
+        if let Some(max) = self.max_request_bytes {
+            parser.max_total_bytes(max);
+        }
+        parser
+    }
+
+    /// The read timeout for a socket we're reading a request from. A read
+    /// can't be allowed to outlast the header timeout, or we wouldn't notice
+    /// the deadline had passed until it returned.
+    fn request_read_timeout(&self) -> Option<Duration> {
+        match (self.read_timeout, self.header_timeout) {
+            (Some(read), Some(header)) => Some(read.min(header)),
+            (read, header) => read.or(header),
+        }
+    }
+}
+
+impl Connection for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+#[cfg(unix)]
+impl Connection for UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        UnixStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+// ****************************************************************************
+//
+// Private Functions
+//
+// ****************************************************************************
+
+/// Pull connections off the queue until it's closed.
+fn worker(rx: &Mutex<mpsc::Receiver<Box<dyn Connection>>>,
+          handler: &Handler,
+          active: &AtomicUsize,
+          limits: &Limits) {
+    loop {
+        let next = match rx.lock() {
+            Ok(rx) => rx.recv(),
+            Err(_) => return,
+        };
+        match next {
+            Ok(stream) => {
+                handle_connection(stream, handler, limits);
+                active.fetch_sub(1, Ordering::SeqCst);
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+/// Answer requests until the client (or `limits`) says that's enough, or
+/// something goes wrong, then hang up.
+fn handle_connection(mut stream: Box<dyn Connection>, handler: &Handler, limits: &Limits) {
+    if stream.set_read_timeout(limits.request_read_timeout()).is_ok() {
+        // Whatever we've read beyond the end of the last request
+        let mut pending = Vec::new();
+        let mut served = 0;
+        loop {
+            if served > 0 && pending.is_empty() &&
+               !wait_for_next(&mut *stream, &mut pending, limits) {
+                break;
+            }
+            served += 1;
+            let last = matches!(limits.max_requests, Some(max) if served >= max);
+            let (mut response, method, close) = match read_request(&mut stream,
+                                                                  &mut pending,
+                                                                  limits) {
+                Ok(Some(request)) => {
+                    trace!("rushttp: handling {} {}", request.method(), request.uri());
+                    let method = request.method().clone();
+                    let close = last || has_body(&request) || !keep_alive(&request) ||
+                                request.version() != Version::HTTP_11;
+                    (handler(request), method, close)
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    debug!("rushttp: bad request: {:?}", e);
+                    (error_response(e), Method::GET, true)
+                }
+            };
+            let close = close || closes_connection(&response);
+            if close {
+                response.add_header("Connection", "close");
+            } else if !response.has_header("Content-Length") &&
+                      !response.has_header("Transfer-Encoding") &&
+                      !matches!(response.status,
+                                HttpResponseStatus::NoContent | HttpResponseStatus::NotModified) {
+                // The client needs to know where this response ends
+                response.add_header("Content-Length", response.body.len().to_string());
+            }
+            if response.write_for_request(&method, &mut stream).is_err() || close {
+                break;
+            }
+        }
+    }
+    let _ = stream.shutdown();
+}
+
+/// Wait for the client to start its next request, putting what it sends in
+/// `pending`. Returns `false` if the connection should be closed instead -
+/// because the client went away, it was idle for too long, or the server is
+/// shutting down.
+fn wait_for_next(stream: &mut dyn Connection, pending: &mut Vec<u8>, limits: &Limits) -> bool {
+    let poll = Duration::from_millis(SHUTDOWN_POLL_MILLISECONDS);
+    let start = Instant::now();
+    let mut buffer = [0u8; READ_BUFFER_SIZE];
+    loop {
+        if limits.shutdown.load(Ordering::SeqCst) {
+            return false;
+        }
+        // Wait in short slices, so we notice a shutdown promptly
+        let timeout = match limits.idle_timeout {
+            Some(idle) => {
+                let elapsed = start.elapsed();
+                if elapsed >= idle {
+                    return false;
+                }
+                poll.min(idle - elapsed)
+            }
+            None => poll,
+        };
+        if stream.set_read_timeout(Some(timeout)).is_err() {
+            return false;
+        }
+        match stream.read(&mut buffer) {
+            Ok(0) => return false,
+            Ok(n) => {
+                pending.extend_from_slice(&buffer[..n]);
+                // The rest of the request gets the usual timeout
+                return stream.set_read_timeout(limits.request_read_timeout()).is_ok();
+            }
+            Err(ref e) if is_timeout(e) || e.kind() == io::ErrorKind::Interrupted => {}
+            Err(_) => return false,
+        }
+    }
+}
+
+/// Whether the handler has said to close the connection after `response`,
+/// i.e. its `Connection` header lists the `close` token.
+fn closes_connection(response: &HttpResponse) -> bool {
+    response.headers
+        .iter()
+        .filter(|&(k, _)| k.eq_ignore_ascii_case("Connection"))
+        .filter_map(|(_, v)| v.to_str())
+        .flat_map(|v| v.split(','))
+        .any(|t| t.trim().eq_ignore_ascii_case("close"))
+}
+
+/// Turn away a connection because we're too busy.
+fn reject_connection(mut stream: Box<dyn Connection>) {
+    let body = "Error 503: Too Many Connections\r\n";
+    let mut response =
+        HttpResponse::new_with_body(HttpResponseStatus::ServiceUnavailable, "HTTP/1.1", body);
+    response.add_header("Content-Type", "text/plain; charset=utf-8");
+    response.add_header("Connection", "close");
+    let _ = response.write(&mut stream);
+    let _ = stream.shutdown();
+}
+
+/// Read a request, starting with whatever is in `pending`, using a parser
+/// with our limits. Anything after the request is left in `pending` for
+/// next time. Returns `Ok(None)` if the client went away without sending
+/// anything, or if reading failed for some reason other than a bad request
+/// (in which case there's no point answering).
+fn read_request<S: Read + ?Sized>(stream: &mut S,
+                                  pending: &mut Vec<u8>,
+                                  limits: &Limits)
+                                  -> Result<Option<Request>, ParseError> {
+    let mut parser = limits.parser();
+    let data = mem::take(pending);
+    match read_request_until(stream, &mut parser, &data, None, limits.header_timeout) {
+        Ok(Some((request, rest))) => {
+            *pending = rest;
+            Ok(Some(request))
+        }
+        Ok(None) => Ok(None),
+        Err(e) => {
+            match e.get_ref().and_then(|inner| inner.downcast_ref::<ParseError>()) {
+                Some(&error) => Err(error),
+                None => Ok(None),
+            }
+        }
+    }
+}
+
+/// Build an error page for a request we couldn't parse.
+fn error_response(error: ParseError) -> HttpResponse<'static> {
+    let status = error.as_status();
+    let body = format!("Error {0}: {1}\r\n", status, error);
+    let mut response = HttpResponse::new_with_body(status, "HTTP/1.1", body);
+    response.add_header("Content-Type", "text/plain; charset=utf-8");
+    response
+}
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
@@ -0,0 +1,133 @@
+//! # Testing Helpers
+//!
+//! An in-memory stand-in for a socket, so a whole request/response cycle
+//! can be tested without any networking. Enable the `testing` feature to
+//! use it in your own tests.
+
+// ****************************************************************************
+//
+// Imports
+//
+// ****************************************************************************
+
+use std::collections::VecDeque;
+use std::io;
+
+// ****************************************************************************
+//
+// Public Types
+//
+// ****************************************************************************
+
+/// A fake stream. Reads hand out a scripted sequence of chunks, one (or as
+/// much of one as fits) per `read`, so you can see how your code copes with
+/// a request arriving in pieces. Empty chunks are skipped, as a read of 0
+/// means end of file, which only comes once the chunks run out. Everything
+/// written is kept for you to inspect.
+#[derive(Debug, Clone, Default)]
+pub struct MockStream {
+    /// What's left to read, one chunk per `read`
+    input: VecDeque<Vec<u8>>,
+    /// Everything written so far
+    output: Vec<u8>,
+    /// How many times `write` was called
+    writes: usize,
+    /// How many times `flush` was called
+    flushes: usize,
+}
+
+// ****************************************************************************
+//
+// Private Types
+//
+// ****************************************************************************
+
+// None
+
+// ****************************************************************************
+//
+// Public Functions
+//
+// ****************************************************************************
+
+impl MockStream {
+    /// A stream whose reads return each of `chunks` in turn.
+    pub fn new<I, C>(chunks: I) -> MockStream
+        where I: IntoIterator<Item = C>,
+              C: Into<Vec<u8>>
+    {
+        MockStream {
+            input: chunks.into_iter()
+                .map(|c| c.into())
+                .filter(|c: &Vec<u8>| !c.is_empty())
+                .collect(),
+            output: Vec::new(),
+            writes: 0,
+            flushes: 0,
+        }
+    }
+
+    /// Everything written to the stream so far.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// How many times `write` has been called, which for a socket would be
+    /// one system call each.
+    pub fn writes(&self) -> usize {
+        self.writes
+    }
+
+    /// How many times the stream has been flushed.
+    pub fn flushes(&self) -> usize {
+        self.flushes
+    }
+
+    /// Whether every scripted chunk has been read.
+    pub fn is_drained(&self) -> bool {
+        self.input.is_empty()
+    }
+}
+
+impl io::Read for MockStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let chunk = match self.input.front_mut() {
+            Some(c) => c,
+            None => return Ok(0),
+        };
+        let n = chunk.len().min(buf.len());
+        buf[..n].copy_from_slice(&chunk[..n]);
+        chunk.drain(..n);
+        if chunk.is_empty() {
+            self.input.pop_front();
+        }
+        Ok(n)
+    }
+}
+
+impl io::Write for MockStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writes += 1;
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flushes += 1;
+        Ok(())
+    }
+}
+
+// ****************************************************************************
+//
+// Private Functions
+//
+// ****************************************************************************
+
+// None
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
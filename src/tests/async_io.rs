@@ -0,0 +1,127 @@
+//! # The rushttp Rust HTTP Library - Async Tests
+//!
+//! Unit tests for reading requests from async streams.
+
+// ****************************************************************************
+//
+// Imports
+//
+// ****************************************************************************
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWriteExt, BufReader, ReadBuf};
+use tokio::runtime;
+
+use super::super::async_io::*;
+use super::super::request::*;
+
+// ****************************************************************************
+//
+// Private Types
+//
+// ****************************************************************************
+
+/// Hands out three octets at a time, and isn't ready every other time it's
+/// polled, as a socket might not be.
+struct PieceReader {
+    data: &'static [u8],
+    ready: bool,
+}
+
+/// Fails every read with the given kind of error.
+struct FailingReader(io::ErrorKind);
+
+// ****************************************************************************
+//
+// Public Functions
+//
+// ****************************************************************************
+
+#[test]
+fn read_from_slice() {
+    let mut input: &[u8] = b"POST /x HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nHello";
+    let r = block_on(read_request_async(&mut input)).unwrap();
+    assert_eq!(r.method(), "POST");
+    assert_eq!(r.uri(), "/x");
+    assert_eq!(r.headers()["Host"], "localhost");
+    // The body is left for the caller
+    assert_eq!(input, b"Hello");
+}
+
+#[test]
+fn read_from_duplex() {
+    let (mut client, server) = tokio::io::duplex(1024);
+    block_on(client.write_all(b"GET /index.html HTTP/1.1\r\nUser-Agent: rust test\r\n\r\n"))
+        .unwrap();
+    let mut server = BufReader::new(server);
+    let r = block_on(read_request_async(&mut server)).unwrap();
+    assert_eq!(r.uri(), "/index.html");
+    assert_eq!(r.headers()["User-Agent"], "rust test");
+}
+
+#[test]
+fn read_in_pieces() {
+    let mut reader = PieceReader {
+        data: b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\nnext",
+        ready: false,
+    };
+    let r = block_on(read_request_async(&mut reader)).unwrap();
+    assert_eq!(r.headers()["Host"], "localhost");
+    assert_eq!(reader.data, b"next");
+}
+
+#[test]
+fn read_errors() {
+    let mut input: &[u8] = b"GET@ / HTTP/1.1\r\n\r\n";
+    assert_eq!(block_on(read_request_async(&mut input)).unwrap_err(), ParseError::BadMethod);
+    let mut input: &[u8] = b"GET / HTTP/1.1\r\nHost: local";
+    assert_eq!(block_on(read_request_async(&mut input)).unwrap_err(),
+               ParseError::UnexpectedEof);
+    let mut reader = FailingReader(io::ErrorKind::TimedOut);
+    assert_eq!(block_on(read_request_async(&mut reader)).unwrap_err(), ParseError::Timeout);
+    let mut reader = FailingReader(io::ErrorKind::ConnectionReset);
+    assert_eq!(block_on(read_request_async(&mut reader)).unwrap_err(),
+               ParseError::UnexpectedEof);
+}
+
+// ****************************************************************************
+//
+// Private Functions
+//
+// ****************************************************************************
+
+impl AsyncRead for PieceReader {
+    fn poll_read(mut self: Pin<&mut Self>,
+                 cx: &mut Context,
+                 buf: &mut ReadBuf)
+                 -> Poll<io::Result<()>> {
+        self.ready = !self.ready;
+        if !self.ready {
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        let n = self.data.len().min(buf.remaining()).min(3);
+        buf.put_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for FailingReader {
+    fn poll_read(self: Pin<&mut Self>, _: &mut Context, _: &mut ReadBuf) -> Poll<io::Result<()>> {
+        Poll::Ready(Err(io::Error::new(self.0, "failed")))
+    }
+}
+
+fn block_on<F: ::std::future::Future>(future: F) -> F::Output {
+    runtime::Builder::new_current_thread().build().unwrap().block_on(future)
+}
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
@@ -0,0 +1,472 @@
+//! # The rushttp Rust HTTP Library - Body Tests
+//!
+//! Unit tests for reading request bodies.
+
+// ****************************************************************************
+//
+// Imports
+//
+// ****************************************************************************
+
+use std::io;
+#[cfg(feature = "gzip")]
+use std::io::Write;
+
+#[cfg(feature = "gzip")]
+use flate2::write::{GzEncoder, ZlibEncoder};
+#[cfg(feature = "gzip")]
+use flate2::Compression;
+
+use super::super::body::*;
+use super::super::request::*;
+
+// ****************************************************************************
+//
+// Public Functions
+//
+// ****************************************************************************
+
+#[test]
+fn read_exact_body() {
+    let test = b"POST / HTTP/1.1\r\nContent-Length: 12\r\n\r\nHello";
+    let (r, used) = parse(test);
+    let mut rest: &[u8] = b", world";
+    let body = read_body(&r, &test[used..], &mut rest, 1024).unwrap();
+    assert_eq!(body, b"Hello, world");
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn read_body_over_limit() {
+    let test = b"POST / HTTP/1.1\r\nContent-Length: 12\r\n\r\nHello";
+    let (r, used) = parse(test);
+    let mut rest: &[u8] = b", world";
+    match read_body(&r, &test[used..], &mut rest, 11) {
+        Err(BodyError::TooLarge) => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn read_truncated_body() {
+    let test = b"POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\nHello";
+    let (r, used) = parse(test);
+    let mut rest: &[u8] = b", wo";
+    match read_body(&r, &test[used..], &mut rest, 1024) {
+        Err(BodyError::Truncated) => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+#[cfg(target_pointer_width = "64")]
+fn read_truncated_huge_body() {
+    // We mustn't try to make room for a terabyte before any of it arrives
+    let test = b"POST / HTTP/1.1\r\nContent-Length: 1099511627776\r\n\r\nHello";
+    let (r, used) = parse(test);
+    let mut rest: &[u8] = b", wo";
+    match read_body(&r, &test[used..], &mut rest, usize::MAX) {
+        Err(BodyError::Truncated) => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn read_body_ignores_pipelined() {
+    let test = b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nHelloGET / HTTP/1.1\r\n\r\n";
+    let (r, used) = parse(test);
+    let mut rest: &[u8] = b"";
+    let body = read_body(&r, &test[used..], &mut rest, 1024).unwrap();
+    assert_eq!(body, b"Hello");
+}
+
+#[test]
+fn continue_sent_before_body() {
+    let test = b"PUT /f HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n";
+    let (r, used) = parse(test);
+    assert!(expects_continue(&r));
+    let mut stream = ContinueStream {
+        body: b"Hello",
+        written: Vec::new(),
+    };
+    let body = read_body_with_continue(&r, &test[used..], &mut stream, 1024).unwrap();
+    assert_eq!(body, b"Hello");
+    assert_eq!(stream.written, b"HTTP/1.1 100 Continue\r\n\r\n");
+}
+
+#[test]
+fn no_continue_without_expect() {
+    let test = b"PUT /f HTTP/1.1\r\nContent-Length: 5\r\n\r\nHello";
+    let (r, used) = parse(test);
+    assert!(!expects_continue(&r));
+    let mut stream = ContinueStream {
+        body: b"",
+        written: Vec::new(),
+    };
+    let body = read_body_with_continue(&r, &test[used..], &mut stream, 1024).unwrap();
+    assert_eq!(body, b"Hello");
+    assert!(stream.written.is_empty());
+}
+
+#[test]
+fn no_continue_when_too_large() {
+    let test = b"PUT /f HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5000\r\n\r\n";
+    let (r, used) = parse(test);
+    let mut stream = ContinueStream {
+        body: b"",
+        written: Vec::new(),
+    };
+    match read_body_with_continue(&r, &test[used..], &mut stream, 1024) {
+        Err(BodyError::TooLarge) => {}
+        _ => panic!(),
+    }
+    assert!(stream.written.is_empty());
+}
+
+#[test]
+fn zero_length_body_completes() {
+    let test = b"POST /f HTTP/1.1\r\nContent-Length: 0\r\n\r\n";
+    let (r, used) = parse(test);
+    assert_eq!(used, test.len());
+    assert!(!has_body(&r));
+    // Nothing more should be read from the stream
+    let mut rest: &[u8] = b"GET / HTTP/1.1\r\n\r\n";
+    let body = read_body(&r, &test[used..], &mut rest, 1024).unwrap();
+    assert!(body.is_empty());
+    assert_eq!(rest, b"GET / HTTP/1.1\r\n\r\n");
+}
+
+#[test]
+fn zero_length_body_with_pipelined() {
+    let test = b"POST /f HTTP/1.1\r\nContent-Length: 0\r\n\r\nGET /next HTTP/1.1\r\n\r\n";
+    let (r, used) = parse(test);
+    let mut rest: &[u8] = b"";
+    let body = read_body(&r, &test[used..], &mut rest, 1024).unwrap();
+    assert!(body.is_empty());
+    let (next, _) = parse(&test[used..]);
+    assert_eq!(next.uri().path(), "/next");
+}
+
+#[test]
+fn no_continue_for_empty_body() {
+    let test = b"PUT /f HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 0\r\n\r\n";
+    let (r, used) = parse(test);
+    let mut stream = ContinueStream {
+        body: b"",
+        written: Vec::new(),
+    };
+    let body = read_body_with_continue(&r, &test[used..], &mut stream, 1024).unwrap();
+    assert!(body.is_empty());
+    assert!(stream.written.is_empty());
+}
+
+#[test]
+fn read_chunked_with_trailer() {
+    let test = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTrailer: Checksum\r\n\r\n\
+                 5\r\nHello\r\n7;ext=1\r\n, world\r\n0\r\n";
+    let mut ctx = Parser::new();
+    let used = match ctx.parse(test) {
+        ParseResult::Complete(r, used) => {
+            assert!(has_body(&r));
+            used
+        }
+        _ => panic!(),
+    };
+    let mut rest: &[u8] = b"Checksum: abc123\r\nExpires: never\r\n\r\nGET / HTTP/1.1";
+    let (body, next) = read_chunked_body(&mut ctx, &test[used..], &mut rest, 1024).unwrap();
+    assert_eq!(body, b"Hello, world");
+    assert!(next.is_empty());
+    // Expires wasn't announced, so it's dropped
+    assert_eq!(ctx.trailers(),
+               &[("Checksum".to_string(), b"abc123".to_vec())][..]);
+    assert_eq!(rest, b"GET / HTTP/1.1");
+}
+
+#[test]
+fn read_chunked_pipelined() {
+    // The next request arrived in the same read as the end of the body
+    let test = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+                 5\r\nHello\r\n0\r\n\r\nGET /next HTTP/1.1\r\n\r\n";
+    let mut ctx = Parser::new();
+    let used = match ctx.parse(test) {
+        ParseResult::Complete(_, used) => used,
+        _ => panic!(),
+    };
+    let mut rest: &[u8] = b"";
+    let (body, next) = read_chunked_body(&mut ctx, &test[used..], &mut rest, 1024).unwrap();
+    assert_eq!(body, b"Hello");
+    assert_eq!(next, b"GET /next HTTP/1.1\r\n\r\n");
+    let mut ctx = Parser::new();
+    match ctx.parse(&next) {
+        ParseResult::Complete(r, _) => assert_eq!(r.uri(), "/next"),
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn read_chunked_over_limit() {
+    let test = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nHello\r\n";
+    let mut ctx = Parser::new();
+    let used = match ctx.parse(test) {
+        ParseResult::Complete(_, used) => used,
+        _ => panic!(),
+    };
+    let mut rest: &[u8] = b"7\r\n, world\r\n0\r\n\r\n";
+    match read_chunked_body(&mut ctx, &test[used..], &mut rest, 11) {
+        Err(BodyError::TooLarge) => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn read_chunked_over_total_budget() {
+    // 47 octets of headers leaves room for 8 octets of body
+    let test = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nHello\r\n";
+    let mut ctx = Parser::new();
+    ctx.max_total_bytes(55);
+    let used = match ctx.parse(test) {
+        ParseResult::Complete(_, used) => used,
+        _ => panic!(),
+    };
+    assert_eq!(ctx.bytes_remaining(), Some(8));
+    let mut rest: &[u8] = b"7\r\n, world\r\n0\r\n\r\n";
+    match read_chunked_body(&mut ctx, &test[used..], &mut rest, 1024) {
+        Err(BodyError::TooLarge) => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+#[cfg(target_pointer_width = "64")]
+fn read_chunked_truncated_huge_chunk() {
+    let test = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n10000000000\r\nHello";
+    let mut ctx = Parser::new();
+    let used = match ctx.parse(test) {
+        ParseResult::Complete(_, used) => used,
+        _ => panic!(),
+    };
+    let mut rest: &[u8] = b", wo";
+    match read_chunked_body(&mut ctx, &test[used..], &mut rest, usize::MAX) {
+        Err(BodyError::Truncated) => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn read_chunked_endless_trailer() {
+    let test = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\nX-Junk: ";
+    let mut ctx = Parser::new();
+    let used = match ctx.parse(test) {
+        ParseResult::Complete(_, used) => used,
+        _ => panic!(),
+    };
+    // There's no total budget, but the trailer line can't go on forever
+    let mut rest = io::repeat(b'a');
+    match read_chunked_body(&mut ctx, &test[used..], &mut rest, 1024) {
+        Err(BodyError::TooLarge) => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn read_chunked_too_many_trailers() {
+    let test = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTrailer: A\r\n\r\n0\r\n";
+    let mut ctx = Parser::new();
+    ctx.max_header_count(3);
+    let used = match ctx.parse(test) {
+        ParseResult::Complete(_, used) => used,
+        _ => panic!(),
+    };
+    let mut rest: &[u8] = b"A: 1\r\nB: 2\r\nC: 3\r\nD: 4\r\n\r\n";
+    match read_chunked_body(&mut ctx, &test[used..], &mut rest, 1024) {
+        Err(BodyError::BadTrailer) => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn read_chunked_bad_size() {
+    let test = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nxyz\r\nHello\r\n";
+    let mut ctx = Parser::new();
+    let used = match ctx.parse(test) {
+        ParseResult::Complete(_, used) => used,
+        _ => panic!(),
+    };
+    let mut rest: &[u8] = b"";
+    match read_chunked_body(&mut ctx, &test[used..], &mut rest, 1024) {
+        Err(BodyError::BadChunk) => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn read_chunked_with_extensions() {
+    let test = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+                 5;name=value\r\nHello\r\n7 ; flag ;q=\"a;\\\"b\"\r\n, world\r\n0;last\r\n\r\n";
+    let mut ctx = Parser::new();
+    let used = match ctx.parse(test) {
+        ParseResult::Complete(_, used) => used,
+        _ => panic!(),
+    };
+    let mut rest: &[u8] = b"";
+    let mut seen = Vec::new();
+    let (body, _) = read_chunked_body_with_extensions(&mut ctx,
+                                                 &test[used..],
+                                                 &mut rest,
+                                                 1024,
+                                                      |name, value| {
+                                                          seen.push((name.to_string(),
+                                                                     value.map(|v| v.to_string())))
+                                                      })
+        .unwrap();
+    assert_eq!(body, b"Hello, world");
+    assert_eq!(seen,
+               [("name".to_string(), Some("value".to_string())),
+                ("flag".to_string(), None),
+                ("q".to_string(), Some("a;\"b".to_string())),
+                ("last".to_string(), None)]);
+    // And by default they're ignored
+    let mut ctx = Parser::new();
+    ctx.parse(test);
+    let mut rest: &[u8] = b"";
+    assert_eq!(read_chunked_body(&mut ctx, &test[used..], &mut rest, 1024).unwrap().0,
+               b"Hello, world");
+}
+
+#[test]
+fn read_chunked_bad_extension() {
+    let tests: [&[u8]; 6] = [b"+5\r\nHello\r\n0\r\n\r\n",
+                             b";x\r\nHello\r\n0\r\n\r\n",
+                             b"5;\r\nHello\r\n0\r\n\r\n",
+                             b"5;a=\r\nHello\r\n0\r\n\r\n",
+                             b"5;a=\"open\r\nHello\r\n0\r\n\r\n",
+                             b"5;a b=c\r\nHello\r\n0\r\n\r\n"];
+    for test in &tests {
+        let mut ctx = Parser::new();
+        ctx.parse(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n");
+        let mut rest: &[u8] = b"";
+        match read_chunked_body(&mut ctx, test, &mut rest, 1024) {
+            Err(BodyError::BadChunk) => {}
+            e => panic!("{:?} {:?}", String::from_utf8_lossy(test), e),
+        }
+    }
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn decode_gzip_body() {
+    let (r, _) = parse(b"POST / HTTP/1.1\r\nContent-Encoding: gzip\r\n\r\n");
+    assert_eq!(decode_body(&r, gzip(b"Hello, world")).unwrap(), b"Hello, world");
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn decode_deflate_body() {
+    let (r, _) = parse(b"POST / HTTP/1.1\r\nContent-Encoding: deflate\r\n\r\n");
+    assert_eq!(decode_body(&r, deflate(b"Hello, world")).unwrap(), b"Hello, world");
+    // Stacked encodings come off in reverse order
+    let (r, _) = parse(b"POST / HTTP/1.1\r\nContent-Encoding: deflate, GZIP\r\n\r\n");
+    assert_eq!(decode_body(&r, gzip(&deflate(b"Hello, world"))).unwrap(), b"Hello, world");
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn decode_identity_body() {
+    for test in &[&b"POST / HTTP/1.1\r\n\r\n"[..],
+                  &b"POST / HTTP/1.1\r\nContent-Encoding: identity\r\n\r\n"[..]] {
+        let (r, _) = parse(test);
+        assert_eq!(decode_body(&r, b"Hello".to_vec()).unwrap(), b"Hello");
+    }
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn decode_body_errors() {
+    let (r, _) = parse(b"POST / HTTP/1.1\r\nContent-Encoding: br\r\n\r\n");
+    match decode_body(&r, b"Hello".to_vec()) {
+        Err(BodyError::UnsupportedEncoding) => {}
+        e => panic!("{:?}", e),
+    }
+    let (r, _) = parse(b"POST / HTTP/1.1\r\nContent-Encoding: gzip\r\n\r\n");
+    match decode_body(&r, b"Hello".to_vec()) {
+        Err(BodyError::BadEncoding) => {}
+        e => panic!("{:?}", e),
+    }
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn decode_body_too_large() {
+    // A megabyte of zeros compresses to about a kilobyte
+    let bomb = gzip(&vec![0u8; 1024 * 1024]);
+    assert!(bomb.len() < 4096);
+    let (r, _) = parse(b"POST / HTTP/1.1\r\nContent-Encoding: gzip\r\n\r\n");
+    match decode_body_limited(&r, bomb.clone(), 1024 * 1024 - 1) {
+        Err(BodyError::TooLarge) => {}
+        e => panic!("{:?}", e),
+    }
+    assert_eq!(decode_body_limited(&r, bomb, 1024 * 1024).unwrap().len(), 1024 * 1024);
+}
+
+// ****************************************************************************
+//
+// Private Types
+//
+// ****************************************************************************
+
+/// A client which won't send its body until it has seen `100 Continue`.
+struct ContinueStream {
+    body: &'static [u8],
+    written: Vec<u8>,
+}
+
+impl io::Read for ContinueStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        assert!(self.written.starts_with(b"HTTP/1.1 100 Continue\r\n\r\n"));
+        self.body.read(buf)
+    }
+}
+
+impl io::Write for ContinueStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// ****************************************************************************
+//
+// Private Functions
+//
+// ****************************************************************************
+
+fn parse(test: &[u8]) -> (Request, usize) {
+    let mut ctx = Parser::new();
+    match ctx.parse(test) {
+        ParseResult::Complete(r, used) => (r, used),
+        _ => panic!(),
+    }
+}
+
+#[cfg(feature = "gzip")]
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[cfg(feature = "gzip")]
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
@@ -0,0 +1,81 @@
+//! # The rushttp Rust HTTP Library - Cookie Tests
+//!
+//! Unit tests for `Cookie` header handling.
+
+// ****************************************************************************
+//
+// Imports
+//
+// ****************************************************************************
+
+use super::super::cookie::*;
+use super::super::request::*;
+
+// ****************************************************************************
+//
+// Public Functions
+//
+// ****************************************************************************
+
+#[test]
+fn single_cookie() {
+    let r = parse(b"GET / HTTP/1.1\r\nCookie: session=abc123\r\n\r\n");
+    assert_eq!(cookies(&r), [("session".to_string(), "abc123".to_string())]);
+}
+
+#[test]
+fn multiple_cookies() {
+    let r = parse(b"GET / HTTP/1.1\r\nCookie: a=1; b=2;c = 3\r\nCookie: d=%20\r\n\r\n");
+    assert_eq!(cookies(&r),
+               [("a".to_string(), "1".to_string()),
+                ("b".to_string(), "2".to_string()),
+                ("c".to_string(), "3".to_string()),
+                ("d".to_string(), "%20".to_string())]);
+}
+
+#[test]
+fn quoted_cookie() {
+    let r = parse(b"GET / HTTP/1.1\r\nCookie: theme=\"dark mode\"; lang=\"\"\r\n\r\n");
+    assert_eq!(cookies(&r),
+               [("theme".to_string(), "dark mode".to_string()),
+                ("lang".to_string(), "".to_string())]);
+}
+
+#[test]
+fn quoted_cookie_keeps_backslashes() {
+    // Cookie values have no escapes, so only the quotes go
+    let r = parse(b"GET / HTTP/1.1\r\nCookie: a=\"x\\\"y\"; b=\"x\\\\y\"\r\n\r\n");
+    assert_eq!(cookies(&r),
+               [("a".to_string(), "x\\\"y".to_string()),
+                ("b".to_string(), "x\\\\y".to_string())]);
+}
+
+#[test]
+fn empty_cookie_header() {
+    let r = parse(b"GET / HTTP/1.1\r\nCookie:\r\n\r\n");
+    assert!(cookies(&r).is_empty());
+    let r = parse(b"GET / HTTP/1.1\r\nCookie: ; novalue; =x\r\n\r\n");
+    assert!(cookies(&r).is_empty());
+    let r = parse(b"GET / HTTP/1.1\r\n\r\n");
+    assert!(cookies(&r).is_empty());
+}
+
+// ****************************************************************************
+//
+// Private Functions
+//
+// ****************************************************************************
+
+fn parse(test: &[u8]) -> Request {
+    let mut ctx = Parser::new();
+    match ctx.parse(test) {
+        ParseResult::Complete(r, _) => r,
+        _ => panic!(),
+    }
+}
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
@@ -8,9 +8,31 @@
 //
 // ****************************************************************************
 
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::io;
+#[cfg(feature = "gzip")]
+use std::io::Read;
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+#[cfg(feature = "gzip")]
+use flate2::read::GzDecoder;
+
 use super::request::*;
+use super::response::*;
+use super::testing::*;
 use super::*;
 
+#[cfg(feature = "async")]
+mod async_io;
+mod auth;
+mod body;
+mod cookie;
+mod negotiation;
+mod query;
+mod routing;
+
 // ****************************************************************************
 //
 // Public Types
@@ -25,7 +47,80 @@ use super::*;
 //
 // ****************************************************************************
 
-// None
+/// A writer which only ever accepts one octet at a time.
+struct OneByteWriter {
+    data: Vec<u8>,
+}
+
+impl io::Write for OneByteWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match buf.first() {
+            Some(b) => {
+                self.data.push(*b);
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Remembers whether it was flushed, and can be told to fail the flush.
+struct FlushWriter {
+    data: Vec<u8>,
+    flushed: bool,
+    fail_flush: bool,
+}
+
+impl io::Write for FlushWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.flushed = false;
+        self.data.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.fail_flush {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "flush failed"));
+        }
+        self.flushed = true;
+        Ok(())
+    }
+}
+
+/// Waits before each read, then hands out its data a little at a time.
+struct SlowReader {
+    delay: Duration,
+    data: &'static [u8],
+}
+
+impl io::Read for SlowReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        thread::sleep(self.delay);
+        let n = buf.len().min(self.data.len()).min(8);
+        buf[..n].copy_from_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        Ok(n)
+    }
+}
+
+// ****************************************************************************
+//
+// Private Data
+//
+// ****************************************************************************
+
+/// Requests for the split-boundary tests, good and bad.
+const SPLIT_TESTS: [&[u8]; 7] =
+    [b"GET /index.html HTTP/1.1\r\nUser-Agent: rust test\r\nHost: localhost\r\n\r\n",
+     b"\r\n\r\nPUT /a%20b?c=d HTTP/1.0\nContent-Length: 5\n\nHello",
+     b"GET / HTTP/1.1\r\nX-Name: J\xc3\xbcrgen \xe2\x9c\x93\r\n\r\n",
+     b"GET / HTTP/1.1\r\nX-Folded: one\r\n\ttwo\r\n three\r\nHost: x\r\n\r\nGET / HTTP/1.1\r\n",
+     b"GET / HTTP/1.1\r\nX-Value:\ta\tb \r\n\r\n",
+     b"GET / HTTP/9.9\r\n\r\n",
+     b"GET / HTTP/1.1\r\nBad Header: x\r\n\r\n"];
 
 // ****************************************************************************
 //
@@ -106,6 +201,35 @@ fn get_complete_wrapped_header() {
     }
 }
 
+#[test]
+fn too_many_fold_lines() {
+    let mut ctx = Parser::new();
+    ctx.max_fold_lines(8);
+    let mut test = b"GET / HTTP/1.1\r\nX-Folded: start\r\n".to_vec();
+    for _ in 0..10 {
+        test.extend_from_slice(b" more\r\n");
+    }
+    test.extend_from_slice(b"Host: localhost\r\n\r\n");
+    match ctx.parse(&test) {
+        ParseResult::ErrorObsFold => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn fold_lines_counted_per_header() {
+    let mut ctx = Parser::new();
+    ctx.max_fold_lines(2);
+    let test = b"GET / HTTP/1.1\r\nX-A: a\r\n 1\r\n 2\r\nX-B: b\r\n 1\r\n 2\r\n\r\n";
+    match ctx.parse(test) {
+        ParseResult::Complete(r, _) => {
+            assert_eq!(r.headers()["X-A"], "a 1 2");
+            assert_eq!(r.headers()["X-B"], "b 1 2");
+        }
+        _ => panic!(),
+    }
+}
+
 #[test]
 fn put_complete_header() {
     let mut ctx = Parser::new();
@@ -133,6 +257,76 @@ fn put_complete_header() {
     }
 }
 
+#[test]
+fn keep_alive_checks() {
+    let tests: [(&[u8], bool); 6] =
+        [(b"GET / HTTP/1.1\r\n\r\n", true),
+         (b"GET / HTTP/1.1\r\nConnection: Upgrade, Close\r\n\r\n", false),
+         (b"GET / HTTP/1.1\r\nConnection: keep-alive\r\n\r\n", true),
+         (b"GET / HTTP/1.0\r\n\r\n", false),
+         (b"GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n", true),
+         (b"GET / HTTP/1.0\r\nConnection: close\r\n\r\n", false)];
+    for &(test, expected) in &tests {
+        assert_eq!(keep_alive(&parse_complete(test)), expected, "{:?}", test);
+    }
+}
+
+#[test]
+fn multipart_boundaries() {
+    let long = "x".repeat(71);
+    let tests: Vec<(String, Option<&str>)> =
+        vec![("multipart/form-data; boundary=----WebKitFormBoundary7MA4YWxk".to_string(),
+              Some("----WebKitFormBoundary7MA4YWxk")),
+             ("Multipart/Mixed; charset=utf-8; BOUNDARY=\"gc0p4Jq0M2Yt08j=34 c0\"".to_string(),
+              Some("gc0p4Jq0M2Yt08j=34 c0")),
+             (format!("multipart/mixed; boundary={}", &long[1..]), Some(&long[1..])),
+             // Too long
+             (format!("multipart/mixed; boundary={}", long), None),
+             // Not allowed characters, or a space at the end
+             ("multipart/mixed; boundary=\"a@b\"".to_string(), None),
+             ("multipart/mixed; boundary=\"ab \"".to_string(), None),
+             // Missing or empty
+             ("multipart/mixed".to_string(), None),
+             ("multipart/mixed; boundary=\"\"".to_string(), None),
+             ("multipart/mixed; boundary=\"open".to_string(), None),
+             // Not multipart
+             ("text/plain; boundary=abc".to_string(), None)];
+    for (content_type, expected) in tests {
+        let r = parse_complete(format!("POST / HTTP/1.1\r\nContent-Type: {}\r\n\r\n",
+                                       content_type)
+                                   .as_bytes());
+        assert_eq!(multipart_boundary(&r).as_deref(),
+                   expected,
+                   "{}",
+                   content_type);
+    }
+    assert_eq!(multipart_boundary(&parse_complete(b"POST / HTTP/1.1\r\n\r\n")), None);
+}
+
+#[test]
+fn has_body_checks() {
+    let mut ctx = Parser::new();
+    match ctx.parse(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n") {
+        ParseResult::Complete(r, _) => assert!(!request::has_body(&r)),
+        _ => panic!(),
+    }
+    let mut ctx = Parser::new();
+    match ctx.parse(b"POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\n") {
+        ParseResult::Complete(r, _) => assert!(request::has_body(&r)),
+        _ => panic!(),
+    }
+    let mut ctx = Parser::new();
+    match ctx.parse(b"POST / HTTP/1.1\r\nContent-Length: 0\r\n\r\n") {
+        ParseResult::Complete(r, _) => assert!(!request::has_body(&r)),
+        _ => panic!(),
+    }
+    let mut ctx = Parser::new();
+    match ctx.parse(b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip, Chunked\r\n\r\n") {
+        ParseResult::Complete(r, _) => assert!(request::has_body(&r)),
+        _ => panic!(),
+    }
+}
+
 #[test]
 fn incomplete_header() {
     let mut ctx = Parser::new();
@@ -160,18 +354,2363 @@ fn bad_header() {
     let mut ctx = Parser::new();
     let test = b"GET /index.html HTTP/1.1\r\nUser-Agent: rust test\r\nHost\r\n\r\n";
     match ctx.parse(test) {
+        ParseResult::ErrorBadHeader => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn bad_header_value_lf_in_wrapped_value() {
+    let mut ctx = Parser::new();
+    let test = b"GET / HTTP/1.1\r\nUser-Agent: rust\r\n test\nHost: localhost\r\n\r\n";
+    match ctx.parse(test) {
+        ParseResult::ErrorBadHeaderValue => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn bad_header_value_cr_without_lf() {
+    let mut ctx = Parser::new();
+    let test = b"GET / HTTP/1.1\r\nHost: localhost\rUser-Agent: rust\r\n\r\n";
+    match ctx.parse(test) {
+        ParseResult::ErrorBadHeaderValue => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn bad_header_value_wrapped_cr_without_lf() {
+    let mut ctx = Parser::new();
+    let test = b"GET / HTTP/1.1\r\nUser-Agent: rust\r\n test\rHost: localhost\r\n\r\n";
+    match ctx.parse(test) {
+        ParseResult::ErrorBadHeaderValue => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn bad_header_value_control_character() {
+    let mut ctx = Parser::new();
+    let test = b"GET / HTTP/1.1\r\nUser-Agent: rust\x01test\r\n\r\n";
+    match ctx.parse(test) {
+        ParseResult::ErrorBadHeaderValue => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn bad_request_line_errors() {
+    let mut ctx = Parser::new();
+    match ctx.parse(b"GET\r\n\r\n") {
+        ParseResult::ErrorBadMethod => {}
+        _ => panic!(),
+    }
+    let mut ctx = Parser::new();
+    match ctx.parse(b"GET /index.html\r\n\r\n") {
+        ParseResult::ErrorBadProtocol => {}
+        _ => panic!(),
+    }
+    let mut ctx = Parser::new();
+    match ctx.parse(b"GET / HTTP/1.1\rHost: localhost\r\n\r\n") {
+        ParseResult::ErrorBadProtocol => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn headers_hook_rejects() {
+    let mut ctx = Parser::new();
+    ctx.on_headers_complete(|headers| {
+        if headers.iter().any(|(k, _)| k.eq_ignore_ascii_case("Authorization")) {
+            HeaderDecision::Continue
+        } else {
+            HeaderDecision::Reject(ParseError::BadHeader)
+        }
+    });
+    let test = b"POST /upload HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nHello";
+    match ctx.parse(test) {
+        ParseResult::ErrorBadHeader => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn headers_hook_rejects_incomplete() {
+    let mut ctx = Parser::new();
+    ctx.on_headers_complete(|_| HeaderDecision::Reject(ParseError::Incomplete));
+    match ctx.parse(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n") {
+        ParseResult::Error => {}
+        e => panic!("{:?}", e),
+    }
+}
+
+#[test]
+fn headers_hook_continues() {
+    let mut ctx = Parser::new();
+    ctx.on_headers_complete(|headers| {
+        if headers.iter().any(|(k, _)| k.eq_ignore_ascii_case("Authorization")) {
+            HeaderDecision::Continue
+        } else {
+            HeaderDecision::Reject(ParseError::BadHeader)
+        }
+    });
+    let test = b"GET / HTTP/1.1\r\nAuthorization: Basic Zm9vOmJhcg==\r\n\r\n";
+    match ctx.parse(test) {
+        ParseResult::Complete(r, _) => assert_eq!(r.headers().len(), 1),
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn raw_headers_keep_duplicates() {
+    let mut ctx = Parser::new();
+    let test = b"GET / HTTP/1.1\r\nAccept: text/html\r\nHost: localhost\r\nAccept: \xfftext/plain\r\n\r\n";
+    match ctx.parse(test) {
+        ParseResult::Complete(r, _) => assert_eq!(r.headers().get_all("Accept").iter().count(), 2),
+        _ => panic!(),
+    }
+    let headers = ctx.into_raw_headers();
+    assert_eq!(headers,
+               vec![("Accept".to_string(), b"text/html".to_vec()),
+                    ("Host".to_string(), b"localhost".to_vec()),
+                    ("Accept".to_string(), b"\xfftext/plain".to_vec())]);
+}
+
+#[test]
+fn leading_empty_lines_skipped() {
+    let mut ctx = Parser::new();
+    let test = b"\r\n\nGET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    match ctx.parse(test) {
+        ParseResult::Complete(r, c) => {
+            assert_eq!(c, test.len());
+            assert_eq!(*r.method(), http::Method::GET);
+            assert_eq!(r.uri(), "/");
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn too_many_leading_empty_lines() {
+    let mut ctx = Parser::new();
+    let mut test = b"\r\n".repeat(9);
+    test.extend_from_slice(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    match ctx.parse(&test) {
         ParseResult::Error => {}
         _ => panic!(),
     }
 }
 
-// ****************************************************************************
-//
-// Private Functions
-//
-// ****************************************************************************
+#[test]
+fn extra_blank_lines_after_headers() {
+    for &fast in &[false, true] {
+        // The second blank line isn't ours
+        let test = b"GET / HTTP/1.1\r\n\r\n\r\n";
+        let mut ctx = Parser::new();
+        ctx.fast_path(fast);
+        match ctx.parse(test) {
+            ParseResult::Complete(_, used) => assert_eq!(&test[used..], b"\r\n"),
+            e => panic!("{:?}", e),
+        }
+        // With a body, they're the start of the body
+        let test = b"POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\n\r\nab";
+        let mut ctx = Parser::new();
+        ctx.fast_path(fast);
+        let (r, used) = ctx.parse(test).into_result().unwrap();
+        let mut rest: &[u8] = b"";
+        assert_eq!(super::body::read_body(&r, &test[used..], &mut rest, 1024).unwrap(),
+                   b"\r\nab");
+        // Without one, the next request skips them
+        let test = b"GET /a HTTP/1.1\r\n\r\n\r\n\r\nGET /b HTTP/1.1\r\n\r\n";
+        let mut ctx = Parser::new();
+        ctx.fast_path(fast);
+        let used = match ctx.parse(test) {
+            ParseResult::Complete(r, used) => {
+                assert_eq!(r.uri(), "/a");
+                used
+            }
+            e => panic!("{:?}", e),
+        };
+        assert_eq!(used, 19);
+        ctx.reset();
+        match ctx.parse(&test[used..]) {
+            ParseResult::Complete(r, used2) => {
+                assert_eq!(r.uri(), "/b");
+                assert_eq!(used + used2, test.len());
+            }
+            e => panic!("{:?}", e),
+        }
+    }
+    match request::parse_once(b"GET / HTTP/1.1\r\n\r\n\r\n") {
+        OnceResult::Complete(_, used) => assert_eq!(used, 18),
+        _ => panic!(),
+    }
+}
 
-// None
+#[test]
+fn lenient_request_line() {
+    let test = b"GET  /path   HTTP/1.1\r\nHost: x\r\n\r\n";
+    for &fast in &[false, true] {
+        let mut ctx = Parser::new();
+        ctx.fast_path(fast);
+        match ctx.parse(test) {
+            ParseResult::ErrorBadURL => {}
+            e => panic!("{:?}", e),
+        }
+        let mut ctx = Parser::new();
+        ctx.fast_path(fast).lenient_request_line(true);
+        match ctx.parse(test) {
+            ParseResult::Complete(r, used) => {
+                assert_eq!(*r.method(), http::Method::GET);
+                assert_eq!(r.uri(), "/path");
+                assert_eq!(r.version(), http::Version::HTTP_11);
+                assert_eq!(used, test.len());
+            }
+            e => panic!("{:?}", e),
+        }
+        // Only spaces, and only between the tokens
+        let mut ctx = Parser::new();
+        ctx.fast_path(fast).lenient_request_line(true);
+        match ctx.parse(b"GET \t/path HTTP/1.1\r\n\r\n") {
+            ParseResult::ErrorBadURL => {}
+            e => panic!("{:?}", e),
+        }
+        let mut ctx = Parser::new();
+        ctx.fast_path(fast).lenient_request_line(true);
+        match ctx.parse(b"GET /path HTTP/1.1 \r\n\r\n") {
+            ParseResult::ErrorBadProtocol => {}
+            e => panic!("{:?}", e),
+        }
+    }
+}
+
+#[test]
+fn many_headers() {
+    let mut test = b"GET / HTTP/1.1\r\n".to_vec();
+    for i in 0..40 {
+        test.extend_from_slice(format!("X-Header-{}: value {}\r\n", i, i).as_bytes());
+    }
+    test.extend_from_slice(b"Accept: a\r\nAccept: b\r\n\r\n");
+    let mut ctx = Parser::new();
+    assert_eq!(ctx.headers_len(), 0);
+    assert!(ctx.parse(&test[..test.len() - 2]).is_in_progress());
+    assert_eq!(ctx.headers_len(), 42);
+    let (r, _) = ctx.parse(b"\r\n").into_result().unwrap();
+    assert_eq!(r.headers().len(), 42);
+    assert_eq!(r.headers().keys_len(), 41);
+    for i in 0..40 {
+        assert_eq!(r.headers()[format!("x-header-{}", i).as_str()],
+                   format!("value {}", i).as_str());
+    }
+    let accept: Vec<_> = r.headers().get_all("Accept").iter().collect();
+    assert_eq!(accept, ["a", "b"]);
+}
+
+#[test]
+fn parse_once_complete() {
+    let test = b"POST /form?x=1 HTTP/1.1\r\nHost: localhost\r\nX-Pad:  padded \t\r\n\r\nbody";
+    match request::parse_once(test) {
+        OnceResult::Complete(r, c) => {
+            assert_eq!(c, test.len() - 4);
+            assert_eq!(r.method, "POST");
+            assert_eq!(r.uri, "/form?x=1");
+            assert_eq!(r.version, http::Version::HTTP_11);
+            assert_eq!(r.headers.len(), 2);
+            assert_eq!(r.header("host"), Some(&b"localhost"[..]));
+            assert_eq!(r.header("X-Pad"), Some(&b"padded"[..]));
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn parse_once_partial() {
+    let test = b"GET / HTTP/1.1\r\nHost: localhost\r\n";
+    match request::parse_once(test) {
+        OnceResult::Incomplete => {}
+        _ => panic!(),
+    }
+    match request::parse_once(b"GET / HTTP/1.1\r\nX: a\r\n b\r\n\r\n") {
+        OnceResult::Error(ParseError::ObsFold) => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn request_line_only() {
+    let test = b"GET /index.html?x=1 HTTP/1.0\r\nUser-Agent: rust test\r\nHost";
+    match Parser::parse_request_line(test) {
+        RequestLineResult::Complete(method, uri, version, used) => {
+            assert_eq!(method, http::Method::GET);
+            assert_eq!(uri, "/index.html?x=1");
+            assert_eq!(version, http::Version::HTTP_10);
+            assert_eq!(used, 30);
+            assert_eq!(&test[used..used + 4], b"User");
+        }
+        _ => panic!(),
+    }
+    match Parser::parse_request_line(b"GET /index.html HTTP/1.1\n") {
+        RequestLineResult::Complete(_, _, _, used) => assert_eq!(used, 25),
+        _ => panic!(),
+    }
+    match Parser::parse_request_line(b"GET /index.html HTTP/1.1\r") {
+        RequestLineResult::InProgress => {}
+        _ => panic!(),
+    }
+    match Parser::parse_request_line(b"GET /index.html HTTP/2.1\r\n") {
+        RequestLineResult::Error(ParseError::UnsupportedVersion) => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn with_capacity_does_not_grow() {
+    let mut ctx = Parser::with_capacity(64, 8);
+    let before = ctx.capacities();
+    let test = b"GET /index.html HTTP/1.1\r\nUser-Agent: rust test\r\nHost: localhost\r\n\r\n";
+    for _ in 0..3 {
+        match ctx.parse(test) {
+            ParseResult::Complete(r, _) => assert_eq!(r.headers().len(), 2),
+            _ => panic!(),
+        }
+        assert_eq!(ctx.capacities(), before);
+        ctx.reset();
+        assert_eq!(ctx.capacities(), before);
+    }
+}
+
+#[test]
+fn decoded_path_escapes() {
+    let mut ctx = Parser::new();
+    match ctx.parse(b"GET /a%20b/caf%C3%A9?x=%20 HTTP/1.1\r\n\r\n") {
+        ParseResult::Complete(r, _) => assert_eq!(request::decoded_path(&r).unwrap(), "/a b/café"),
+        _ => panic!(),
+    }
+    let mut ctx = Parser::new();
+    match ctx.parse(b"GET /a%2 HTTP/1.1\r\n\r\n") {
+        ParseResult::Complete(r, _) => assert_eq!(request::decoded_path(&r), None),
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn method_not_allowed_lists_methods() {
+    let response = HttpResponse::method_not_allowed(&["GET", "POST", "HEAD"]);
+    assert_eq!(response.status as u32, 405);
+    assert_eq!(response.headers["Allow"], "GET, POST, HEAD");
+    let mut out: Vec<u8> = Vec::new();
+    response.write(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(),
+               "HTTP/1.1 405 Method Not Allowed\r\nAllow: GET, POST, HEAD\r\n\r\n");
+}
+
+#[test]
+fn status_from_valid_code() {
+    let status = HttpResponseStatus::try_from(404).unwrap();
+    assert_eq!(status.as_string(), "Not Found");
+    assert_eq!(u16::from(status), 404);
+    let status: HttpResponseStatus = "418".parse().unwrap();
+    assert_eq!(status.as_u16(), 418);
+}
+
+#[test]
+fn status_from_unknown_code() {
+    let e = HttpResponseStatus::try_from(299).unwrap_err();
+    assert_eq!(e.to_string(), "Unknown HTTP status code \"299\"");
+    assert!("600".parse::<HttpResponseStatus>().is_err());
+}
+
+#[test]
+fn status_from_non_numeric() {
+    let e = "Not Found".parse::<HttpResponseStatus>().unwrap_err();
+    assert_eq!(e.to_string(), "Unknown HTTP status code \"Not Found\"");
+}
+
+#[test]
+fn http10_response_defaults_to_close() {
+    let response = HttpResponse::new_with_body(HttpResponseStatus::OK, "HTTP/1.0", "Hi");
+    let mut out: Vec<u8> = Vec::new();
+    response.write(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(),
+               "HTTP/1.0 200 OK\r\nConnection: close\r\n\r\nHi");
+}
+
+#[test]
+fn http11_response_has_no_default_connection() {
+    let response = HttpResponse::new_with_body(HttpResponseStatus::OK, "HTTP/1.1", "Hi");
+    let mut out: Vec<u8> = Vec::new();
+    response.write(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "HTTP/1.1 200 OK\r\n\r\nHi");
+}
+
+#[test]
+fn connection_header_overrides_default() {
+    let mut response = HttpResponse::new(HttpResponseStatus::OK, "HTTP/1.0");
+    response.add_header("connection", "keep-alive");
+    let mut out: Vec<u8> = Vec::new();
+    response.write(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(),
+               "HTTP/1.0 200 OK\r\nconnection: keep-alive\r\n\r\n");
+}
+
+#[test]
+fn response_header_raw_bytes() {
+    let mut response = HttpResponse::new(HttpResponseStatus::OK, "HTTP/1.1");
+    response.add_header("X-Legacy", b"caf\xe9 \x80\xff".to_vec());
+    assert_eq!(response.headers["X-Legacy"].to_str(), None);
+    let mut out: Vec<u8> = Vec::new();
+    response.write(&mut out).unwrap();
+    assert_eq!(out, b"HTTP/1.1 200 OK\r\nX-Legacy: caf\xe9 \x80\xff\r\n\r\n".to_vec());
+}
+
+#[test]
+fn response_survives_short_writes() {
+    let mut response = HttpResponse::new_with_body(HttpResponseStatus::OK, "HTTP/1.1", "Hello");
+    response.add_header("Content-Type", "text/plain");
+    let mut sink = OneByteWriter { data: Vec::new() };
+    let total = response.write(&mut sink).unwrap();
+    let expected = "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\nHello";
+    assert_eq!(total, expected.len());
+    assert_eq!(String::from_utf8(sink.data).unwrap(), expected);
+}
+
+#[test]
+fn write_flushes() {
+    let response = HttpResponse::new_with_body(HttpResponseStatus::OK, "HTTP/1.1", "Hello");
+    let mut sink = FlushWriter {
+        data: Vec::new(),
+        flushed: false,
+        fail_flush: false,
+    };
+    response.write(&mut sink).unwrap();
+    assert!(sink.flushed);
+    assert_eq!(sink.data, b"HTTP/1.1 200 OK\r\n\r\nHello");
+
+    let mut sink = FlushWriter {
+        data: Vec::new(),
+        flushed: false,
+        fail_flush: false,
+    };
+    let writer = response.write_chunked(&mut sink, &[]).unwrap();
+    writer.finish().unwrap();
+    assert!(sink.flushed);
+
+    let mut sink = FlushWriter {
+        data: Vec::new(),
+        flushed: false,
+        fail_flush: true,
+    };
+    let e = response.write(&mut sink).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+}
+
+#[test]
+fn partial_content_response() {
+    let mut response = HttpResponse::partial_content(0, 499, 1234);
+    response.body = "x".repeat(500).into();
+    assert_eq!(response.status as u32, 206);
+    assert_eq!(response.headers["Content-Range"], "bytes 0-499/1234");
+    let response = HttpResponse::partial_content(1233, 1233, 1234);
+    assert_eq!(response.headers["Content-Range"], "bytes 1233-1233/1234");
+}
+
+#[test]
+fn range_not_satisfiable_response() {
+    let response = HttpResponse::range_not_satisfiable(1234);
+    assert_eq!(response.status as u32, 416);
+    assert_eq!(response.headers["Content-Range"], "bytes */1234");
+    let mut out: Vec<u8> = Vec::new();
+    response.write(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(),
+               "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */1234\r\n\r\n");
+}
+
+#[test]
+fn write_headers_sorted() {
+    let mut response = HttpResponse::new_with_body(HttpResponseStatus::OK, "HTTP/1.0", "Hi");
+    response.add_header("x-zebra", "1");
+    response.add_header("Content-Type", "text/plain");
+    response.add_header("ETag", "\"abc\"");
+    response.add_header("accept-ranges", "bytes");
+    response.add_header("Date", "Tue, 15 Nov 1994 08:12:31 GMT");
+    for _ in 0..4 {
+        let mut out: Vec<u8> = Vec::new();
+        let total = response.write_sorted(&mut out).unwrap();
+        assert_eq!(total, out.len());
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "HTTP/1.0 200 OK\r\n\
+                    accept-ranges: bytes\r\n\
+                    Connection: close\r\n\
+                    Content-Type: text/plain\r\n\
+                    Date: Tue, 15 Nov 1994 08:12:31 GMT\r\n\
+                    ETag: \"abc\"\r\n\
+                    x-zebra: 1\r\n\
+                    \r\n\
+                    Hi");
+    }
+}
+
+#[test]
+fn ok_text_response() {
+    let response = HttpResponse::ok_text("Hello");
+    assert_eq!(response.status as u32, 200);
+    assert_eq!(response.headers["Content-Type"], "text/plain; charset=utf-8");
+    assert_eq!(response.body, "Hello");
+}
+
+#[test]
+fn ok_text_defaults_to_http_11() {
+    let response = HttpResponse::ok_text("hi");
+    assert_eq!(response.version(), Some(Version::HTTP_11));
+    assert_eq!(String::from_utf8(response.to_vec()).unwrap(),
+               "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\n\r\nhi");
+}
+
+#[test]
+fn response_with_version() {
+    let response = HttpResponse::ok_text("hi").with_version(Version::HTTP_10);
+    assert_eq!(response.protocol, "HTTP/1.0");
+    assert_eq!(response.version(), Some(Version::HTTP_10));
+    let out = String::from_utf8(response.to_vec()).unwrap();
+    assert!(out.starts_with("HTTP/1.0 200 OK\r\n"));
+    assert!(out.contains("Connection: close\r\n"));
+    let response = HttpResponse::new(HttpResponseStatus::NoContent, "").with_version(Version::HTTP_11);
+    assert_eq!(response.to_vec(), b"HTTP/1.1 204 No Content\r\n\r\n");
+}
+
+#[test]
+fn ok_html_response() {
+    let response = HttpResponse::ok_html(String::from("<p>Hello</p>"));
+    assert_eq!(response.status as u32, 200);
+    assert_eq!(response.headers["Content-Type"], "text/html; charset=utf-8");
+    assert_eq!(response.body, "<p>Hello</p>");
+}
+
+#[test]
+fn ok_json_response() {
+    let response = HttpResponse::ok_json(r#"{"hello": "world"}"#);
+    assert_eq!(response.status as u32, 200);
+    assert_eq!(response.headers["Content-Type"], "application/json");
+    assert_eq!(response.body, r#"{"hello": "world"}"#);
+    let mut out: Vec<u8> = Vec::new();
+    response.write(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(),
+               "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n{\"hello\": \"world\"}");
+}
+
+#[test]
+fn interim_then_final_response() {
+    let mut out: Vec<u8> = Vec::new();
+    let mut hints = HttpResponse::new_with_body(HttpResponseStatus::EarlyHints,
+                                                "HTTP/1.1",
+                                                "not sent");
+    hints.add_header("Link", "</style.css>; rel=preload; as=style");
+    hints.write_interim(&mut out).unwrap();
+    let response = HttpResponse::new_with_body(HttpResponseStatus::OK, "HTTP/1.1", "Hello");
+    response.write(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(),
+               "HTTP/1.1 103 Early Hints\r\nLink: </style.css>; rel=preload; as=style\r\n\r\n\
+                HTTP/1.1 200 OK\r\n\r\nHello");
+}
+
+#[test]
+fn early_hints_links() {
+    let hints = HttpResponse::early_hints(&[("/style.css", "preload"),
+                                            ("https://cdn.example.com", "preconnect")]);
+    assert_eq!(hints.status as u32, 103);
+    assert!(hints.headers.is_empty());
+    let mut out: Vec<u8> = Vec::new();
+    hints.write_interim(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out.matches("\r\nLink: ").count(), 2);
+    assert_eq!(out,
+               "HTTP/1.1 103 Early Hints\r\n\
+                Link: </style.css>; rel=preload\r\n\
+                Link: <https://cdn.example.com>; rel=preconnect\r\n\r\n");
+}
+
+#[test]
+#[should_panic]
+#[cfg(debug_assertions)]
+fn interim_must_be_1xx() {
+    let mut out: Vec<u8> = Vec::new();
+    HttpResponse::new(HttpResponseStatus::OK, "HTTP/1.1").write_interim(&mut out).unwrap();
+}
+
+#[test]
+fn head_then_streamed_body() {
+    let mut response = HttpResponse::new(HttpResponseStatus::OK, "HTTP/1.1");
+    response.add_header("Content-Length", "12");
+    let mut sink = MockStream::new(Vec::<Vec<u8>>::new());
+    let head = response.write_head(&mut sink).unwrap();
+    assert_eq!(head, 39);
+    assert_eq!(sink.flushes(), 0);
+    io::Write::write_all(&mut sink, b"Hello").unwrap();
+    io::Write::write_all(&mut sink, b", world").unwrap();
+    assert_eq!(String::from_utf8(sink.output().to_vec()).unwrap(),
+               "HTTP/1.1 200 OK\r\nContent-Length: 12\r\n\r\nHello, world");
+    // The same as writing it all in one go
+    let mut response =
+        HttpResponse::new_with_body(HttpResponseStatus::OK, "HTTP/1.1", "Hello, world");
+    response.add_header("Content-Length", "12");
+    assert_eq!(sink.output(), &response.to_vec()[..]);
+}
+
+#[test]
+fn multipart_byteranges_response() {
+    let resource = b"The quick brown fox jumps over the lazy dog";
+    let response = HttpResponse::multipart_byteranges(&[(4, 8), (40, 42)],
+                                                      resource.len() as u64,
+                                                      "text/plain",
+                                                      |start, end| {
+                                                          resource[start as usize..=end as usize]
+                                                              .to_vec()
+                                                      });
+    assert_eq!(response.status, HttpResponseStatus::PartialContent);
+    let content_type = response.headers["Content-Type"].to_str().unwrap();
+    let boundary = content_type.trim_start_matches("multipart/byteranges; boundary=");
+    assert_ne!(boundary, content_type);
+    assert_eq!(response.body.to_str().unwrap(),
+               format!("--{0}\r\nContent-Type: text/plain\r\nContent-Range: bytes 4-8/43\r\n\r\n\
+                        quick\r\n\
+                        --{0}\r\nContent-Type: text/plain\r\nContent-Range: bytes 40-42/43\r\n\r\n\
+                        dog\r\n\
+                        --{0}--\r\n",
+                       boundary));
+    // Data which looks like one of our boundaries doesn't confuse things
+    let response = HttpResponse::multipart_byteranges(&[(0, 0)], 1, "text/plain", |_, _| {
+        b"--rushttp-0123456789abcdef".to_vec()
+    });
+    let content_type = response.headers["Content-Type"].to_str().unwrap();
+    let boundary = content_type.trim_start_matches("multipart/byteranges; boundary=");
+    let body = response.body.to_str().unwrap();
+    assert_eq!(body.matches(&format!("--{}", boundary)).count(), 2);
+}
+
+#[test]
+fn chunked_response_with_trailer() {
+    let response = HttpResponse::new_with_body(HttpResponseStatus::OK, "HTTP/1.1", "Hello");
+    let mut out: Vec<u8> = Vec::new();
+    {
+        let mut writer = response.write_chunked(&mut out, &["Content-MD5"]).unwrap();
+        writer.write_chunk(b", world").unwrap();
+        writer.finish_with_trailers(&[("Content-MD5", "abc123")]).unwrap();
+    }
+    assert_eq!(String::from_utf8(out).unwrap(),
+               "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nTrailer: Content-MD5\r\n\r\n\
+                5\r\nHello\r\n7\r\n, world\r\n0\r\nContent-MD5: abc123\r\n\r\n");
+}
+
+#[test]
+fn chunked_response_drops_content_length() {
+    let mut response = HttpResponse::new_with_body(HttpResponseStatus::OK, "HTTP/1.1", "Hello");
+    response.add_header("Content-Length", "5");
+    let mut out: Vec<u8> = Vec::new();
+    response.write_chunked(&mut out, &[]).unwrap().finish().unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(),
+               "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nHello\r\n0\r\n\r\n");
+}
+
+#[test]
+fn chunked_response_no_trailers() {
+    let response = HttpResponse::new(HttpResponseStatus::OK, "HTTP/1.1");
+    let mut out: Vec<u8> = Vec::new();
+    {
+        let writer = response.write_chunked(&mut out, &[]).unwrap();
+        writer.finish().unwrap();
+    }
+    assert_eq!(String::from_utf8(out).unwrap(),
+               "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n");
+}
+
+#[test]
+fn chunked_response_unannounced_trailer() {
+    let response = HttpResponse::new(HttpResponseStatus::OK, "HTTP/1.1");
+    let mut out: Vec<u8> = Vec::new();
+    let writer = response.write_chunked(&mut out, &[]).unwrap();
+    assert!(writer.finish_with_trailers(&[("Content-MD5", "abc123")]).is_err());
+}
+
+#[test]
+fn raw_request_line_kept() {
+    let mut p = Parser::new();
+    p.keep_raw_request_line(true);
+    let test = b"\r\nGET /index.html?a=b HTTP/1.1\r\nHost: example.com\r\n\r\n";
+    assert!(p.raw_request_line().is_none());
+    match p.parse(&test[..10]) {
+        ParseResult::InProgress => {}
+        _ => panic!(),
+    }
+    assert!(p.raw_request_line().is_none());
+    match p.parse(&test[10..]) {
+        ParseResult::Complete(_, _) => {}
+        _ => panic!(),
+    }
+    assert_eq!(p.raw_request_line(), Some("GET /index.html?a=b HTTP/1.1"));
+    p.reset();
+    match p.parse(b"PUT / HTTP/1.0\n\n") {
+        ParseResult::Complete(_, _) => {}
+        _ => panic!(),
+    }
+    assert_eq!(p.raw_request_line(), Some("PUT / HTTP/1.0"));
+}
+
+#[test]
+fn raw_request_line_off_by_default() {
+    let mut p = Parser::new();
+    match p.parse(b"GET / HTTP/1.1\r\n\r\n") {
+        ParseResult::Complete(_, _) => {}
+        _ => panic!(),
+    }
+    assert!(p.raw_request_line().is_none());
+}
+
+#[test]
+fn url_control_characters() {
+    for test in &[&b"GET /a\tb HTTP/1.1\r\n\r\n"[..],
+                  &b"GET /a\x00b HTTP/1.1\r\n\r\n"[..],
+                  &b"GET /a\x1bb HTTP/1.1\r\n\r\n"[..],
+                  &b"GET /a\x7f HTTP/1.1\r\n\r\n"[..]] {
+        let mut p = Parser::new();
+        match p.parse(test) {
+            ParseResult::ErrorBadURL => {}
+            _ => panic!("{:?}", test),
+        }
+        match parse_once(test) {
+            OnceResult::Error(ParseError::BadURL) => {}
+            _ => panic!("{:?}", test),
+        }
+    }
+    let mut p = Parser::new();
+    match p.parse(b"GET /a%20b HTTP/1.1\r\n\r\n") {
+        ParseResult::Complete(r, _) => assert_eq!(r.uri().path(), "/a%20b"),
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn method_predicates() {
+    type Predicate = fn(&request::Request) -> bool;
+    let checks: [(&str, Predicate); 7] = [("GET", is_get),
+                                          ("HEAD", is_head),
+                                          ("POST", is_post),
+                                          ("PUT", is_put),
+                                          ("DELETE", is_delete),
+                                          ("OPTIONS", is_options),
+                                          ("PATCH", is_patch)];
+    for &(method, _) in &checks {
+        let mut p = Parser::new();
+        let r = match p.parse(format!("{} / HTTP/1.1\r\n\r\n", method).as_bytes()) {
+            ParseResult::Complete(r, _) => r,
+            _ => panic!(),
+        };
+        assert_eq!(method_str(&r), method);
+        for &(other, predicate) in &checks {
+            assert_eq!(predicate(&r), other == method, "{} vs {}", method, other);
+        }
+    }
+}
+
+#[test]
+fn request_round_trip() {
+    let tests: [&[u8]; 3] = [b"GET /index.html?a=b HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\n\r\n",
+                             b"POST /form HTTP/1.0\r\nContent-Length: 0\r\nX-Bytes: \xff\xfe\r\n\r\n",
+                             b"DELETE / HTTP/1.1\r\nCookie: a=1\r\nCookie: b=2\r\n\r\n"];
+    for test in tests.iter() {
+        let first = parse_complete(test);
+        let mut bytes = Vec::new();
+        let total = serialize_request(&first, &mut bytes).unwrap();
+        assert_eq!(total, bytes.len());
+        let second = parse_complete(&bytes);
+        assert_eq!(first.method(), second.method());
+        assert_eq!(first.uri(), second.uri());
+        assert_eq!(first.version(), second.version());
+        assert_eq!(first.headers(), second.headers());
+    }
+}
+
+#[test]
+fn serialize_get_request() {
+    let r = parse_complete(b"GET /a%20b?c=d HTTP/1.0\nHost: example.com\n\n");
+    let mut sink = OneByteWriter { data: Vec::new() };
+    let expected = b"GET /a%20b?c=d HTTP/1.0\r\nhost: example.com\r\n\r\n";
+    assert_eq!(serialize_request(&r, &mut sink).unwrap(), expected.len());
+    assert_eq!(sink.data, &expected[..]);
+}
+
+#[test]
+fn response_round_trip() {
+    let mut response = HttpResponse::new_with_body(HttpResponseStatus::NotFound,
+                                                   "HTTP/1.1",
+                                                   "Not here\r\n");
+    response.add_header("Content-Type", "text/plain");
+    response.add_header("X-Bytes", &b"\x01\xff"[..]);
+    let bytes = response.to_vec();
+    let (status, headers, body) = split_response(&bytes);
+    assert_eq!(status, "HTTP/1.1 404 Not Found");
+    assert_eq!(headers.len(), 2);
+    for (k, v) in headers {
+        assert_eq!(response.headers[&*k].as_bytes(), &v[..]);
+    }
+    assert_eq!(body, response.body.as_bytes());
+}
+
+#[test]
+fn finish_without_final_line() {
+    let mut p = Parser::new();
+    match p.parse(b"GET / HTTP/1.1\r\nHost: localhost\r\n") {
+        ParseResult::InProgress => {}
+        _ => panic!(),
+    }
+    match p.finish() {
+        ParseResult::ErrorUnexpectedEof => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn finish_part_way_through() {
+    for test in &[&b""[..], &b"GET / HT"[..], &b"GET / HTTP/1.0\r\nHost: loc"[..]] {
+        let mut p = Parser::new();
+        match p.parse(test) {
+            ParseResult::InProgress => {}
+            _ => panic!(),
+        }
+        match p.finish() {
+            ParseResult::ErrorUnexpectedEof => {}
+            _ => panic!("{:?}", test),
+        }
+    }
+}
+
+#[test]
+fn finish_lenient_http_10() {
+    let mut p = Parser::new();
+    match p.parse(b"GET /old HTTP/1.0\r\nHost: localhost\r\n") {
+        ParseResult::InProgress => {}
+        _ => panic!(),
+    }
+    match p.finish() {
+        ParseResult::Complete(r, 0) => {
+            assert_eq!(r.uri(), "/old");
+            assert_eq!(r.headers()["Host"], "localhost");
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn header_name_casing() {
+    let test = b"GET / HTTP/1.1\r\ncontent-TYPE: text/plain\r\nX-request-id: 1\r\nETAG: x\r\n\r\n";
+    let cases = [(Casing::AsReceived, ["content-TYPE", "X-request-id", "ETAG"]),
+                 (Casing::Lowercase, ["content-type", "x-request-id", "etag"]),
+                 (Casing::TitleCase, ["Content-Type", "X-Request-Id", "Etag"])];
+    for &(casing, expected) in &cases {
+        let mut p = Parser::new();
+        p.canonicalize_header_names(casing);
+        match p.parse(test) {
+            ParseResult::Complete(r, _) => assert_eq!(r.headers()["content-type"], "text/plain"),
+            _ => panic!(),
+        }
+        let names: Vec<String> = p.into_raw_headers().into_iter().map(|(k, _)| k).collect();
+        assert_eq!(names, expected);
+    }
+}
+
+#[test]
+fn host_and_port() {
+    type Expected<'a> = Option<(&'a str, Option<u16>)>;
+    let cases: [(&str, Expected); 8] =
+        [("example.com", Some(("example.com", None))),
+         ("example.com:8080", Some(("example.com", Some(8080)))),
+         ("example.com:", Some(("example.com", None))),
+         ("[::1]", Some(("[::1]", None))),
+         ("[::1]:8080", Some(("[::1]", Some(8080)))),
+         ("example.com:http", None),
+         ("example.com:99999", None),
+         ("fe80::1:8080", None)];
+    for &(value, expected) in &cases {
+        let r = parse_complete(format!("GET / HTTP/1.1\r\nHost: {}\r\n\r\n", value).as_bytes());
+        assert_eq!(host(&r), expected.map(|(h, p)| (h.to_string(), p)), "{}", value);
+    }
+    let r = parse_complete(b"GET / HTTP/1.0\r\n\r\n");
+    assert_eq!(host(&r), None);
+}
+
+#[test]
+fn parse_error_into_io_error() {
+    let e: io::Error = ParseError::BadMethod.into();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(e.to_string(), "Bad method");
+    assert_eq!(e.into_inner().unwrap().downcast_ref::<ParseError>(),
+               Some(&ParseError::BadMethod));
+}
+
+#[test]
+fn unsupported_transfer_codings() {
+    let tests: [(&[u8], bool); 7] =
+        [(b"POST / HTTP/1.1\r\nTransfer-Encoding: compress\r\n\r\n", false),
+         (b"POST / HTTP/1.1\r\nTransfer-Encoding: GZIP, chunked\r\n\r\n", false),
+         (b"POST / HTTP/1.1\r\nTransfer-Encoding: identity, chunked\r\n\r\n", true),
+         (b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: x-foo\r\n\r\n",
+          false),
+         (b"POST / HTTP/1.1\r\nTransfer-Encoding: Chunked\r\n\r\n", true),
+         (b"POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\n", true),
+         (b"GET / HTTP/1.1\r\n\r\n", true)];
+    for &(test, supported) in &tests {
+        let mut ctx = Parser::new();
+        ctx.reject_unsupported_transfer_codings(true);
+        match ctx.parse(test).into_result() {
+            Ok(_) => assert!(supported),
+            Err(e) => {
+                assert!(!supported);
+                assert_eq!(e, ParseError::UnsupportedTransferEncoding);
+                assert_eq!(e.as_status(), HttpResponseStatus::NotImplemented);
+            }
+        }
+    }
+    // Off by default, when we see it as a malformed request instead
+    let mut ctx = Parser::new();
+    match ctx.parse(tests[0].0) {
+        ParseResult::ErrorBadTransferEncoding => {}
+        e => panic!("{:?}", e),
+    }
+}
+
+#[test]
+fn parse_error_as_status() {
+    let tests = [(ParseError::Malformed, 400),
+                 (ParseError::BadHeader, 400),
+                 (ParseError::BadHeaderValue, 400),
+                 (ParseError::BadMethod, 501),
+                 (ParseError::BadProtocol, 505),
+                 (ParseError::BadURL, 400),
+                 (ParseError::ObsFold, 400),
+                 (ParseError::UnexpectedEof, 400),
+                 (ParseError::Timeout, 408),
+                 (ParseError::UnsupportedVersion, 505),
+                 (ParseError::UnexpectedBody, 400),
+                 (ParseError::BadTransferEncoding, 400),
+                 (ParseError::RequestTooLarge, 413),
+                 (ParseError::TooManyHeaders, 431),
+                 (ParseError::UnsupportedTransferEncoding, 501),
+                 (ParseError::Incomplete, 400)];
+    for &(ref e, code) in &tests {
+        assert_eq!(e.as_status().as_u16(), code, "{:?}", e);
+    }
+}
+
+#[test]
+fn tab_starts_a_fold() {
+    let mut p = Parser::new();
+    match p.parse(b"GET / HTTP/1.1\r\nX-Folded: one\r\n\ttwo\r\n \t three\r\nX-Next: 1\r\n\r\n") {
+        ParseResult::Complete(r, _) => {
+            assert_eq!(r.headers()["X-Folded"], "one two three");
+            assert_eq!(r.headers()["X-Next"], "1");
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn tabs_inside_value_kept() {
+    let mut p = Parser::new();
+    match p.parse(b"GET / HTTP/1.1\r\nX-Tabs:\ta\tb \tc\r\nX-Folded: x\r\n\ty\tz\r\n\r\n") {
+        ParseResult::Complete(r, _) => {
+            assert_eq!(r.headers()["X-Tabs"].as_bytes(), b"a\tb \tc");
+            assert_eq!(r.headers()["X-Folded"].as_bytes(), b"x y\tz");
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn fast_path_matches_slow_path() {
+    let tests: [&[u8]; 9] =
+        [b"GET /index.html HTTP/1.1\r\nUser-Agent: rust test\r\nHost: localhost\r\n\r\n",
+         b"\r\nPOST /a?b=c HTTP/1.0\nContent-Length: 0\nX-Folded: one\n two\r\n\r\nBODY",
+         b"GET /a\tb HTTP/1.1\r\n\r\n",
+         b"GET / HTTP/1.1\r\nBad Header: x\r\n\r\n",
+         b"GET / HTTP/1.1\r\nX-Value: a\x00b\r\n\r\n",
+         b"GET / HTTP/1.1\r\nX-Tabs:\ta\tb\r\n\r\n",
+         b"GET / HTTP/1.1\r\nX-Value: a\rb\r\n\r\n",
+         b"GET / HTTP/1.1\r\n: empty\r\n\r\n",
+         b"GET / HTTP/1.1\r\nHost: localhost\r\n"];
+    for test in tests.iter() {
+        let slow = format!("{:?}", Parser::new().parse(test));
+        let mut p = Parser::new();
+        p.fast_path(true);
+        assert_eq!(format!("{:?}", p.parse(test)), slow);
+        // And the same again when the input arrives in two pieces
+        for split in 0..test.len() {
+            assert_eq!(parse_in_two(test, split, true), parse_in_two(test, split, false));
+        }
+    }
+}
+
+#[test]
+fn fast_path_raw_request_line() {
+    let mut p = Parser::new();
+    p.fast_path(true).keep_raw_request_line(true);
+    match p.parse(b"GET /some/long/path?x=1 HTTP/1.1\r\n\r\n") {
+        ParseResult::Complete(_, _) => {}
+        _ => panic!(),
+    }
+    assert_eq!(p.raw_request_line(), Some("GET /some/long/path?x=1 HTTP/1.1"));
+}
+
+#[test]
+fn any_single_split() {
+    for test in SPLIT_TESTS.iter() {
+        for &fast in &[false, true] {
+            let whole = parse_in_pieces(test, &[], fast);
+            for split in 0..test.len() + 1 {
+                assert_eq!(parse_in_pieces(test, &[split], fast),
+                           whole,
+                           "split at {} of {:?}",
+                           split,
+                           String::from_utf8_lossy(test));
+            }
+        }
+    }
+}
+
+#[test]
+fn split_between_cr_and_lf() {
+    // Leading empty line, version, empty value, value, folded value and
+    // final EOLs, in that order
+    let test = b"\r\nGET / HTTP/1.1\r\nX-Empty:\r\nX-Value: a\r\nX-Folded: b\r\n c\r\n\r\nBODY";
+    let crs: Vec<usize> = test.windows(2)
+                              .enumerate()
+                              .filter(|&(_, w)| w == b"\r\n")
+                              .map(|(i, _)| i)
+                              .collect();
+    assert_eq!(crs.len(), 7);
+    for &fast in &[false, true] {
+        let whole = parse_in_pieces(test, &[], fast);
+        assert!(whole.ends_with(" 59"), "{}", whole);
+        for &cr in &crs {
+            assert_eq!(parse_in_pieces(test, &[cr + 1], fast), whole, "split after CR at {}", cr);
+        }
+    }
+}
+
+#[test]
+fn split_crlf_one_piece_at_a_time() {
+    let mut p = Parser::new();
+    for piece in &["GET / HTTP/1.1\r", "\n", "Host: x\r", "\n", "X-Fold: a\r", "\n b\r", "\n\r"] {
+        assert!(p.parse_str(piece).is_in_progress(), "{:?}", piece);
+    }
+    let (r, used) = p.parse_str("\nBODY").into_result().unwrap();
+    assert_eq!(used, 1);
+    assert_eq!(r.headers()["X-Fold"], "a b");
+    // A bare LF, with no CR stored beforehand, ends a line just as well
+    let mut p = Parser::new();
+    for piece in &["GET / HTTP/1.1", "\n", "Host: x", "\n", "X-Empty:", "\n"] {
+        assert!(p.parse_str(piece).is_in_progress(), "{:?}", piece);
+    }
+    assert!(p.parse_str("\n").is_complete());
+}
+
+#[test]
+fn split_cr_not_followed_by_lf() {
+    // Whatever state is waiting for the LF, something else is an error
+    let tests: [(&str, &str); 5] = [("\r", "\rGET / HTTP/1.1\r\n\r\n"),
+                                    ("GET / HTTP/1.1\r", "\rHost: x\r\n\r\n"),
+                                    ("GET / HTTP/1.1\r\nHost: x\r", "X: y\r\n\r\n"),
+                                    ("GET / HTTP/1.1\r\nX: a\r\n b\r", " c\r\n\r\n"),
+                                    ("GET / HTTP/1.1\r\n\r", "\r\n")];
+    for &(first, second) in &tests {
+        let mut p = Parser::new();
+        assert!(p.parse_str(first).is_in_progress(), "{:?}", first);
+        assert!(p.parse_str(second).is_error(), "{:?}", first);
+    }
+}
+
+#[test]
+fn random_multi_split() {
+    // A simple LCG, so the test is repeatable without a random crate
+    let mut seed: u32 = 0x1234_5678;
+    let mut next = |limit: usize| {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (seed >> 16) as usize % limit
+    };
+    for test in SPLIT_TESTS.iter() {
+        let whole = parse_in_pieces(test, &[], false);
+        for _ in 0..200 {
+            let mut splits: Vec<usize> = (0..next(8)).map(|_| next(test.len() + 1)).collect();
+            splits.sort_unstable();
+            let fast = next(2) == 1;
+            assert_eq!(parse_in_pieces(test, &splits, fast), whole, "splits {:?}", splits);
+        }
+    }
+}
+
+#[test]
+fn bare_cr_in_header_value() {
+    for test in &[&b"GET / HTTP/1.1\r\nX-Foo: a\rb\r\n\r\n"[..],
+                  &b"GET / HTTP/1.1\r\nX-Foo: a\r\n b\rc\r\n\r\n"[..]] {
+        for &fast in &[false, true] {
+            let mut p = Parser::new();
+            p.fast_path(fast);
+            match p.parse(test) {
+                ParseResult::ErrorBadHeaderValue => {}
+                e => panic!("{:?}", e),
+            }
+        }
+    }
+    match parse_once(b"GET / HTTP/1.1\r\nX-Foo: a\rb\r\n\r\n") {
+        OnceResult::Error(ParseError::BadHeaderValue) => {}
+        _ => panic!(),
+    }
+    let r = parse_complete(b"GET / HTTP/1.1\r\nX-Foo: ab\r\n\r\n");
+    assert_eq!(r.headers()["X-Foo"], "ab");
+}
+
+#[test]
+fn read_request_from_reader() {
+    let mut reader = SlowReader {
+        delay: Duration::from_millis(0),
+        data: b"POST /upload HTTP/1.1\r\nContent-Length: 5\r\n\r\nHello",
+    };
+    let (r, leftover) = read_request_from(&mut reader, Some(Duration::from_secs(5))).unwrap();
+    assert_eq!(r.uri(), "/upload");
+    // Whatever came in the same read as the end of the headers
+    assert_eq!(leftover, b"Hell");
+    assert_eq!(reader.data, b"o");
+}
+
+#[test]
+fn read_request_from_first_byte_timeout() {
+    let mut reader = SlowReader {
+        delay: Duration::from_millis(50),
+        data: b"GET / HTTP/1.1\r\n\r\n",
+    };
+    let e = read_request_from(&mut reader, Some(Duration::from_millis(10))).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    assert_eq!(e.into_inner().unwrap().downcast_ref::<ParseError>(),
+               Some(&ParseError::Timeout));
+    // Without the limit, a slow start is fine
+    let mut reader = SlowReader {
+        delay: Duration::from_millis(20),
+        data: b"GET / HTTP/1.1\r\n\r\n",
+    };
+    assert!(read_request_from(&mut reader, None).is_ok());
+}
+
+#[test]
+fn read_request_header_deadline() {
+    // Four reads of 8 octets, 20ms apart, is too slow
+    let mut reader = SlowReader {
+        delay: Duration::from_millis(20),
+        data: b"GET / HTTP/1.1\r\nHost: x\r\n\r\n",
+    };
+    let e = read_request_with_deadline(&mut reader, None, Duration::from_millis(50)).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    assert_eq!(e.into_inner().unwrap().downcast_ref::<ParseError>(),
+               Some(&ParseError::Timeout));
+    // Quick enough, and the body can take as long as it likes afterwards
+    let mut reader = SlowReader {
+        delay: Duration::from_millis(1),
+        data: b"POST / HTTP/1.1\r\nContent-Length: 20\r\n\r\n01234567890123456789",
+    };
+    let (r, leftover) =
+        read_request_with_deadline(&mut reader, None, Duration::from_millis(100)).unwrap();
+    assert_eq!(r.uri(), "/");
+    thread::sleep(Duration::from_millis(150));
+    let body = super::body::read_body(&r, &leftover, &mut reader, 1024).unwrap();
+    assert_eq!(body, b"01234567890123456789");
+}
+
+#[test]
+fn read_request_from_errors() {
+    let mut reader: &[u8] = b"GET / HTTP/1.1\r\nHost: x\r\n";
+    let e = read_request_from(&mut reader, None).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(e.into_inner().unwrap().downcast_ref::<ParseError>(),
+               Some(&ParseError::UnexpectedEof));
+    let mut reader: &[u8] = b"GET / http/1.1\r\n\r\n";
+    let e = read_request_from(&mut reader, None).unwrap_err();
+    assert_eq!(e.into_inner().unwrap().downcast_ref::<ParseError>(),
+               Some(&ParseError::BadProtocol));
+}
+
+#[test]
+fn summarize_request() {
+    let r = parse_complete(b"GET /index.html HTTP/1.1\r\nHost: x\r\nAccept: */*\r\nX-A: 1\r\n\r\n");
+    assert_eq!(summarize(&r), "GET /index.html HTTP/1.1 [3 headers]");
+    let r = parse_complete(b"POST /form?a=b HTTP/1.0\r\nHost: x\r\n\r\n");
+    assert_eq!(summarize(&r), "POST /form?a=b HTTP/1.0 [1 header]");
+}
+
+#[test]
+fn common_log_format() {
+    let r = parse_complete(b"GET /apache_pb.gif HTTP/1.0\r\n\r\n");
+    // 2000-10-10 20:55:36 UTC
+    let when = std::time::UNIX_EPOCH + Duration::from_secs(971_211_336);
+    let client = "127.0.0.1".parse().unwrap();
+    assert_eq!(log_line(&r, client, 200, Some(2326), when),
+               "127.0.0.1 - - [10/Oct/2000:20:55:36 +0000] \"GET /apache_pb.gif HTTP/1.0\" 200 2326");
+    let when = std::time::UNIX_EPOCH + Duration::from_secs(951_782_400);
+    let client = "::1".parse().unwrap();
+    assert_eq!(log_line(&r, client, 404, None, when),
+               "::1 - - [29/Feb/2000:00:00:00 +0000] \"GET /apache_pb.gif HTTP/1.0\" 404 -");
+}
+
+#[test]
+fn parse_owned_stitches_buffers() {
+    let mut p = Parser::new();
+    match p.parse_owned(b"GET /index.html HT".to_vec()) {
+        OnceResult::Incomplete => {}
+        _ => panic!(),
+    }
+    match p.parse_owned(b"TP/1.1\r\nHost: local".to_vec()) {
+        OnceResult::Incomplete => {}
+        _ => panic!(),
+    }
+    let (request, used) = match p.parse_owned(b"host\r\nX-Big: 0123456789\r\n\r\nBODY".to_vec()) {
+        OnceResult::Complete(r, used) => (r, used),
+        _ => panic!(),
+    };
+    assert_eq!(request.method, "GET");
+    assert_eq!(request.uri, "/index.html");
+    assert_eq!(request.version, Version::HTTP_11);
+    assert_eq!(request.header("host"), Some(&b"localhost"[..]));
+    assert_eq!(request.header("X-Big"), Some(&b"0123456789"[..]));
+    // The fields point into the parser's arena rather than being copies
+    let pointers = [request.uri.as_ptr(), request.headers[0].1.as_ptr()];
+    let arena = p.arena();
+    assert!(pointers.iter().all(|ptr| arena.as_ptr_range().contains(ptr)));
+    assert_eq!(&arena[used..], b"BODY");
+    p.reset();
+    assert!(p.arena().is_empty());
+}
+
+#[test]
+fn parse_owned_errors() {
+    let mut p = Parser::new();
+    match p.parse_owned(b"GET / HTTP/1.1\r\nX-Folded: a\r\n b\r\n\r\n".to_vec()) {
+        OnceResult::Error(ParseError::ObsFold) => {}
+        _ => panic!(),
+    }
+    p.reset();
+    match p.parse_owned(b"GET / HTTP/1.1x\r\n\r\n".to_vec()) {
+        OnceResult::Error(ParseError::BadProtocol) => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn parse_owned_applies_options() {
+    // The arena can't grow past the budget
+    let mut p = Parser::new();
+    p.max_total_bytes(24);
+    match p.parse_owned(b"GET / HTTP/1.1\r\nX-A: ".to_vec()) {
+        OnceResult::Incomplete => {}
+        _ => panic!(),
+    }
+    match p.parse_owned(b"0123456789".to_vec()) {
+        OnceResult::Error(ParseError::RequestTooLarge) => {}
+        _ => panic!(),
+    }
+    let mut p = Parser::new();
+    p.max_header_count(1);
+    match p.parse_owned(b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\n\r\n".to_vec()) {
+        OnceResult::Error(ParseError::TooManyHeaders) => {}
+        _ => panic!(),
+    }
+    let mut p = Parser::new();
+    p.lenient_request_line(true);
+    match p.parse_owned(b"GET  /x   HTTP/1.1\r\n\r\n".to_vec()) {
+        OnceResult::Complete(r, 22) => assert_eq!((r.method, r.uri), ("GET", "/x")),
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn parse_owned_one_octet_at_a_time() {
+    let test = b"POST /x HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let mut p = Parser::new();
+    for &c in &test[..test.len() - 1] {
+        assert!(matches!(p.parse_owned(vec![c]), OnceResult::Incomplete));
+    }
+    match p.parse_owned(vec![b'\n']) {
+        OnceResult::Complete(r, used) => {
+            assert_eq!(used, test.len());
+            assert_eq!(r.header("Host"), Some(&b"localhost"[..]));
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn protocol_versions() {
+    let cases: [(&[u8], Option<ParseError>); 9] =
+        [(b"HTTP/1.1", None),
+         (b"HTTP/1.0", None),
+         (b"http/1.1", Some(ParseError::BadProtocol)),
+         (b"HTTP/1.10", Some(ParseError::BadProtocol)),
+         (b"HTTP/1", Some(ParseError::BadProtocol)),
+         (b"HTTP/a.b", Some(ParseError::BadProtocol)),
+         (b"HTTP/3.0", Some(ParseError::UnsupportedVersion)),
+         (b"HTTP/2.0", Some(ParseError::UnsupportedVersion)),
+         (b"HTTP/0.9", Some(ParseError::UnsupportedVersion))];
+    for &(protocol, expected) in &cases {
+        let mut test = b"GET / ".to_vec();
+        test.extend_from_slice(protocol);
+        test.extend_from_slice(b"\r\n\r\n");
+        let result = Parser::new().parse(&test);
+        let once = match parse_once(&test) {
+            OnceResult::Error(e) => Some(e),
+            _ => None,
+        };
+        match expected {
+            None => {
+                assert!(matches!(result, ParseResult::Complete(..)));
+                assert_eq!(once, None);
+            }
+            Some(ParseError::BadProtocol) => {
+                assert!(matches!(result, ParseResult::ErrorBadProtocol));
+                assert_eq!(once, expected);
+            }
+            Some(_) => {
+                assert!(matches!(result, ParseResult::ErrorUnsupportedVersion));
+                assert_eq!(once, expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn body_on_bodiless_methods() {
+    let tests: [(&[u8], bool); 6] =
+        [(b"GET / HTTP/1.1\r\nContent-Length: 5\r\n\r\nHello", true),
+         (b"HEAD / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n", true),
+         (b"DELETE /x HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}", true),
+         (b"GET / HTTP/1.1\r\nContent-Length: 0\r\n\r\n", false),
+         (b"GET / HTTP/1.1\r\n\r\n", false),
+         (b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nHello", false)];
+    for &(test, rejected) in &tests {
+        // Allowed by default
+        match Parser::new().parse(test) {
+            ParseResult::Complete(_, _) => {}
+            _ => panic!(),
+        }
+        let mut p = Parser::new();
+        p.reject_body_on_bodiless_methods(true);
+        match p.parse(test) {
+            ParseResult::ErrorUnexpectedBody if rejected => {}
+            ParseResult::Complete(_, _) if !rejected => {}
+            e => panic!("{:?}", e),
+        }
+    }
+}
+
+#[test]
+fn unauthorized_basic_challenge() {
+    let response = HttpResponse::unauthorized_basic("Staging Server");
+    assert_eq!(response.status as u32, 401);
+    assert_eq!(response.headers["WWW-Authenticate"], "Basic realm=\"Staging Server\"");
+    let mut out: Vec<u8> = Vec::new();
+    response.write(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(),
+               "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Basic realm=\"Staging Server\"\r\n\r\n");
+}
+
+#[test]
+fn unauthorized_bearer_challenge() {
+    let response = HttpResponse::unauthorized_bearer("api");
+    assert_eq!(response.status as u32, 401);
+    assert_eq!(response.headers["WWW-Authenticate"], "Bearer realm=\"api\"");
+    // Quotes and backslashes in the realm are escaped, and newlines dropped
+    let response = HttpResponse::unauthorized_bearer("say \"hi\" \\o/\r\n");
+    assert_eq!(response.headers["WWW-Authenticate"],
+               "Bearer realm=\"say \\\"hi\\\" \\\\o/\"");
+}
+
+#[test]
+fn etag_exact_match() {
+    let r = parse_complete(b"GET / HTTP/1.1\r\nIf-None-Match: \"v1\"\r\n\r\n");
+    assert!(etag_matches(&r, "\"v1\""));
+    assert!(etag_matches(&r, "v1"));
+}
+
+#[test]
+fn etag_wildcard() {
+    let r = parse_complete(b"GET / HTTP/1.1\r\nIf-None-Match: *\r\n\r\n");
+    assert!(etag_matches(&r, "\"anything\""));
+}
+
+#[test]
+fn etag_weak_comparison() {
+    let r = parse_complete(b"GET / HTTP/1.1\r\nIf-None-Match: \"a\", W/\"v2\" , \"c\"\r\n\r\n");
+    assert!(etag_matches(&r, "\"v2\""));
+    assert!(etag_matches(&r, "W/\"c\""));
+}
+
+#[test]
+fn etag_with_comma() {
+    let r = parse_complete(b"GET / HTTP/1.1\r\nIf-None-Match: \"x\", \"a,b\"\r\n\r\n");
+    assert!(etag_matches(&r, "\"a,b\""));
+    assert!(!etag_matches(&r, "\"a\""));
+    assert!(!etag_matches(&r, "\"b\""));
+}
+
+#[test]
+fn etag_no_match() {
+    let r = parse_complete(b"GET / HTTP/1.1\r\nIf-None-Match: \"v1\", W/\"v2\"\r\n\r\n");
+    assert!(!etag_matches(&r, "\"v3\""));
+    let r = parse_complete(b"GET / HTTP/1.1\r\n\r\n");
+    assert!(!etag_matches(&r, "\"v1\""));
+}
+
+#[test]
+fn not_modified_with_etag() {
+    let mut response = HttpResponse::not_modified();
+    response.set_etag("v\"1");
+    assert_eq!(response.status as u32, 304);
+    let mut out: Vec<u8> = Vec::new();
+    response.write(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(),
+               "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\n\r\n");
+}
+
+#[test]
+fn empty_header_value() {
+    for test in &[&b"GET / HTTP/1.1\r\nX-Empty:\r\nHost: localhost\r\n\r\n"[..],
+                  &b"GET / HTTP/1.1\r\nX-Empty: \r\nHost: localhost\r\n\r\n"[..],
+                  &b"GET / HTTP/1.1\nX-Empty:\nHost: localhost\n\n"[..]] {
+        for &fast in &[false, true] {
+            let mut p = Parser::new();
+            p.fast_path(fast);
+            match p.parse(test) {
+                ParseResult::Complete(r, _) => {
+                    assert_eq!(r.headers()["X-Empty"], "");
+                    assert_eq!(r.headers()["Host"], "localhost");
+                }
+                e => panic!("{:?}", e),
+            }
+        }
+        match parse_once(test) {
+            OnceResult::Complete(r, _) => assert_eq!(r.header("X-Empty"), Some(&b""[..])),
+            _ => panic!(),
+        }
+    }
+}
+
+#[test]
+fn body_offset_is_exact() {
+    let tests: [(&[u8], usize); 4] = [(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nHello", 38),
+                                      (b"POST / HTTP/1.1\nContent-Length: 5\n\nHello", 35),
+                                      (b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\nHello", 37),
+                                      (b"POST / HTTP/1.1\r\nContent-Length: 5\n\r\nHello", 37)];
+    for &(test, offset) in &tests {
+        for &fast in &[false, true] {
+            let mut p = Parser::new();
+            p.fast_path(fast);
+            match p.parse(test) {
+                ParseResult::Complete(_, used) => {
+                    assert_eq!(used, offset);
+                    assert_eq!(&test[used..], b"Hello");
+                }
+                e => panic!("{:?}", e),
+            }
+        }
+        match parse_once(test) {
+            OnceResult::Complete(_, used) => assert_eq!(used, offset),
+            _ => panic!(),
+        }
+        // Split anywhere in the headers, the offset is into the last buffer
+        for split in 1..offset {
+            let mut p = Parser::new();
+            match p.parse(&test[..split]) {
+                ParseResult::InProgress => {}
+                e => panic!("{:?}", e),
+            }
+            match p.parse(&test[split..]) {
+                ParseResult::Complete(_, used) => assert_eq!(&test[split + used..], b"Hello"),
+                e => panic!("{:?}", e),
+            }
+        }
+    }
+}
+
+#[test]
+fn etags_from_content() {
+    assert_eq!(strong_etag(b"a"), "\"af63dc4c8601ec8c\"");
+    assert_eq!(weak_etag(b"a"), "W/\"af63dc4c8601ec8c\"");
+    assert_eq!(strong_etag(b"Hello, world"), strong_etag(b"Hello, world"));
+    assert_eq!(weak_etag(b"Hello, world"), weak_etag(b"Hello, world"));
+    assert_ne!(strong_etag(b"Hello, world"), strong_etag(b"Hello, world!"));
+    assert_ne!(strong_etag(b""), strong_etag(b"\0"));
+    // They work with the conditional request helpers
+    let tag = weak_etag(b"Hello, world");
+    let r = parse_complete(format!("GET / HTTP/1.1\r\nIf-None-Match: {}\r\n\r\n",
+                                   strong_etag(b"Hello, world"))
+                               .as_bytes());
+    assert!(etag_matches(&r, &tag));
+    assert!(!etag_matches(&r, &weak_etag(b"Goodbye")));
+}
+
+#[test]
+fn append_header_joins_values() {
+    let mut response = HttpResponse::new(HttpResponseStatus::OK, "HTTP/1.1");
+    response.append_header("Vary", "Accept");
+    response.append_header("vary", "Accept-Encoding");
+    assert_eq!(response.headers.len(), 1);
+    assert_eq!(response.headers["Vary"], "Accept, Accept-Encoding");
+    let mut out: Vec<u8> = Vec::new();
+    response.write(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(),
+               "HTTP/1.1 200 OK\r\nVary: Accept, Accept-Encoding\r\n\r\n");
+    // Raw bytes can be appended too
+    response.append_header("Vary", &b"X-\xff"[..]);
+    assert_eq!(response.headers["Vary"].as_bytes(), &b"Accept, Accept-Encoding, X-\xff"[..]);
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn compress_for_gzip_client() {
+    let r = parse_complete(b"GET / HTTP/1.1\r\nAccept-Encoding: deflate, gzip\r\n\r\n");
+    let text = "All work and no play makes Jack a dull boy. ".repeat(100);
+    let mut response = HttpResponse::ok_text(text.clone());
+    response.add_header("Vary", "Accept");
+    response.add_header("content-length", text.len().to_string());
+    response.compress_for(&r);
+    assert_eq!(response.headers["Content-Encoding"], "gzip");
+    assert_eq!(response.headers["Vary"], "Accept, Accept-Encoding");
+    assert!(response.body.len() < text.len());
+    assert_eq!(response.headers["Content-Length"], &*response.body.len().to_string());
+    assert_eq!(response.headers.len(), 4);
+    let mut decoded = String::new();
+    GzDecoder::new(response.body.as_bytes()).read_to_string(&mut decoded).unwrap();
+    assert_eq!(decoded, text);
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn compress_for_leaves_alone() {
+    let text = "All work and no play makes Jack a dull boy. ".repeat(100);
+    // The client doesn't take gzip
+    let tests: [&[u8]; 3] = [b"GET / HTTP/1.1\r\n\r\n",
+                             b"GET / HTTP/1.1\r\nAccept-Encoding: deflate\r\n\r\n",
+                             b"GET / HTTP/1.1\r\nAccept-Encoding: gzip;q=0\r\n\r\n"];
+    for test in &tests {
+        let mut response = HttpResponse::ok_text(text.clone());
+        response.compress_for(&parse_complete(test));
+        assert_eq!(response.body, &*text);
+        assert_eq!(response.headers.len(), 1);
+    }
+    // The body is too small to bother
+    let r = parse_complete(b"GET / HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n");
+    let mut response = HttpResponse::ok_text("Hello, world");
+    response.compress_for(&r);
+    assert_eq!(response.body, "Hello, world");
+    assert_eq!(response.headers.len(), 1);
+    // The body is already encoded
+    let mut response = HttpResponse::ok_text(text.clone());
+    response.add_header("Content-Encoding", "br");
+    response.compress_for(&r);
+    assert_eq!(response.body, &*text);
+}
+
+#[test]
+fn client_ip_x_forwarded_for() {
+    let r = parse_complete(b"GET / HTTP/1.1\r\n\
+                             X-Forwarded-For: 203.0.113.195, 70.41.3.18, 150.172.238.178\r\n\r\n");
+    assert_eq!(client_ip(&r, true), Some("203.0.113.195".parse().unwrap()));
+    let r = parse_complete(b"GET / HTTP/1.1\r\nX-Forwarded-For: 2001:db8:85a3::8a2e:370:7334\r\n\r\n");
+    assert_eq!(client_ip(&r, true), Some("2001:db8:85a3::8a2e:370:7334".parse().unwrap()));
+    let r = parse_complete(b"GET / HTTP/1.1\r\nX-Forwarded-For: 192.0.2.1:1234\r\n\r\n");
+    assert_eq!(client_ip(&r, true), Some("192.0.2.1".parse().unwrap()));
+}
+
+#[test]
+fn client_ip_forwarded() {
+    let r = parse_complete(b"GET / HTTP/1.1\r\n\
+                             Forwarded: For=\"[2001:db8:cafe::17]:4711\"; proto=https, for=192.0.2.43\r\n\
+                             X-Forwarded-For: 198.51.100.17\r\n\r\n");
+    assert_eq!(client_ip(&r, true), Some("2001:db8:cafe::17".parse().unwrap()));
+    let r = parse_complete(b"GET / HTTP/1.1\r\nForwarded: proto=http;by=203.0.113.43;for=192.0.2.60\r\n\r\n");
+    assert_eq!(client_ip(&r, true), Some("192.0.2.60".parse().unwrap()));
+    // Obfuscated identifiers aren't addresses
+    for test in &[&b"GET / HTTP/1.1\r\nForwarded: for=_hidden, for=192.0.2.43\r\n\r\n"[..],
+                  &b"GET / HTTP/1.1\r\nForwarded: for=unknown\r\n\r\n"[..],
+                  &b"GET / HTTP/1.1\r\n\r\n"[..]] {
+        assert_eq!(client_ip(&parse_complete(test), true), None);
+    }
+}
+
+#[test]
+fn client_ip_untrusted() {
+    let r = parse_complete(b"GET / HTTP/1.1\r\n\
+                             Forwarded: for=192.0.2.60\r\n\
+                             X-Forwarded-For: 203.0.113.195\r\n\r\n");
+    assert_eq!(client_ip(&r, false), None);
+}
+
+#[test]
+fn bytes_needed_for_body() {
+    let mut test = b"POST /upload HTTP/1.1\r\nContent-Length: 100\r\n\r\n".to_vec();
+    test.extend_from_slice(&[b'x'; 30]);
+    let mut p = Parser::new();
+    assert_eq!(p.bytes_needed(), None);
+    match p.parse(&test[..10]) {
+        ParseResult::InProgress => {}
+        e => panic!("{:?}", e),
+    }
+    assert_eq!(p.bytes_needed(), None);
+    match p.parse(&test[10..]) {
+        ParseResult::Complete(_, used) => assert_eq!(test.len() - 10 - used, 30),
+        e => panic!("{:?}", e),
+    }
+    assert_eq!(p.bytes_needed(), Some(70));
+    p.body_received(50);
+    assert_eq!(p.bytes_needed(), Some(20));
+    p.body_received(50);
+    assert_eq!(p.bytes_needed(), Some(0));
+    p.reset();
+    assert_eq!(p.bytes_needed(), None);
+}
+
+#[test]
+fn bytes_needed_without_length() {
+    let mut p = Parser::new();
+    match p.parse(b"GET / HTTP/1.1\r\n\r\n") {
+        ParseResult::Complete(_, _) => assert_eq!(p.bytes_needed(), Some(0)),
+        e => panic!("{:?}", e),
+    }
+    let mut p = Parser::new();
+    match p.parse(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nHello") {
+        ParseResult::Complete(_, _) => assert_eq!(p.bytes_needed(), None),
+        e => panic!("{:?}", e),
+    }
+}
+
+#[test]
+fn no_content_has_no_body() {
+    let mut response = HttpResponse::new_with_body(HttpResponseStatus::NoContent, "HTTP/1.1", "oops");
+    response.add_header("Content-Length", "4");
+    assert_eq!(String::from_utf8(response.to_vec()).unwrap(), "HTTP/1.1 204 No Content\r\n\r\n");
+}
+
+#[test]
+fn not_modified_has_no_body() {
+    let mut response = HttpResponse::not_modified();
+    response.body = "oops".into();
+    let mut out: Vec<u8> = Vec::new();
+    let total = response.write_for_request(&Method::GET, &mut out).unwrap();
+    assert_eq!(total, out.len());
+    assert_eq!(String::from_utf8(out).unwrap(), "HTTP/1.1 304 Not Modified\r\n\r\n");
+}
+
+#[test]
+fn head_response_has_length_but_no_body() {
+    let response = HttpResponse::ok_text("Hello, world");
+    let mut out: Vec<u8> = Vec::new();
+    let total = response.write_for_request(&Method::HEAD, &mut out).unwrap();
+    assert_eq!(total, out.len());
+    assert_eq!(String::from_utf8(out).unwrap(),
+               "HTTP/1.1 200 OK\r\n\
+                Content-Type: text/plain; charset=utf-8\r\n\
+                Content-Length: 12\r\n\
+                \r\n");
+    // Any other method gets the body as usual
+    let mut out: Vec<u8> = Vec::new();
+    response.write_for_request(&Method::GET, &mut out).unwrap();
+    assert!(out.ends_with(b"\r\n\r\nHello, world"));
+}
+
+#[test]
+fn header_value_encoding_modes() {
+    let test = b"GET / HTTP/1.1\r\nX-Name: J\xfcrgen\r\nX-Ok: caf\xc3\xa9\r\n\r\n";
+    let mut p = Parser::new();
+    p.header_value_encoding(HeaderValueEncoding::Raw);
+    match p.parse(test) {
+        ParseResult::Complete(r, _) => {
+            assert_eq!(r.headers()["X-Name"].as_bytes(), b"J\xfcrgen");
+            assert_eq!(r.headers()["X-Ok"].as_bytes(), "café".as_bytes());
+        }
+        e => panic!("{:?}", e),
+    }
+    let mut p = Parser::new();
+    p.header_value_encoding(HeaderValueEncoding::Strict);
+    match p.parse(test) {
+        ParseResult::ErrorBadHeaderValue => {}
+        e => panic!("{:?}", e),
+    }
+    let mut p = Parser::new();
+    p.header_value_encoding(HeaderValueEncoding::Lossy);
+    match p.parse(test) {
+        ParseResult::Complete(r, _) => {
+            assert_eq!(r.headers()["X-Name"].as_bytes(), "J\u{fffd}rgen".as_bytes());
+            assert_eq!(r.headers()["X-Ok"].as_bytes(), "café".as_bytes());
+        }
+        e => panic!("{:?}", e),
+    }
+}
+
+#[test]
+fn write_with_line_endings() {
+    let mut response = HttpResponse::ok_text("Hello");
+    response.add_header("X-Thing", "1");
+    let mut options = WriteOptions {
+        sort_headers: true,
+        content_length: true,
+        ..WriteOptions::default()
+    };
+    let mut crlf: Vec<u8> = Vec::new();
+    let total = response.write_with_options(&mut crlf, &options).unwrap();
+    assert_eq!(total, crlf.len());
+    assert_eq!(String::from_utf8(crlf).unwrap(),
+               "HTTP/1.1 200 OK\r\n\
+                Content-Length: 5\r\n\
+                Content-Type: text/plain; charset=utf-8\r\n\
+                X-Thing: 1\r\n\
+                \r\n\
+                Hello");
+    options.line_ending = LineEnding::Lf;
+    let mut lf: Vec<u8> = Vec::new();
+    let total = response.write_with_options(&mut lf, &options).unwrap();
+    assert_eq!(total, lf.len());
+    assert_eq!(String::from_utf8(lf).unwrap(),
+               "HTTP/1.1 200 OK\n\
+                Content-Length: 5\n\
+                Content-Type: text/plain; charset=utf-8\n\
+                X-Thing: 1\n\
+                \n\
+                Hello");
+    // The defaults are the same as plain write
+    let mut plain: Vec<u8> = Vec::new();
+    response.write_with_options(&mut plain, &WriteOptions::default()).unwrap();
+    assert_eq!(plain, response.to_vec());
+}
+
+#[test]
+fn write_with_date() {
+    let response = HttpResponse::new(HttpResponseStatus::NoContent, "HTTP/1.1");
+    let options = WriteOptions {
+        date: true,
+        ..WriteOptions::default()
+    };
+    let mut out: Vec<u8> = Vec::new();
+    response.write_with_options(&mut out, &options).unwrap();
+    let (_, headers, _) = split_response(&out);
+    let date = &headers.iter().find(|h| h.0 == "Date").unwrap().1;
+    assert_eq!(date.len(), "Sun, 06 Nov 1994 08:49:37 GMT".len());
+    assert!(date.ends_with(b" GMT"));
+}
+
+#[test]
+fn http_date_format() {
+    let when = UNIX_EPOCH + Duration::from_secs(784_111_777);
+    assert_eq!(http_date(when), "Sun, 06 Nov 1994 08:49:37 GMT");
+    assert_eq!(http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+}
+
+#[test]
+fn mock_stream_round_trip() {
+    let mut stream = MockStream::new(vec![&b"GET /hel"[..], b"lo HTTP/1.1\r\nHo", b"st: example.com\r\n\r\n"]);
+    let (req, leftover) = read_request_from(&mut stream, None).unwrap();
+    assert!(stream.is_drained());
+    assert!(leftover.is_empty());
+    assert_eq!(req.uri(), "/hello");
+    assert_eq!(req.headers()["Host"], "example.com");
+
+    let response = HttpResponse::ok_text(format!("You asked for {}", req.uri()));
+    response.write(&mut stream).unwrap();
+    assert_eq!(stream.flushes(), 1);
+    assert_eq!(stream.output(),
+               &b"HTTP/1.1 200 OK\r\n\
+                  Content-Type: text/plain; charset=utf-8\r\n\
+                  \r\n\
+                  You asked for /hello"[..]);
+}
+
+#[test]
+fn mock_stream_skips_empty_chunks() {
+    let mut stream = MockStream::new(vec![&b"GET / HTTP/1.1\r\n"[..], b"", b"Host: a\r\n\r\n", b""]);
+    let (req, _) = read_request_from(&mut stream, None).unwrap();
+    assert_eq!(req.headers()["Host"], "a");
+    assert!(stream.is_drained());
+    let mut buf = [0u8; 4];
+    assert_eq!(io::Read::read(&mut stream, &mut buf).unwrap(), 0);
+}
+
+#[test]
+fn stream_large_header_value() {
+    use std::sync::{Arc, Mutex};
+
+    const THRESHOLD: usize = 8192;
+    let big: Vec<u8> = (0..1024 * 1024).map(|i| b'a' + (i % 26) as u8).collect();
+    let mut test = b"GET / HTTP/1.1\r\nHost: localhost\r\nX-Assertion: ".to_vec();
+    test.extend_from_slice(&big);
+    test.extend_from_slice(b"\r\n more\r\nX-Small: tiny\r\n\r\n");
+    for &fast in &[false, true] {
+        let chunks: Arc<Mutex<Vec<RawHeader>>> = Arc::new(Mutex::new(Vec::new()));
+        let sink = chunks.clone();
+        let mut p = Parser::new();
+        p.fast_path(fast);
+        p.on_large_header_value(THRESHOLD, move |name, chunk| {
+            sink.lock().unwrap().push((name.to_string(), chunk.to_vec()))
+        });
+        let mut result = None;
+        for piece in test.chunks(5000) {
+            match p.parse(piece) {
+                ParseResult::InProgress => {}
+                ParseResult::Complete(r, _) => result = Some(r),
+                e => panic!("{:?}", e),
+            }
+        }
+        let r = result.unwrap();
+        assert_eq!(r.headers()["X-Assertion"], "");
+        assert_eq!(r.headers()["X-Small"], "tiny");
+        assert_eq!(r.headers()["Host"], "localhost");
+        let chunks = chunks.lock().unwrap();
+        assert!(chunks.iter().all(|c| c.0 == "X-Assertion" && c.1.len() <= THRESHOLD));
+        let mut streamed: Vec<u8> = chunks.iter().flat_map(|c| c.1.clone()).collect();
+        assert!(streamed.ends_with(b" more"));
+        streamed.truncate(streamed.len() - 5);
+        assert!(streamed == big);
+    }
+}
+
+#[test]
+fn stream_folded_large_header_value() {
+    use std::sync::{Arc, Mutex};
+
+    // No one line reaches the threshold, but the value as a whole does
+    const THRESHOLD: usize = 16;
+    let test = b"GET / HTTP/1.1\r\nX-Folded: aaaaaaaaaa\r\n bbbbbbbbbb\r\n cccccccccc\r\n\
+                 X-Small: tiny\r\n\r\n";
+    for &fast in &[false, true] {
+        let chunks: Arc<Mutex<Vec<RawHeader>>> = Arc::new(Mutex::new(Vec::new()));
+        let sink = chunks.clone();
+        let mut p = Parser::new();
+        p.fast_path(fast);
+        p.on_large_header_value(THRESHOLD, move |name, chunk| {
+            sink.lock().unwrap().push((name.to_string(), chunk.to_vec()))
+        });
+        let r = match p.parse(test) {
+            ParseResult::Complete(r, _) => r,
+            e => panic!("{:?}", e),
+        };
+        assert_eq!(r.headers()["X-Folded"], "");
+        assert_eq!(r.headers()["X-Small"], "tiny");
+        let chunks = chunks.lock().unwrap();
+        assert!(chunks.iter().all(|c| c.0 == "X-Folded" && c.1.len() <= THRESHOLD));
+        let streamed: Vec<u8> = chunks.iter().flat_map(|c| c.1.clone()).collect();
+        assert_eq!(streamed, &b"aaaaaaaaaa bbbbbbbbbb cccccccccc"[..]);
+    }
+}
+
+#[test]
+fn connect_authority_form() {
+    let r = parse_complete(b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n");
+    assert_eq!(r.method(), Method::CONNECT);
+    assert_eq!(connect_target(&r), Some(("example.com".to_string(), 443)));
+    let r = parse_complete(b"CONNECT [2001:db8::1]:8443 HTTP/1.1\r\n\r\n");
+    assert_eq!(connect_target(&r), Some(("[2001:db8::1]".to_string(), 8443)));
+    // Only CONNECT has a tunnel target
+    let r = parse_complete(b"GET / HTTP/1.1\r\nHost: example.com:443\r\n\r\n");
+    assert_eq!(connect_target(&r), None);
+}
+
+#[test]
+fn connect_needs_authority_form() {
+    for test in &[&b"CONNECT example.com HTTP/1.1\r\n\r\n"[..],
+                  &b"CONNECT /index.html HTTP/1.1\r\n\r\n"[..],
+                  &b"CONNECT http://example.com:443/ HTTP/1.1\r\n\r\n"[..],
+                  &b"CONNECT user@example.com:443 HTTP/1.1\r\n\r\n"[..]] {
+        for &fast in &[false, true] {
+            let mut p = Parser::new();
+            p.fast_path(fast);
+            match p.parse(test) {
+                ParseResult::ErrorBadURL => {}
+                e => panic!("{:?}", e),
+            }
+        }
+        match parse_once(test) {
+            OnceResult::Error(ParseError::BadURL) => {}
+            _ => panic!(),
+        }
+    }
+}
+
+#[test]
+fn parse_result_predicates() {
+    let complete = Parser::new().parse(b"GET / HTTP/1.1\r\n\r\n");
+    assert!(complete.is_complete() && !complete.is_in_progress() && !complete.is_error());
+    let in_progress = Parser::new().parse(b"GET / HTT");
+    assert!(!in_progress.is_complete() && in_progress.is_in_progress() && !in_progress.is_error());
+    let error = Parser::new().parse(b"GET / HTTP/1.1\r\n: x\r\n\r\n");
+    assert!(!error.is_complete() && !error.is_in_progress() && error.is_error());
+}
+
+#[test]
+fn parse_result_into_result() {
+    let (r, used) = Parser::new().parse(b"GET /x HTTP/1.1\r\n\r\nbody").into_result().unwrap();
+    assert_eq!(r.uri(), "/x");
+    assert_eq!(used, 19);
+    assert_eq!(Parser::new().parse(b"GET / HTT").into_result().unwrap_err(),
+               ParseError::Incomplete);
+    assert_eq!(Parser::new().parse(b"GET / HTTP/1.1\r\n: x\r\n\r\n").into_result().unwrap_err(),
+               ParseError::BadHeader);
+    assert_eq!(Parser::new().parse(b"GET / HTTP/3.0\r\n").into_result().unwrap_err(),
+               ParseError::UnsupportedVersion);
+    // The sentinel turns back into InProgress
+    assert!(ParseResult::from(ParseError::Incomplete).is_in_progress());
+}
+
+#[test]
+fn fragment_in_url() {
+    for test in &[&b"GET /path#frag HTTP/1.1\r\n\r\n"[..], &b"GET /path?q=1# HTTP/1.1\r\n\r\n"[..]] {
+        for &fast in &[false, true] {
+            let mut p = Parser::new();
+            p.fast_path(fast);
+            match p.parse(test) {
+                ParseResult::ErrorBadURL => {}
+                e => panic!("{:?}", e),
+            }
+        }
+        match parse_once(test) {
+            OnceResult::Error(ParseError::BadURL) => {}
+            _ => panic!(),
+        }
+    }
+    // An escaped hash is fine
+    let r = parse_complete(b"GET /path%23frag HTTP/1.1\r\n\r\n");
+    assert_eq!(r.uri(), "/path%23frag");
+}
+
+#[cfg(feature = "logging")]
+#[test]
+fn parse_diagnostics_go_to_log() {
+    use std::sync::Mutex;
+
+    struct Capture;
+    static RECORDS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    static CAPTURE: Capture = Capture;
+    impl ::log::Log for Capture {
+        fn enabled(&self, _: &::log::Metadata) -> bool {
+            true
+        }
+        fn log(&self, record: &::log::Record) {
+            RECORDS.lock().unwrap().push(format!("{} {}", record.level(), record.args()));
+        }
+        fn flush(&self) {}
+    }
+    ::log::set_logger(&CAPTURE).unwrap();
+    ::log::set_max_level(::log::LevelFilter::Trace);
+
+    parse_complete(b"GET /logged HTTP/1.1\r\n\r\n");
+    match Parser::new().parse(b"GET /logged HTTP/1.1\r\n: x\r\n\r\n") {
+        ParseResult::ErrorBadHeader => {}
+        e => panic!("{:?}", e),
+    }
+    let records = RECORDS.lock().unwrap();
+    assert!(records.iter().any(|r| r.starts_with("TRACE rushttp: parsed GET /logged")));
+    assert!(records.iter().any(|r| r.starts_with("DEBUG rushttp: parse failed")));
+}
+
+#[test]
+fn file_response() {
+    let response = HttpResponse::from_file_bytes("site/Index.HTML", b"<p>Hi</p>".to_vec(), 784111777);
+    assert_eq!(response.status as u32, 200);
+    assert_eq!(response.headers["Content-Type"], "text/html; charset=utf-8");
+    assert_eq!(response.headers["Content-Length"], "9");
+    assert_eq!(response.headers["Last-Modified"], "Sun, 06 Nov 1994 08:49:37 GMT");
+    assert_eq!(response.body, "<p>Hi</p>");
+}
+
+#[test]
+fn binary_file_response() {
+    let png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0xFF];
+    let response = HttpResponse::from_file_bytes("logo.png", png.clone(), 0);
+    assert_eq!(response.headers["Content-Type"], "image/png");
+    assert_eq!(response.headers["Last-Modified"], "Thu, 01 Jan 1970 00:00:00 GMT");
+    assert_eq!(response.body.to_str(), None);
+    let out = response.to_vec();
+    assert!(out.ends_with(&png));
+}
+
+#[test]
+fn guess_mime_type() {
+    assert_eq!(mime_type("style.css"), "text/css; charset=utf-8");
+    assert_eq!(mime_type("a/b.c/photo.JPEG"), "image/jpeg");
+    assert_eq!(mime_type("a.b/README"), "application/octet-stream");
+    assert_eq!(mime_type(".hidden"), "application/octet-stream");
+    assert_eq!(mime_type("archive.tar.gz"), "application/gzip");
+}
+
+#[test]
+fn transfer_encoding_smuggling() {
+    let tests: [&[u8]; 7] = [
+        // Duplicated header
+        b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: chunked\r\n\r\n",
+        b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\ntransfer-encoding: chunked\r\n\r\n",
+        // chunked isn't the last coding
+        b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked, gzip\r\n\r\n",
+        b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n",
+        b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked, chunked\r\n\r\n",
+        // Unknown codings
+        b"POST / HTTP/1.1\r\nTransfer-Encoding: xchunked\r\n\r\n",
+        b"POST / HTTP/1.1\r\nTransfer-Encoding: foo, chunked\r\n\r\n",
+    ];
+    for test in &tests {
+        let mut ctx = Parser::new();
+        match ctx.parse(test) {
+            ParseResult::ErrorBadTransferEncoding => {}
+            e => panic!("{:?} gave {:?}", String::from_utf8_lossy(test), e),
+        }
+        match request::parse_once(test) {
+            OnceResult::Error(ParseError::BadTransferEncoding) => {}
+            e => panic!("{:?} gave {:?}", String::from_utf8_lossy(test), e),
+        }
+    }
+    let test = b"POST / HTTP/1.1\r\nTransfer-Encoding: X-GZIP ,CHUNKED\r\n\r\n";
+    assert!(Parser::new().parse(test).is_complete());
+    assert!(matches!(request::parse_once(test), OnceResult::Complete(..)));
+}
+
+#[test]
+fn max_total_bytes_body() {
+    // 40 octets of headers, and 100 of body
+    let test = b"POST / HTTP/1.1\r\nContent-Length: 100\r\n\r\n";
+    let mut p = Parser::new();
+    p.max_total_bytes(139);
+    assert!(matches!(p.parse(test), ParseResult::ErrorRequestTooLarge));
+    p.reset();
+    p.max_total_bytes(140);
+    assert!(p.parse(test).is_complete());
+    assert_eq!(p.bytes_remaining(), Some(100));
+    p.body_received(60);
+    assert_eq!(p.bytes_remaining(), Some(40));
+    // The count starts again for the next request
+    p.reset();
+    assert_eq!(p.bytes_remaining(), Some(140));
+    assert!(p.parse(test).is_complete());
+}
+
+#[test]
+fn max_total_bytes_headers() {
+    let mut p = Parser::new();
+    p.max_total_bytes(20);
+    assert!(p.parse(b"GET / HTTP/1.1\r\n").is_in_progress());
+    assert!(matches!(p.parse(b"Host: x\r\n"), ParseResult::ErrorRequestTooLarge));
+    assert_eq!(ParseResult::ErrorRequestTooLarge.into_result().unwrap_err(),
+               ParseError::RequestTooLarge);
+    let mut p = Parser::new();
+    assert_eq!(p.bytes_remaining(), None);
+    assert!(p.parse(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n").is_complete());
+}
+
+#[test]
+fn write_is_one_system_call() {
+    let mut response = HttpResponse::ok_text("Hello");
+    response.add_header("X-One", "1");
+    response.add_header("X-Two", "2");
+    let mut stream = MockStream::default();
+    let total = response.write_with_options(&mut stream, &WriteOptions {
+        content_length: true,
+        date: true,
+        ..WriteOptions::default()
+    }).unwrap();
+    assert_eq!(stream.writes(), 1);
+    assert_eq!(total, stream.output().len());
+    assert!(stream.output().ends_with(b"\r\n\r\nHello"));
+    // A huge body isn't copied, so goes in a second write
+    let body = "x".repeat(100 * 1024);
+    let response = HttpResponse::ok_text(body.as_str());
+    let mut stream = MockStream::default();
+    let total = response.write(&mut stream).unwrap();
+    assert_eq!(stream.writes(), 2);
+    assert_eq!(total, stream.output().len());
+    let mut stream = MockStream::default();
+    HttpResponse::new(HttpResponseStatus::Continue, "HTTP/1.1").write_interim(&mut stream).unwrap();
+    assert_eq!(stream.writes(), 1);
+    assert_eq!(stream.output(), b"HTTP/1.1 100 Continue\r\n\r\n");
+}
+
+#[test]
+fn status_as_map_key() {
+    let statuses = [HttpResponseStatus::NotFound,
+                    HttpResponseStatus::OK,
+                    HttpResponseStatus::InternalServerError,
+                    HttpResponseStatus::NotFound,
+                    HttpResponseStatus::Continue,
+                    HttpResponseStatus::MovedPermanently];
+    let mut counts: HashMap<HttpResponseStatus, u64> = HashMap::new();
+    let mut sorted: BTreeMap<HttpResponseStatus, u64> = BTreeMap::new();
+    for status in &statuses {
+        *counts.entry(*status).or_insert(0) += 1;
+        *sorted.entry(*status).or_insert(0) += 1;
+    }
+    assert_eq!(counts[&HttpResponseStatus::NotFound], 2);
+    assert_eq!(counts[&HttpResponseStatus::OK], 1);
+    let codes: Vec<u16> = sorted.keys().map(|s| s.as_u16()).collect();
+    assert_eq!(codes, [100, 200, 301, 404, 500]);
+    assert!(HttpResponseStatus::OK < HttpResponseStatus::NotFound);
+    assert_eq!(HttpResponseStatus::OK, HttpResponseStatus::OK);
+    // Every status sorts by code
+    let all: Vec<HttpResponseStatus> = (100..600).filter_map(HttpResponseStatus::from_u16).collect();
+    for pair in all.windows(2) {
+        assert!(pair[0] < pair[1]);
+        assert!(pair[0].as_u16() < pair[1].as_u16());
+    }
+}
+
+#[test]
+fn path_depth_counts_segments() {
+    let tests: [(&[u8], usize); 8] = [(b"GET / HTTP/1.1\r\n\r\n", 0),
+                                      (b"GET /a/b/c HTTP/1.1\r\n\r\n", 3),
+                                      (b"GET /a/b/ HTTP/1.1\r\n\r\n", 2),
+                                      (b"GET /a//b?x=/y/z HTTP/1.1\r\n\r\n", 2),
+                                      (b"GET /a/../b/./c HTTP/1.1\r\n\r\n", 2),
+                                      (b"GET /../../a HTTP/1.1\r\n\r\n", 1),
+                                      (b"GET /a/%2E%2E/b HTTP/1.1\r\n\r\n", 1),
+                                      (b"GET /a%2Fb/c HTTP/1.1\r\n\r\n", 2)];
+    for &(test, depth) in &tests {
+        let r = parse_complete(test);
+        assert_eq!(request::path_depth(&r), depth, "{}", r.uri());
+    }
+}
+
+#[test]
+fn normalize_path_resolves_dots() {
+    let tests: [(&[u8], &str); 6] = [(b"GET / HTTP/1.1\r\n\r\n", "/"),
+                                     (b"GET /static/css/site.css HTTP/1.1\r\n\r\n",
+                                      "/static/css/site.css"),
+                                     (b"GET /static/./css//../site.css HTTP/1.1\r\n\r\n",
+                                      "/static/site.css"),
+                                     (b"GET /a/b/../ HTTP/1.1\r\n\r\n", "/a/"),
+                                     (b"GET /a/.. HTTP/1.1\r\n\r\n", "/"),
+                                     (b"GET /caf%C3%A9/%20x HTTP/1.1\r\n\r\n", "/café/ x")];
+    for &(test, path) in &tests {
+        let r = parse_complete(test);
+        assert_eq!(request::normalize_path(&r).as_deref(), Ok(path));
+    }
+}
+
+#[test]
+fn normalize_path_traversal() {
+    let tests: [(&[u8], PathError); 6] =
+        [(b"GET /static/../../etc/passwd HTTP/1.1\r\n\r\n", PathError::Traversal),
+         (b"GET /.. HTTP/1.1\r\n\r\n", PathError::Traversal),
+         (b"GET /static/%2E%2E/%2e%2e/etc/passwd HTTP/1.1\r\n\r\n", PathError::Traversal),
+         // Decoding first would make these two look like they stay inside
+         (b"GET /static/..%2F..%2Fetc/passwd HTTP/1.1\r\n\r\n", PathError::EncodedSeparator),
+         (b"GET /static/..%5c..%5cwin.ini HTTP/1.1\r\n\r\n", PathError::EncodedSeparator),
+         (b"GET /static/%zz HTTP/1.1\r\n\r\n", PathError::BadEncoding)];
+    for &(test, error) in &tests {
+        let r = parse_complete(test);
+        assert_eq!(request::normalize_path(&r), Err(error), "{}", r.uri());
+    }
+}
+
+#[test]
+fn parsed_request_accessors() {
+    let test = b"POST /search?q=rust&page=2 HTTP/1.1\r\nHost: example.com\r\n\
+                 Content-Type: text/plain\r\nX-Bin: \xff\r\nContent-Length: 5\r\n\r\nHello";
+    let mut p = Parser::new();
+    let (r, used) = p.parse(test).into_result().unwrap();
+    let body = super::body::read_body(&r, &test[used..], &mut io::empty(), 1024).unwrap();
+    let r = ParsedRequest::with_body(r, body);
+    assert_eq!(r.method(), "POST");
+    assert_eq!(r.path(), "/search");
+    assert_eq!(r.query(), Some("q=rust&page=2"));
+    assert_eq!(r.version(), "HTTP/1.1");
+    assert_eq!(r.header("content-type"), Some("text/plain"));
+    assert_eq!(r.header("Accept"), None);
+    assert_eq!(r.header("X-Bin"), None);
+    assert_eq!(r.body(), b"Hello");
+    let inner = r.into_inner();
+    assert_eq!(inner.method(), Method::POST);
+    assert_eq!(inner.headers()["Host"], "example.com");
+
+    let r = ParsedRequest::from(parse_complete(b"GET / HTTP/1.0\r\n\r\n"));
+    assert_eq!(r.path(), "/");
+    assert_eq!(r.query(), None);
+    assert_eq!(r.version(), "HTTP/1.0");
+    assert!(r.body().is_empty());
+}
+
+#[test]
+fn upgrade_tokens() {
+    let r = parse_complete(b"GET / HTTP/1.1\r\nConnection: keep-alive, Upgrade\r\n\
+                             Upgrade: h2c, websocket/13 ,\r\nUpgrade: foo\r\n\r\n");
+    assert_eq!(request::requested_upgrades(&r), ["h2c", "websocket/13", "foo"]);
+    let r = parse_complete(b"GET / HTTP/1.1\r\nConnection: UPGRADE\r\nUpgrade: websocket\r\n\r\n");
+    assert_eq!(request::requested_upgrades(&r), ["websocket"]);
+    // Without Connection: upgrade, the Upgrade header doesn't count
+    let r = parse_complete(b"GET / HTTP/1.1\r\nConnection: close\r\nUpgrade: websocket\r\n\r\n");
+    assert!(request::requested_upgrades(&r).is_empty());
+    let r = parse_complete(b"GET / HTTP/1.1\r\nConnection: upgrade\r\n\r\n");
+    assert!(request::requested_upgrades(&r).is_empty());
+}
+
+#[test]
+fn switching_protocols_response() {
+    let response = HttpResponse::switching_protocols("h2c");
+    assert_eq!(response.status, HttpResponseStatus::SwitchingProtocols);
+    assert_eq!(response.headers["Upgrade"], "h2c");
+    assert_eq!(response.headers["Connection"], "Upgrade");
+    let mut out = Vec::new();
+    response.write_interim(&mut out).unwrap();
+    let (status, headers, rest) = split_response(&out);
+    assert_eq!(status, "HTTP/1.1 101 Switching Protocols");
+    assert_eq!(headers.len(), 2);
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn max_header_count() {
+    let mut p = Parser::new();
+    p.max_header_count(5);
+    assert!(p.parse(b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n").is_in_progress());
+    // Folded lines don't count
+    assert!(p.parse(b"D: 4\r\n more\r\nE: 5\r\n").is_in_progress());
+    // The sixth is refused as soon as it starts
+    assert!(matches!(p.parse(b"F"), ParseResult::ErrorTooManyHeaders));
+    p.reset();
+    assert!(p.parse(b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\nD: 4\r\nE: 5\r\n\r\n")
+             .is_complete());
+}
+
+#[test]
+fn effective_host_sources() {
+    let r = parse_complete(b"GET /index.html HTTP/1.1\r\nHost: example.com:8080\r\n\r\n");
+    assert_eq!(request::effective_host(&r), Some(("example.com".to_string(), Some(8080))));
+    // An HTTP/1.0 client with no Host header, sending the whole URL
+    let r = parse_complete(b"GET http://legacy.example.org/index.html HTTP/1.0\r\n\r\n");
+    assert_eq!(r.uri().path(), "/index.html");
+    assert_eq!(request::host(&r), None);
+    assert_eq!(request::effective_host(&r), Some(("legacy.example.org".to_string(), None)));
+    let r = parse_complete(b"GET http://[::1]:81/ HTTP/1.0\r\n\r\n");
+    assert_eq!(request::effective_host(&r), Some(("[::1]".to_string(), Some(81))));
+    // The Host header wins
+    let r = parse_complete(b"GET http://uri.example.org/ HTTP/1.1\r\nHost: header.example.org\r\n\r\n");
+    assert_eq!(request::effective_host(&r), Some(("header.example.org".to_string(), None)));
+    let r = parse_complete(b"GET / HTTP/1.0\r\n\r\n");
+    assert_eq!(request::effective_host(&r), None);
+}
+
+#[test]
+fn parse_str_complete() {
+    let mut p = Parser::new();
+    match p.parse_str("GET /x HTTP/1.1\r\nHost: localhost\r\n\r\nbody") {
+        ParseResult::Complete(r, used) => {
+            assert_eq!(r.uri(), "/x");
+            assert_eq!(r.headers()["Host"], "localhost");
+            assert_eq!(used, 36);
+        }
+        e => panic!("{:?}", e),
+    }
+}
+
+#[test]
+fn parse_str_in_pieces() {
+    let mut p = Parser::new();
+    assert!(p.parse_str("POST /x HTTP/1.1\r\n").is_in_progress());
+    assert!(p.parse_str("X-Name: Jürgen\r\n").is_in_progress());
+    let (r, _) = p.parse_str("\r\n").into_result().unwrap();
+    assert_eq!(r.headers()["X-Name"].as_bytes(), "Jürgen".as_bytes());
+    assert!(matches!(Parser::new().parse_str("GET@ / HTTP/1.1\r\n"), ParseResult::ErrorBadMethod));
+}
+
+#[test]
+fn serialized_len_matches_write() {
+    let mut custom = HttpResponse::new_with_body(HttpResponseStatus::IAmATeapot, "HTTP/1.0", "short");
+    custom.add_header("X-Bin", &b"\xff\xfe"[..]);
+    let mut no_content = HttpResponse::new(HttpResponseStatus::NoContent, "HTTP/1.1");
+    no_content.add_header("Content-Length", "10");
+    no_content.body = "ignored".into();
+    let responses = [HttpResponse::ok_text("Hello"),
+                     HttpResponse::ok_html(""),
+                     HttpResponse::not_modified(),
+                     HttpResponse::from_file_bytes("a.bin", vec![0, 1, 2, 255], 0),
+                     HttpResponse::method_not_allowed(&["GET", "HEAD"]),
+                     custom,
+                     no_content];
+    let options = [WriteOptions::default(),
+                   WriteOptions {
+                       line_ending: LineEnding::Lf,
+                       content_length: true,
+                       date: true,
+                       ..WriteOptions::default()
+                   }];
+    for response in &responses {
+        assert_eq!(response.serialized_len(), response.to_vec().len());
+        for opts in &options {
+            let mut out = Vec::new();
+            let written = response.write_with_options(&mut out, opts).unwrap();
+            assert_eq!(response.serialized_len_with_options(opts), out.len());
+            assert_eq!(written, out.len());
+        }
+    }
+}
+
+// ****************************************************************************
+//
+// Private Functions
+//
+// ****************************************************************************
+
+// None
+
+/// Parse `test` in two pieces, split at `split`, and describe the result.
+fn parse_in_two(test: &[u8], split: usize, fast: bool) -> String {
+    let mut p = Parser::new();
+    p.fast_path(fast);
+    let result = match p.parse(&test[..split]) {
+        ParseResult::InProgress => p.parse(&test[split..]),
+        r => r,
+    };
+    format!("{:?}", result)
+}
+
+/// Feed `test` to one parser in pieces, cut at each of the (sorted)
+/// `splits`, and describe the result. For a complete request, the count is
+/// of octets taken from the whole of `test`, so it doesn't depend on where
+/// the pieces were cut.
+fn parse_in_pieces(test: &[u8], splits: &[usize], fast: bool) -> String {
+    let mut p = Parser::new();
+    p.fast_path(fast);
+    let mut start = 0;
+    for &end in splits.iter().chain(Some(&test.len())) {
+        match p.parse(&test[start..end]) {
+            ParseResult::InProgress => start = end,
+            ParseResult::Complete(r, used) => return format!("{:?} {}", r, start + used),
+            e => return format!("{:?}", e),
+        }
+    }
+    "InProgress".to_string()
+}
+
+/// Parse a request which must be complete.
+fn parse_complete(test: &[u8]) -> request::Request {
+    let mut p = Parser::new();
+    match p.parse(test) {
+        ParseResult::Complete(r, _) => r,
+        e => panic!("{:?}", e),
+    }
+}
+
+/// Take a serialised response apart into status line, headers and body.
+fn split_response(bytes: &[u8]) -> (String, Vec<RawHeader>, &[u8]) {
+    let end = bytes.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    let mut lines = bytes[..end].split(|&b| b == b'\n')
+                                .map(|l| l.strip_suffix(b"\r").unwrap_or(l));
+    let status = String::from_utf8(lines.next().unwrap().to_vec()).unwrap();
+    let headers = lines.map(|l| {
+                           let colon = l.iter().position(|&b| b == b':').unwrap();
+                           let name = String::from_utf8(l[..colon].to_vec()).unwrap();
+                           (name, l[colon + 2..].to_vec())
+                       })
+                       .collect();
+    (status, headers, &bytes[end + 4..])
+}
 
 // ****************************************************************************
 //
@@ -0,0 +1,186 @@
+//! # The rushttp Rust HTTP Library - Negotiation Tests
+//!
+//! Unit tests for content negotiation.
+
+// ****************************************************************************
+//
+// Imports
+//
+// ****************************************************************************
+
+use super::super::negotiation::*;
+use super::super::request::*;
+
+// ****************************************************************************
+//
+// Public Functions
+//
+// ****************************************************************************
+
+#[test]
+fn accept_sorted_by_quality() {
+    let r = parse(b"GET / HTTP/1.1\r\nAccept: text/plain;q=0.5, text/html, */*;q=0.1, \
+                    application/json;q=0.8\r\n\r\n");
+    let ranges = parse_accept(&r);
+    let names: Vec<String> = ranges.iter()
+                                   .map(|m| format!("{}/{}", m.type_, m.subtype))
+                                   .collect();
+    assert_eq!(names,
+               ["text/html", "application/json", "text/plain", "*/*"]);
+    assert_eq!(ranges[1].quality, 0.8);
+    assert_eq!(ranges[3].quality, 0.1);
+}
+
+#[test]
+fn accept_specificity_breaks_ties() {
+    let r = parse(b"GET / HTTP/1.1\r\nAccept: */*, text/*, text/html;level=1, text/html\r\n\r\n");
+    let ranges = parse_accept(&r);
+    let names: Vec<String> = ranges.iter()
+                                   .map(|m| format!("{}/{}", m.type_, m.subtype))
+                                   .collect();
+    assert_eq!(names, ["text/html", "text/html", "text/*", "*/*"]);
+    assert_eq!(ranges[0].params, [("level".to_string(), "1".to_string())]);
+}
+
+#[test]
+fn accept_missing_means_anything() {
+    let r = parse(b"GET / HTTP/1.1\r\n\r\n");
+    let ranges = parse_accept(&r);
+    assert_eq!(ranges.len(), 1);
+    assert_eq!(ranges[0].type_, "*");
+    assert_eq!(ranges[0].subtype, "*");
+    assert_eq!(ranges[0].quality, 1.0);
+    assert_eq!(best_match(&ranges, &["application/json", "text/html"]),
+               Some("application/json".to_string()));
+}
+
+#[test]
+fn accept_skips_garbage() {
+    let r = parse(b"GET / HTTP/1.1\r\nAccept: html, text/html;q=2, */json, text/plain\r\n\r\n");
+    let ranges = parse_accept(&r);
+    assert_eq!(ranges.len(), 1);
+    assert_eq!(ranges[0].subtype, "plain");
+}
+
+#[test]
+fn best_match_wildcards() {
+    let r = parse(b"GET / HTTP/1.1\r\nAccept: text/*;q=0.5, application/json\r\n\r\n");
+    let ranges = parse_accept(&r);
+    assert_eq!(best_match(&ranges, &["text/html", "application/json"]),
+               Some("application/json".to_string()));
+    assert_eq!(best_match(&ranges, &["text/html", "text/plain"]),
+               Some("text/html".to_string()));
+    assert_eq!(best_match(&ranges, &["image/png"]), None);
+}
+
+#[test]
+fn best_match_most_specific_range_wins() {
+    // text/html is explicitly refused even though text/* is fine
+    let r = parse(b"GET / HTTP/1.1\r\nAccept: text/*, text/html;q=0\r\n\r\n");
+    let ranges = parse_accept(&r);
+    assert_eq!(best_match(&ranges, &["text/html"]), None);
+    assert_eq!(best_match(&ranges, &["text/html", "text/plain"]),
+               Some("text/plain".to_string()));
+}
+
+#[test]
+fn encoding_gzip_deflate() {
+    let r = parse(b"GET / HTTP/1.1\r\nAccept-Encoding: gzip, deflate\r\n\r\n");
+    assert!(accepts_gzip(&r));
+    assert_eq!(preferred_encoding(&r, &["identity", "deflate", "gzip"]),
+               Some("deflate".to_string()));
+    assert_eq!(preferred_encoding(&r, &["br", "identity"]),
+               Some("identity".to_string()));
+}
+
+#[test]
+fn encoding_quality_ordering() {
+    let r = parse(b"GET / HTTP/1.1\r\nAccept-Encoding: deflate;q=0.5, GZIP;q=0.9\r\n\r\n");
+    assert_eq!(preferred_encoding(&r, &["deflate", "gzip"]),
+               Some("gzip".to_string()));
+}
+
+#[test]
+fn encoding_gzip_refused() {
+    let r = parse(b"GET / HTTP/1.1\r\nAccept-Encoding: gzip;q=0, deflate\r\n\r\n");
+    assert!(!accepts_gzip(&r));
+    assert_eq!(preferred_encoding(&r, &["gzip", "identity"]),
+               Some("identity".to_string()));
+}
+
+#[test]
+fn encoding_identity_refused() {
+    let r = parse(b"GET / HTTP/1.1\r\nAccept-Encoding: *;q=0\r\n\r\n");
+    assert!(!accepts_gzip(&r));
+    assert_eq!(preferred_encoding(&r, &["gzip", "identity"]), None);
+    let r = parse(b"GET / HTTP/1.1\r\nAccept-Encoding: br, identity;q=0\r\n\r\n");
+    assert_eq!(preferred_encoding(&r, &["identity"]), None);
+    let r = parse(b"GET / HTTP/1.1\r\nAccept-Encoding: *\r\n\r\n");
+    assert!(accepts_gzip(&r));
+}
+
+#[test]
+fn encoding_missing_header() {
+    let r = parse(b"GET / HTTP/1.1\r\n\r\n");
+    assert!(!accepts_gzip(&r));
+    assert_eq!(preferred_encoding(&r, &["gzip", "identity"]),
+               Some("identity".to_string()));
+    assert_eq!(preferred_encoding(&r, &["gzip"]), None);
+}
+
+#[test]
+fn language_sorted_by_quality() {
+    let r = parse(b"GET / HTTP/1.1\r\nAccept-Language: fr;q=0.5, en-GB, *;q=0.1, \
+                    de;q=0.8, en;q=0.8, x!y\r\n\r\n");
+    let ranges = parse_accept_language(&r);
+    let tags: Vec<&str> = ranges.iter().map(|l| l.tag.as_str()).collect();
+    assert_eq!(tags, ["en-gb", "de", "en", "fr", "*"]);
+    assert_eq!(ranges[1].quality, 0.8);
+    assert_eq!(best_language(&ranges, &["fr", "de", "en-GB"]), Some("en-GB".to_string()));
+    assert_eq!(best_language(&ranges, &["fr", "de"]), Some("de".to_string()));
+    assert_eq!(best_language(&ranges, &["ja"]), Some("ja".to_string()));
+}
+
+#[test]
+fn language_prefix_matching() {
+    let r = parse(b"GET / HTTP/1.1\r\nAccept-Language: en-US, fr;q=0.9\r\n\r\n");
+    let ranges = parse_accept_language(&r);
+    // en-US falls back to en
+    assert_eq!(best_language(&ranges, &["fr", "en"]), Some("en".to_string()));
+    assert_eq!(best_language(&ranges, &["fr", "eng"]), Some("fr".to_string()));
+    assert_eq!(best_language(&ranges, &["de"]), None);
+    // en matches en-GB, but the exact tag is closer
+    let r = parse(b"GET / HTTP/1.1\r\nAccept-Language: en;q=0.5, en-us;q=0\r\n\r\n");
+    let ranges = parse_accept_language(&r);
+    assert_eq!(best_language(&ranges, &["en-US", "en-GB"]), Some("en-GB".to_string()));
+    assert_eq!(best_language(&ranges, &["en-US"]), None);
+}
+
+#[test]
+fn language_missing_header() {
+    let r = parse(b"GET / HTTP/1.1\r\n\r\n");
+    let ranges = parse_accept_language(&r);
+    assert!(ranges.is_empty());
+    assert_eq!(best_language(&ranges, &["de", "en"]), Some("de".to_string()));
+    assert_eq!(best_language(&ranges, &[]), None);
+}
+
+// ****************************************************************************
+//
+// Private Functions
+//
+// ****************************************************************************
+
+fn parse(test: &[u8]) -> Request {
+    let mut ctx = Parser::new();
+    match ctx.parse(test) {
+        ParseResult::Complete(r, _) => r,
+        _ => panic!(),
+    }
+}
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
@@ -0,0 +1,114 @@
+//! # The rushttp Rust HTTP Library - Query Tests
+//!
+//! Unit tests for query string handling.
+
+// ****************************************************************************
+//
+// Imports
+//
+// ****************************************************************************
+
+use std::collections::HashMap;
+
+use super::super::query::*;
+use super::super::request::*;
+
+// ****************************************************************************
+//
+// Public Functions
+//
+// ****************************************************************************
+
+#[test]
+fn query_pairs_decoded() {
+    let r = parse(b"GET /search?q=rust+http&page=2&&flag&name=J%C3%BCrgen%21 HTTP/1.1\r\n\r\n");
+    assert_eq!(query_pairs(&r),
+               [("q".to_string(), "rust http".to_string()),
+                ("page".to_string(), "2".to_string()),
+                ("flag".to_string(), "".to_string()),
+                ("name".to_string(), "Jürgen!".to_string())]);
+}
+
+#[test]
+fn query_pairs_none() {
+    let r = parse(b"GET /search HTTP/1.1\r\n\r\n");
+    assert!(query_pairs(&r).is_empty());
+    assert_eq!(query_pairs_limited(&r, 0), Ok(Vec::new()));
+}
+
+#[test]
+fn query_pairs_skips_bad_escapes() {
+    let r = parse(b"GET /search?a=%zz&b=1 HTTP/1.1\r\n\r\n");
+    assert_eq!(query_pairs(&r), [("b".to_string(), "1".to_string())]);
+}
+
+#[test]
+fn query_pairs_over_limit() {
+    let mut test = b"GET /flood?".to_vec();
+    for i in 0..1000 {
+        test.extend(format!("k{}=v&", i).bytes());
+    }
+    test.extend(b" HTTP/1.1\r\n\r\n");
+    let r = parse(&test);
+    assert_eq!(query_pairs(&r).len(), 1000);
+    assert_eq!(query_pairs_limited(&r, 1000).unwrap().len(), 1000);
+    assert_eq!(query_pairs_limited(&r, 999), Err(QueryError::TooManyPairs));
+}
+
+#[test]
+fn query_as_struct() {
+    let r = parse(b"GET /list?page=2&size=10 HTTP/1.1\r\n\r\n");
+    assert_eq!(query_as::<Page>(&r), Ok(Page { page: 2, size: 10 }));
+    let r = parse(b"GET /list?page=3 HTTP/1.1\r\n\r\n");
+    assert_eq!(query_as::<Page>(&r), Ok(Page { page: 3, size: 20 }));
+    let r = parse(b"GET /list?size=10 HTTP/1.1\r\n\r\n");
+    assert_eq!(query_as::<Page>(&r), Err(QueryError::Missing("page".to_string())));
+    let r = parse(b"GET /list?page=two HTTP/1.1\r\n\r\n");
+    assert_eq!(query_as::<Page>(&r), Err(QueryError::BadValue("page".to_string())));
+}
+
+#[test]
+fn query_as_collections() {
+    let r = parse(b"GET /list?a=1&b=2&a=3 HTTP/1.1\r\n\r\n");
+    let map: HashMap<String, String> = query_as(&r).unwrap();
+    assert_eq!(map.len(), 2);
+    assert_eq!(map["a"], "1");
+    assert_eq!(map["b"], "2");
+    let pairs: Vec<(String, String)> = query_as(&r).unwrap();
+    assert_eq!(pairs, query_pairs(&r));
+}
+
+// ****************************************************************************
+//
+// Private Functions
+//
+// ****************************************************************************
+
+#[derive(Debug, PartialEq)]
+struct Page {
+    page: u32,
+    size: u32,
+}
+
+impl FromQuery for Page {
+    fn from_query(pairs: &[(String, String)]) -> Result<Page, QueryError> {
+        Ok(Page {
+            page: required(pairs, "page")?,
+            size: optional(pairs, "size")?.unwrap_or(20),
+        })
+    }
+}
+
+fn parse(test: &[u8]) -> Request {
+    let mut ctx = Parser::new();
+    match ctx.parse(test) {
+        ParseResult::Complete(r, _) => r,
+        _ => panic!(),
+    }
+}
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
@@ -0,0 +1,104 @@
+//! # The rushttp Rust HTTP Library - Routing Tests
+//!
+//! Unit tests for the request router.
+
+// ****************************************************************************
+//
+// Imports
+//
+// ****************************************************************************
+
+use super::super::request::*;
+use super::super::response::*;
+use super::super::routing::*;
+use super::super::Method;
+
+// ****************************************************************************
+//
+// Public Functions
+//
+// ****************************************************************************
+
+#[test]
+fn route_exact_match() {
+    let mut router = Router::new();
+    router.route(Method::GET, "/index.html", |_, _| {
+        HttpResponse::new_with_body(HttpResponseStatus::OK, "HTTP/1.1", "index")
+    });
+    let response = router.dispatch(&make_request(Method::GET, "/index.html"));
+    assert_eq!(response.status as u32, 200);
+    assert_eq!(response.body, "index");
+    let response = router.dispatch(&make_request(Method::GET, "/index.htm"));
+    assert_eq!(response.status as u32, 404);
+}
+
+#[test]
+fn route_param_match() {
+    let mut router = Router::new();
+    router.route(Method::GET, "/users/:id/posts/:post", |_, params| {
+        let body = format!("{} {}", params["id"], params["post"]);
+        HttpResponse::new_with_body(HttpResponseStatus::OK, "HTTP/1.1", body)
+    });
+    let response = router.dispatch(&make_request(Method::GET, "/users/42/posts/a%20b"));
+    assert_eq!(response.status as u32, 200);
+    assert_eq!(response.body, "42 a b");
+    let response = router.dispatch(&make_request(Method::GET, "/users/42/posts"));
+    assert_eq!(response.status as u32, 404);
+}
+
+#[test]
+fn route_wildcard() {
+    let mut router = Router::new();
+    router.route(Method::GET, "/static/*", |_, params| {
+        HttpResponse::new_with_body(HttpResponseStatus::OK, "HTTP/1.1", params["*"].clone())
+    });
+    let response = router.dispatch(&make_request(Method::GET, "/static/css/site.css"));
+    assert_eq!(response.status as u32, 200);
+    assert_eq!(response.body, "css/site.css");
+    let response = router.dispatch(&make_request(Method::GET, "/dynamic/site.css"));
+    assert_eq!(response.status as u32, 404);
+}
+
+#[test]
+fn route_wildcard_needs_a_segment() {
+    let mut router = Router::new();
+    router.route(Method::GET, "/static/*", |_, params| {
+        HttpResponse::new_with_body(HttpResponseStatus::OK, "HTTP/1.1", params["*"].clone())
+    });
+    let response = router.dispatch(&make_request(Method::GET, "/static"));
+    assert_eq!(response.status as u32, 404);
+    let response = router.dispatch(&make_request(Method::GET, "/static/"));
+    assert_eq!(response.status as u32, 200);
+    assert_eq!(response.body, "");
+}
+
+#[test]
+fn route_method_mismatch() {
+    let mut router = Router::new();
+    router.route(Method::GET, "/form", |_, _| HttpResponse::new(HttpResponseStatus::OK, "HTTP/1.1"))
+          .route(Method::POST, "/form", |_, _| HttpResponse::new(HttpResponseStatus::OK, "HTTP/1.1"));
+    let response = router.dispatch(&make_request(Method::DELETE, "/form"));
+    assert_eq!(response.status as u32, 405);
+    assert_eq!(response.headers["Allow"], "GET, POST");
+}
+
+// ****************************************************************************
+//
+// Private Functions
+//
+// ****************************************************************************
+
+fn make_request(method: Method, uri: &str) -> Request {
+    let mut ctx = Parser::new();
+    let test = format!("{} {} HTTP/1.1\r\nHost: localhost\r\n\r\n", method, uri);
+    match ctx.parse(test.as_bytes()) {
+        ParseResult::Complete(r, _) => r,
+        _ => panic!(),
+    }
+}
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
@@ -0,0 +1,391 @@
+//! # The rushttp Rust HTTP Library - Server Integration Tests
+//!
+//! Starts a real `Server` on an ephemeral port and talks to it over a socket.
+
+#![cfg(feature = "server")]
+
+// ****************************************************************************
+//
+// Imports
+//
+// ****************************************************************************
+
+extern crate rushttp;
+
+use rushttp::response::*;
+use rushttp::server::*;
+
+use std::io::prelude::*;
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// ****************************************************************************
+//
+// Public Functions
+//
+// ****************************************************************************
+
+#[test]
+fn serve_one_request() {
+    let server = Server::bind("127.0.0.1:0", 2).unwrap();
+    let addr = server.local_addr().unwrap();
+    let handle = server.shutdown_handle().unwrap();
+    let t = thread::spawn(move || {
+        server.serve(|req| {
+                  let body = format!("You asked for {}", req.uri());
+                  HttpResponse::new_with_body(HttpResponseStatus::OK, "HTTP/1.1", body)
+              })
+              .unwrap()
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Connection: close\r\n"));
+    assert!(response.ends_with("\r\n\r\nYou asked for /hello"));
+
+    handle.shutdown();
+    t.join().unwrap();
+}
+
+#[test]
+fn serve_parse_error() {
+    let server = Server::bind("127.0.0.1:0", 1).unwrap();
+    let addr = server.local_addr().unwrap();
+    let handle = server.shutdown_handle().unwrap();
+    let t = thread::spawn(move || {
+        server.serve(|_| HttpResponse::new(HttpResponseStatus::OK, "HTTP/1.1")).unwrap()
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /hello HTTP/9.9\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 505 HTTP Version Not Supported\r\n"));
+
+    handle.shutdown();
+    t.join().unwrap();
+}
+
+#[test]
+fn serve_bad_method() {
+    let server = Server::bind("127.0.0.1:0", 1).unwrap();
+    let addr = server.local_addr().unwrap();
+    let handle = server.shutdown_handle().unwrap();
+    let t = thread::spawn(move || {
+        server.serve(|_| HttpResponse::new(HttpResponseStatus::OK, "HTTP/1.1")).unwrap()
+    });
+
+    // We can't say which methods are allowed, so it's not a 405
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GE(T /hello HTTP/1.1\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 501 Not Implemented\r\n"), "{:?}", response);
+    assert!(!response.contains("Allow"));
+
+    handle.shutdown();
+    t.join().unwrap();
+}
+
+#[test]
+fn serve_request_limits() {
+    let mut server = Server::bind("127.0.0.1:0", 1).unwrap();
+    server.max_header_count(Some(2)).max_request_bytes(Some(256));
+    let addr = server.local_addr().unwrap();
+    let handle = server.shutdown_handle().unwrap();
+    let t = thread::spawn(move || {
+        server.serve(|_| HttpResponse::new(HttpResponseStatus::OK, "HTTP/1.1")).unwrap()
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"),
+            "{:?}",
+            response);
+
+    // A header which goes on and on is cut off at the limit
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut request = b"GET / HTTP/1.1\r\nX-Long: ".to_vec();
+    request.resize(600, b'a');
+    stream.write_all(&request).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"), "{:?}", response);
+
+    handle.shutdown();
+    t.join().unwrap();
+}
+
+#[test]
+fn serve_header_timeout() {
+    let mut server = Server::bind("127.0.0.1:0", 1).unwrap();
+    server.header_timeout(Some(Duration::from_millis(200)));
+    let addr = server.local_addr().unwrap();
+    let handle = server.shutdown_handle().unwrap();
+    let t = thread::spawn(move || {
+        server.serve(|_| HttpResponse::new(HttpResponseStatus::OK, "HTTP/1.1")).unwrap()
+    });
+
+    // Dribbling out the headers doesn't keep the connection alive forever
+    let start = Instant::now();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+    thread::sleep(Duration::from_millis(100));
+    stream.write_all(b"X-Slow: 1\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"), "{:?}", response);
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    handle.shutdown();
+    t.join().unwrap();
+}
+
+#[test]
+fn serve_ipv6() {
+    // Not every machine has IPv6, even on loopback
+    let server = match Server::bind("[::1]:0", 1) {
+        Ok(s) => s,
+        Err(_) => return,
+    };
+    let addr = server.local_addr().unwrap();
+    assert!(addr.is_ipv6());
+    let handle = server.shutdown_handle().unwrap();
+    let t = thread::spawn(move || {
+        server.serve(|req| HttpResponse::ok_text(format!("Hello {}", req.uri()))).unwrap()
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /v6 HTTP/1.1\r\nHost: [::1]\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("\r\n\r\nHello /v6"));
+
+    handle.shutdown();
+    t.join().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn serve_unix_socket() {
+    use std::os::unix::net::UnixStream;
+
+    let path = std::env::temp_dir().join(format!("rushttp-test-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let server = Server::bind_unix(&path, 1).unwrap();
+    assert!(server.local_addr().is_err());
+    let handle = server.shutdown_handle().unwrap();
+    let t = thread::spawn(move || {
+        server.serve(|req| HttpResponse::ok_text(format!("Hello {}", req.uri()))).unwrap()
+    });
+
+    let mut stream = UnixStream::connect(&path).unwrap();
+    stream.write_all(b"GET /unix HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("\r\n\r\nHello /unix"));
+
+    handle.shutdown();
+    t.join().unwrap();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn serve_max_connections() {
+    let mut server = Server::bind("127.0.0.1:0", 2).unwrap();
+    server.max_connections(Some(2));
+    let addr = server.local_addr().unwrap();
+    let handle = server.shutdown_handle().unwrap();
+    let t = thread::spawn(move || {
+        server.serve(|req| HttpResponse::ok_text(format!("Hello {}", req.uri()))).unwrap()
+    });
+
+    // These two sit there without sending a request, using up the limit
+    let mut idle: Vec<TcpStream> = (0..2).map(|_| TcpStream::connect(addr).unwrap()).collect();
+    let mut extra = TcpStream::connect(addr).unwrap();
+    let mut response = String::new();
+    extra.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+
+    // The first two are still served
+    for stream in &mut idle {
+        stream.write_all(b"GET /held HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+
+    handle.shutdown();
+    t.join().unwrap();
+}
+
+#[test]
+fn serve_head_request() {
+    let server = Server::bind("127.0.0.1:0", 1).unwrap();
+    let addr = server.local_addr().unwrap();
+    let handle = server.shutdown_handle().unwrap();
+    let t = thread::spawn(move || server.serve(|_| HttpResponse::ok_text("Hello")).unwrap());
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"HEAD / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Content-Length: 5\r\n"));
+    assert!(response.ends_with("\r\n\r\n"));
+
+    handle.shutdown();
+    t.join().unwrap();
+}
+
+#[test]
+fn serve_keep_alive() {
+    let server = Server::bind("127.0.0.1:0", 1).unwrap();
+    let addr = server.local_addr().unwrap();
+    let handle = server.shutdown_handle().unwrap();
+    let t = thread::spawn(move || {
+        server.serve(|req| HttpResponse::ok_text(format!("Hello {}", req.uri()))).unwrap()
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /one HTTP/1.1\r\nHost: localhost\r\n\r\n\
+                       GET /two HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+          .unwrap();
+    // The first response stays open, so it has to say how long it is
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let answers: Vec<&str> = response.split("HTTP/1.1 200 OK\r\n").skip(1).collect();
+    assert_eq!(answers.len(), 2);
+    assert!(answers[0].contains("Content-Length: 10\r\n"));
+    assert!(!answers[0].contains("Connection"));
+    assert!(answers[0].ends_with("\r\n\r\nHello /one"));
+    assert!(answers[1].contains("Connection: close\r\n"));
+    assert!(answers[1].ends_with("\r\n\r\nHello /two"));
+
+    handle.shutdown();
+    t.join().unwrap();
+}
+
+#[test]
+fn serve_max_requests_per_connection() {
+    let mut server = Server::bind("127.0.0.1:0", 1).unwrap();
+    server.max_requests_per_connection(Some(3));
+    let addr = server.local_addr().unwrap();
+    let handle = server.shutdown_handle().unwrap();
+    let t = thread::spawn(move || {
+        server.serve(|req| HttpResponse::ok_text(format!("Hello {}", req.uri()))).unwrap()
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    for i in 0..4 {
+        write!(stream, "GET /{} HTTP/1.1\r\nHost: localhost\r\n\r\n", i).unwrap();
+    }
+    // We only get three answers, then the server hangs up
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let answers: Vec<&str> = response.split("HTTP/1.1 200 OK\r\n").skip(1).collect();
+    assert_eq!(answers.len(), 3);
+    for (i, answer) in answers.iter().enumerate() {
+        assert!(answer.ends_with(&format!("\r\n\r\nHello /{}", i)), "{:?}", answer);
+        assert_eq!(answer.contains("Connection: close\r\n"), i == 2, "{:?}", answer);
+    }
+
+    handle.shutdown();
+    t.join().unwrap();
+}
+
+#[test]
+fn serve_shutdown_with_idle_connection() {
+    let mut server = Server::bind("127.0.0.1:0", 1).unwrap();
+    server.idle_timeout(None);
+    let addr = server.local_addr().unwrap();
+    let handle = server.shutdown_handle().unwrap();
+    let t = thread::spawn(move || {
+        server.serve(|req| HttpResponse::ok_text(format!("Hello {}", req.uri()))).unwrap()
+    });
+
+    // One request, then the connection sits there, kept alive
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /one HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut response = Vec::new();
+    let mut buffer = [0u8; 256];
+    while !response.ends_with(b"Hello /one") {
+        let n = stream.read(&mut buffer).unwrap();
+        assert!(n > 0);
+        response.extend_from_slice(&buffer[..n]);
+    }
+
+    // Shutting down mustn't wait for the idle client
+    let start = Instant::now();
+    handle.shutdown();
+    t.join().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(5));
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn serve_idle_timeout() {
+    let mut server = Server::bind("127.0.0.1:0", 1).unwrap();
+    server.idle_timeout(Some(Duration::from_millis(200)));
+    let addr = server.local_addr().unwrap();
+    let handle = server.shutdown_handle().unwrap();
+    let t = thread::spawn(move || {
+        server.serve(|req| HttpResponse::ok_text(format!("Hello {}", req.uri()))).unwrap()
+    });
+
+    // The server hangs up once we've been quiet for too long
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /one HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(!response.contains("Connection"));
+    assert!(response.ends_with("\r\n\r\nHello /one"));
+
+    handle.shutdown();
+    t.join().unwrap();
+}
+
+#[test]
+fn serve_handler_closes_connection() {
+    let server = Server::bind("127.0.0.1:0", 1).unwrap();
+    let addr = server.local_addr().unwrap();
+    let handle = server.shutdown_handle().unwrap();
+    let t = thread::spawn(move || {
+        server.serve(|_| {
+                  let mut response = HttpResponse::ok_text("Bye");
+                  response.add_header("Connection", "Keep-Alive, Close");
+                  response
+              })
+              .unwrap()
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /one HTTP/1.1\r\nHost: localhost\r\n\r\n\
+                       GET /two HTTP/1.1\r\nHost: localhost\r\n\r\n")
+          .unwrap();
+    // Only the first request is answered
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert_eq!(response.matches("HTTP/1.1 200 OK\r\n").count(), 1);
+    assert!(response.ends_with("\r\n\r\nBye"));
+
+    handle.shutdown();
+    t.join().unwrap();
+}
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************